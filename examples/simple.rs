@@ -16,6 +16,6 @@ async fn main() -> Result<(), Error> {
     let downloader = DownloaderBuilder::new()
         .directory(PathBuf::from("output"))
         .build();
-    downloader.download(&downloads).await;
+    downloader.download(&downloads).await?;
     Ok(())
 }
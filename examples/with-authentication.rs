@@ -39,7 +39,7 @@ async fn main() -> Result<(), Error> {
         .directory(PathBuf::from("output"))
         .header(header::AUTHORIZATION, auth)
         .build();
-    let summaries = downloader.download(&downloads).await;
+    let summaries = downloader.download(&downloads).await?;
     let summary = summaries.first().unwrap();
     println!("{:?}", summary.status());
     Ok(())
@@ -12,11 +12,11 @@ use reqwest::Url;
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     let reqwest_rs = "https://github.com/seanmonstar/reqwest/archive/refs/tags/v0.11.9.zip";
-    let downloads = vec![Download {
-        url: Url::parse(reqwest_rs).unwrap(),
-        filename: "output/test_dir/reqwest.zip".to_string(),
-    }];
+    let downloads = vec![Download::new(
+        &Url::parse(reqwest_rs).unwrap(),
+        "output/test_dir/reqwest.zip",
+    )];
     let downloader = DownloaderBuilder::new().build();
-    downloader.download(&downloads).await;
+    downloader.download(&downloads).await?;
     Ok(())
 }
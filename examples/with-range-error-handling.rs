@@ -28,7 +28,7 @@ async fn main() -> Result<(), Error> {
     let downloader = DownloaderBuilder::new()
         .directory(PathBuf::from("output"))
         .build();
-    let summary = downloader.download(&downloads).await;
+    let summary = downloader.download(&downloads).await?;
     dbg!(summary);
     Ok(())
 }
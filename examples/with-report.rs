@@ -1,18 +1,14 @@
-//! Download files and show the report using comfy-table.
+//! Download files and show the report as a table.
 //!
 //! Run with:
 //!
 //! ```not_rust
-//! cargo run -q --example with-report
+//! cargo run -q --example with-report --features report
 //! ```
 
 use color_eyre::{eyre::Report, Result};
-use comfy_table::{Row, Table};
 use std::path::PathBuf;
-use trauma::{
-    download::{Download, Status, Summary},
-    downloader::DownloaderBuilder,
-};
+use trauma::{download::Download, downloader::DownloaderBuilder, report};
 
 #[tokio::main]
 async fn main() -> Result<(), Report> {
@@ -30,42 +26,10 @@ async fn main() -> Result<(), Report> {
     let downloader = DownloaderBuilder::new()
         .directory(PathBuf::from("output"))
         .build();
-    let summaries = downloader.download(&downloads).await;
+    let summaries = downloader.download(&downloads).await?;
 
     // Display results.
-    display_summary(&summaries);
+    report::print_table(&summaries);
 
     Ok(())
 }
-
-fn display_summary(summaries: &[Summary]) {
-    let mut table = Table::new();
-    let header = Row::from(vec!["File", "Size", "Status", "Error"]);
-    table.set_header(header);
-    summaries.iter().for_each(|s| {
-        let mut error = String::new();
-        let status = match s.status() {
-            Status::Success => String::from("✅"),
-            Status::Fail(s) => {
-                error = s.to_string();
-                error.truncate(50);
-                if error.len() <= 50 {
-                    error.push_str("...");
-                }
-                String::from("❌")
-            }
-            Status::NotStarted => String::from("🔜"),
-            Status::Skipped(s) => {
-                error = s.to_string();
-                String::from("⏭️")
-            }
-        };
-        table.add_row(vec![
-            &s.download().filename,
-            &s.size().to_string(),
-            &status,
-            &error,
-        ]);
-    });
-    println!("{table}");
-}
@@ -38,7 +38,7 @@ async fn main() -> Result<(), Report> {
 
     // Make sure the server accepts range requests.
     let res = reqwest::Client::new()
-        .head(&avatar.to_string())
+        .head(avatar.to_string())
         .send()
         .await?;
     let headers = res.headers();
@@ -56,7 +56,7 @@ async fn main() -> Result<(), Report> {
     let mut rng = rand::thread_rng();
     let random_bytes: u8 = rng.gen();
     let res = reqwest::Client::new()
-        .get(&avatar.to_string())
+        .get(avatar.to_string())
         .header(RANGE, format!("bytes=0-{}", random_bytes))
         .send()
         .await?;
@@ -83,7 +83,7 @@ async fn main() -> Result<(), Report> {
     let downloader = DownloaderBuilder::hidden()
         .directory(output.parent().unwrap().to_path_buf())
         .build();
-    downloader.download(&downloads).await;
+    downloader.download(&downloads).await?;
 
     Ok(())
 }
@@ -51,6 +51,6 @@ async fn main() -> Result<(), Error> {
         .directory(PathBuf::from("output"))
         .style_options(style_opts)
         .build();
-    downloader.download(&downloads).await;
+    downloader.download(&downloads).await?;
     Ok(())
 }
@@ -0,0 +1,130 @@
+//! Caches the content downloaded in memory, to revalidate it with conditional
+//! requests instead of downloading it again.
+
+use bytes::Bytes;
+use reqwest::{header::HeaderValue, Url};
+use std::{collections::HashMap, sync::Mutex};
+
+/// Least recently used content, keyed by URL and validated by its ETag.
+#[derive(Debug)]
+pub(crate) struct MemoryCache {
+    /// Maximum size of the cached content, in bytes.
+    max_bytes: usize,
+    /// Cached entries, along with their total size.
+    inner: Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    /// Cached content per URL.
+    entries: HashMap<Url, Entry>,
+    /// Total size of the cached content, in bytes.
+    size: usize,
+    /// Counter ordering the uses of the entries.
+    tick: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    /// ETag of the content.
+    etag: HeaderValue,
+    /// Cached content.
+    content: Content,
+    /// Tick of the last use of the entry.
+    last_used: u64,
+}
+
+/// Content downloaded in memory.
+#[derive(Debug, Clone)]
+pub(crate) struct Content {
+    /// Body of the response.
+    pub(crate) bytes: Bytes,
+    /// `Content-Type` header of the response, telling the charset of a text.
+    pub(crate) content_type: Option<HeaderValue>,
+}
+
+impl MemoryCache {
+    /// Creates a cache holding up to `max_bytes` of content.
+    pub(crate) fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            inner: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// Returns the content cached for a URL, along with its ETag.
+    pub(crate) fn get(&self, url: &Url) -> Option<(HeaderValue, Content)> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+        let entry = inner.entries.get_mut(url)?;
+        entry.last_used = tick;
+        Some((entry.etag.clone(), entry.content.clone()))
+    }
+
+    /// Caches the content of a URL, evicting the least recently used entries
+    /// if the cache is full.
+    pub(crate) fn insert(&self, url: Url, etag: HeaderValue, content: Content) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(stale) = inner.entries.remove(&url) {
+            inner.size -= stale.content.bytes.len();
+        }
+        // Never evict the whole cache for a single entry.
+        if content.bytes.len() > self.max_bytes {
+            return;
+        }
+        inner.tick += 1;
+        inner.size += content.bytes.len();
+        let entry = Entry {
+            etag,
+            content,
+            last_used: inner.tick,
+        };
+        inner.entries.insert(url, entry);
+
+        while inner.size > self.max_bytes {
+            let lru = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(url, _)| url.clone())
+                .expect("the cache is not empty");
+            let evicted = inner.entries.remove(&lru).expect("the entry exists");
+            inner.size -= evicted.content.bytes.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_memory_cache_evicts_lru() {
+        let cache = MemoryCache::new(10);
+        let url = |path: &str| {
+            Url::parse("https://example.com")
+                .unwrap()
+                .join(path)
+                .unwrap()
+        };
+        let content = |bytes: Bytes| Content {
+            bytes,
+            content_type: None,
+        };
+        let etag = HeaderValue::from_static("\"v1\"");
+        cache.insert(url("a"), etag.clone(), content(Bytes::from_static(b"aaaa")));
+        cache.insert(url("b"), etag.clone(), content(Bytes::from_static(b"bbbb")));
+        assert!(cache.get(&url("a")).is_some());
+
+        // `b` is the least recently used.
+        cache.insert(url("c"), etag.clone(), content(Bytes::from_static(b"cccc")));
+        assert!(cache.get(&url("b")).is_none());
+        assert_eq!(cache.get(&url("a")).unwrap().1.bytes, "aaaa");
+        assert_eq!(cache.get(&url("c")).unwrap().1.bytes, "cccc");
+
+        // Too large to be cached.
+        cache.insert(url("a"), etag, content(Bytes::from(vec![0; 11])));
+        assert!(cache.get(&url("a")).is_none());
+    }
+}
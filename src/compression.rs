@@ -0,0 +1,74 @@
+//! Decodes the files stored compressed, detecting their compression from
+//! their file name.
+
+use std::path::Path;
+
+/// Compression of a file, detected from its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+}
+
+impl Compression {
+    /// Detects the compression of a file from its extension, returning it
+    /// along with the file name without the extension.
+    pub(crate) fn from_filename(filename: &str) -> Option<(Self, &str)> {
+        let extension = Path::new(filename).extension()?.to_str()?;
+        let compression = match extension.to_ascii_lowercase().as_str() {
+            "gz" => Self::Gzip,
+            "zst" => Self::Zstd,
+            "bz2" => Self::Bzip2,
+            "xz" => Self::Xz,
+            _ => return None,
+        };
+        let stem = &filename[..filename.len() - extension.len() - 1];
+        if stem.is_empty() || stem.ends_with('/') {
+            return None;
+        }
+        Some((compression, stem))
+    }
+
+    /// Decodes a stream of compressed chunks.
+    #[cfg(feature = "auto-decompress")]
+    pub(crate) fn decode(self, stream: crate::download::ByteStream) -> crate::download::ByteStream {
+        use async_compression::tokio::bufread::{BzDecoder, GzipDecoder, XzDecoder, ZstdDecoder};
+        use futures::{StreamExt, TryStreamExt};
+        use tokio_util::io::{ReaderStream, StreamReader};
+
+        let reader = StreamReader::new(stream.map_err(std::io::Error::other));
+        let decoded = match self {
+            Self::Gzip => {
+                let mut decoder = GzipDecoder::new(reader);
+                // Concatenated gzip files are valid gzip files.
+                decoder.multiple_members(true);
+                ReaderStream::new(decoder).boxed()
+            }
+            Self::Zstd => ReaderStream::new(ZstdDecoder::new(reader)).boxed(),
+            Self::Bzip2 => ReaderStream::new(BzDecoder::new(reader)).boxed(),
+            Self::Xz => ReaderStream::new(XzDecoder::new(reader)).boxed(),
+        };
+        decoded.map_err(crate::Error::from).boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_filename() {
+        assert_eq!(
+            Compression::from_filename("data.json.zst"),
+            Some((Compression::Zstd, "data.json"))
+        );
+        assert_eq!(
+            Compression::from_filename("dir/archive.tar.GZ"),
+            Some((Compression::Gzip, "dir/archive.tar"))
+        );
+        assert_eq!(Compression::from_filename("data.json"), None);
+        assert_eq!(Compression::from_filename(".xz"), None);
+    }
+}
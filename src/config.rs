@@ -0,0 +1,54 @@
+//! Configures a [`Downloader`](crate::downloader::Downloader) from a file.
+//!
+//! This module is only available with the `serde` feature.
+
+use serde::Deserialize;
+use std::{collections::BTreeMap, path::PathBuf};
+
+/// Options of a [`Downloader`](crate::downloader::Downloader), deserialized
+/// from a configuration file, e.g. in TOML or YAML.
+///
+/// Every field is optional, and the missing ones keep the defaults of the
+/// [`DownloaderBuilder`](crate::downloader::DownloaderBuilder). The
+/// durations are expressed in seconds.
+///
+/// ```toml
+/// directory = "downloads"
+/// retries = 5
+/// concurrent_downloads = 8
+/// timeout_secs = 30
+///
+/// [headers]
+/// user-agent = "my-tool/1.0"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct DownloaderConfig {
+    /// Directory where to store the downloads.
+    pub directory: Option<PathBuf>,
+    /// Number of retries per download.
+    pub retries: Option<u32>,
+    /// Maximum number of concurrent downloads.
+    pub concurrent_downloads: Option<usize>,
+    /// Maximum duration of a request.
+    pub timeout_secs: Option<u64>,
+    /// Maximum duration of a download, retries included.
+    pub per_download_timeout_secs: Option<u64>,
+    /// Maximum time to wait for a chunk of data.
+    pub read_idle_timeout_secs: Option<u64>,
+    /// Resume the partial downloads.
+    pub resumable: Option<bool>,
+    /// Write the downloads to temporary files, moved in place once complete.
+    pub use_temp_file: Option<bool>,
+    /// Directory where to write the partial files.
+    pub temp_dir: Option<PathBuf>,
+    /// Maximum number of redirects to follow.
+    pub max_redirects: Option<usize>,
+    /// Maximum aggregate throughput of the downloads, in bytes per second.
+    pub max_bytes_per_second: Option<u64>,
+    /// Name the files after their `Content-Disposition` header.
+    pub respect_content_disposition: Option<bool>,
+    /// Headers sent with every request.
+    pub headers: BTreeMap<String, String>,
+}
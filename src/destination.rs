@@ -0,0 +1,101 @@
+//! Destinations the downloads can be streamed to, as an opt-in alternative to
+//! the local files.
+//!
+//! By default, a downloader writes the local files itself, which allows to
+//! resume them. A [`Destination`] set with
+//! [`DownloaderBuilder::destination`](crate::downloader::DownloaderBuilder::destination)
+//! receives the content of the downloads as it is received instead, which
+//! allows to stream it into an object store, e.g. S3, without touching the
+//! local disk, but not to resume it:
+//!
+//! ```no_run
+//! use async_trait::async_trait;
+//! use trauma::{
+//!     destination::{Destination, ObjectWriter},
+//!     downloader::DownloaderBuilder,
+//!     Error,
+//! };
+//!
+//! struct Discard;
+//!
+//! #[async_trait]
+//! impl Destination for Discard {
+//!     async fn open(&self, _name: &str, _size_hint: Option<u64>) -> Result<ObjectWriter, Error> {
+//!         Ok(Box::pin(tokio::io::sink()))
+//!     }
+//! }
+//!
+//! let downloader = DownloaderBuilder::new().destination(Discard).build();
+//! ```
+
+use crate::Error;
+use async_trait::async_trait;
+use std::{path::PathBuf, pin::Pin, sync::Mutex};
+use tokio::{fs, io::AsyncWrite};
+
+/// Writer of an object of a [`Destination`].
+///
+/// The object is complete once the writer is shut down, which allows to
+/// finalize an upload.
+pub type ObjectWriter = Pin<Box<dyn AsyncWrite + Send>>;
+
+/// Represents where the downloads are written.
+#[async_trait]
+pub trait Destination: Send + Sync {
+    /// Opens the object receiving the content of a download, named after the
+    /// file name of the download.
+    ///
+    /// The size of the content is hinted if it is known.
+    async fn open(&self, name: &str, size_hint: Option<u64>) -> Result<ObjectWriter, Error>;
+}
+
+/// Writes the objects as files of a local directory, e.g. as a starting point
+/// for a destination of its own.
+///
+/// It is not what a downloader without destination uses: the files are
+/// written in place, and are therefore never resumed.
+#[derive(Debug, Clone)]
+pub struct FileSystem {
+    directory: PathBuf,
+}
+
+impl FileSystem {
+    /// Creates a destination writing the files in `directory`.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Destination for FileSystem {
+    async fn open(&self, name: &str, _size_hint: Option<u64>) -> Result<ObjectWriter, Error> {
+        let path = self.directory.join(name);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        Ok(Box::pin(fs::File::create(path).await?))
+    }
+}
+
+/// Hands out a single object, whatever its name.
+pub(crate) struct OneShot(Mutex<Option<ObjectWriter>>);
+
+impl OneShot {
+    /// Creates a destination handing out `object`.
+    pub(crate) fn new(object: ObjectWriter) -> Self {
+        Self(Mutex::new(Some(object)))
+    }
+}
+
+#[async_trait]
+impl Destination for OneShot {
+    async fn open(&self, _name: &str, _size_hint: Option<u64>) -> Result<ObjectWriter, Error> {
+        self.0
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| Error::Internal("the object was already opened".into()))
+    }
+}
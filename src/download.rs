@@ -1,20 +1,86 @@
 //! Represents a file to be downloaded.
 
-use crate::Error;
+use crate::{
+    hash::{Checksum, PieceHashes},
+    Error,
+};
+use bytes::Bytes;
+use futures::stream::{BoxStream, Stream, StreamExt};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+#[cfg(feature = "multipart")]
+use reqwest::multipart::Form;
 use reqwest::{
-    header::{ACCEPT_RANGES, CONTENT_LENGTH},
+    header::{
+        HeaderMap, HeaderValue, IntoHeaderName, ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE,
+        RANGE, REFERER,
+    },
     StatusCode, Url,
 };
-use reqwest_middleware::ClientWithMiddleware;
-use std::convert::TryFrom;
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fmt,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 /// Represents a file to be downloaded.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct Download {
     /// URL of the file to download.
     pub url: Url,
     /// File name used to save the file on disk.
     pub filename: String,
+    /// Override the [`Downloader`](crate::downloader::Downloader) resume
+    /// setting for this download.
+    ///
+    /// Set it to `Some(false)` for URLs pointing to dynamically generated
+    /// content, which must never be resumed.
+    pub resumable_override: Option<bool>,
+    /// HTTP headers sent with every request of this download.
+    ///
+    /// They take precedence over the headers of the
+    /// [`Downloader`](crate::downloader::Downloader).
+    pub headers: HeaderMap,
+    /// Portion of the file to download.
+    ///
+    /// Downloads of a range are never resumed.
+    pub range: Option<ByteRange>,
+    /// Form sent as a `POST` request to initiate the download.
+    ///
+    /// Downloads requested with a form are never resumed.
+    #[cfg(feature = "multipart")]
+    pub form: Option<MultipartForm>,
+    /// Stream providing the content instead of the URL.
+    ///
+    /// Downloads from a stream are never resumed.
+    pub source: Option<StreamSource>,
+    /// Media type the response must have, e.g. `application/zip`.
+    ///
+    /// A response with another `Content-Type` fails the download before its
+    /// content is written, catching the error pages served with a `200 OK`
+    /// status.
+    pub expected_content_type: Option<String>,
+    /// Metadata of the user, carried along to the [`Summary`] of the download.
+    ///
+    /// It is not used by Trauma.
+    pub metadata: HashMap<String, String>,
+    /// Digest the downloaded file must have.
+    ///
+    /// It is computed as the chunks are received, and a mismatch fails the
+    /// download with [`Status::ChecksumMismatch`].
+    pub checksum: Option<Checksum>,
+    /// Digests of the pieces of the downloaded file.
+    ///
+    /// They are verified as the chunks are received, and the corrupted pieces
+    /// are downloaded again with range requests.
+    pub piece_hashes: Option<PieceHashes>,
+    /// URLs serving the same file, tried in order when the download fails.
+    pub mirrors: Vec<Url>,
 }
 
 impl Download {
@@ -43,66 +109,522 @@ impl Download {
         Self {
             url: url.clone(),
             filename: String::from(filename),
+            resumable_override: None,
+            headers: HeaderMap::new(),
+            range: None,
+            #[cfg(feature = "multipart")]
+            form: None,
+            source: None,
+            expected_content_type: None,
+            metadata: HashMap::new(),
+            checksum: None,
+            piece_hashes: None,
+            mirrors: Vec::new(),
+        }
+    }
+
+    /// Creates the [`Download`]s of a list of URLs, deriving their file names
+    /// like [`Download::try_from`] does.
+    ///
+    /// Fails on the first URL which is not valid.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use trauma::download::Download;
+    ///
+    /// # fn main() -> Result<(), Report> {
+    /// let downloads = Download::from_urls([
+    ///     "https://example.com/a.zip",
+    ///     "https://example.com/b.zip",
+    /// ])?;
+    /// assert_eq!(downloads[1].filename, "b.zip");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_urls<I, S>(urls: I) -> Result<Vec<Self>, Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        urls.into_iter()
+            .map(|url| Download::try_from(url.as_ref()))
+            .collect()
+    }
+
+    /// Creates a new [`Download`] from a path relative to a base URL.
+    ///
+    /// The base URL is considered a directory, whether its path ends with a
+    /// slash or not, and the relative path is resolved within it even if it
+    /// starts with a slash. The file name is derived from the resolved URL,
+    /// like [`Download::try_from`] does.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use reqwest::Url;
+    /// use trauma::download::Download;
+    ///
+    /// # fn main() -> Result<(), Report> {
+    /// let base = Url::parse("https://example.com/releases")?;
+    /// let d = Download::from_base(&base, "v1.0/app.zip")?;
+    /// assert_eq!(d.url.as_str(), "https://example.com/releases/v1.0/app.zip");
+    /// assert_eq!(d.filename, "app.zip");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_base(base: &Url, relative: &str) -> Result<Self, Error> {
+        let mut base = base.clone();
+        if !base.path().ends_with('/') {
+            base.set_path(&format!("{}/", base.path()));
+        }
+        let url = base.join(relative.trim_start_matches('/')).map_err(|e| {
+            Error::InvalidUrl(format!(
+                "the path \"{}\" cannot be joined to \"{}\": {}",
+                relative, base, e
+            ))
+        })?;
+        Download::try_from(&url)
+    }
+
+    /// Creates a new [`Download`] whose content is read from a stream instead
+    /// of being requested over HTTP.
+    ///
+    /// The content is written to `filename` like any other download, with the
+    /// same progress and summary, which allows to test the code consuming the
+    /// downloads without a server. Its URL uses the `stream:` scheme.
+    ///
+    /// Since a stream can only be consumed once, downloading it again fails.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bytes::Bytes;
+    /// use futures::stream;
+    /// use trauma::download::Download;
+    ///
+    /// let chunks = vec![Ok(Bytes::from("hello ")), Ok(Bytes::from("world"))];
+    /// let d = Download::from_stream("hello.txt", stream::iter(chunks));
+    /// ```
+    pub fn from_stream(
+        filename: &str,
+        stream: impl Stream<Item = Result<Bytes, Error>> + Send + 'static,
+    ) -> Self {
+        let url = format!("stream:{}", utf8_percent_encode(filename, NON_ALPHANUMERIC));
+        Self {
+            source: Some(StreamSource(Arc::new(Mutex::new(Some(stream.boxed()))))),
+            ..Self::new(
+                &Url::parse(&url).expect("the stream URL is valid"),
+                filename,
+            )
+        }
+    }
+
+    /// Override the downloader resume setting for this [`Download`].
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use trauma::download::Download;
+    ///
+    /// # fn main() -> Result<(), Report> {
+    /// let d = Download::try_from("https://example.com/report.csv")?.with_resumable(false);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_resumable(self, resumable: bool) -> Self {
+        Self {
+            resumable_override: Some(resumable),
+            ..self
+        }
+    }
+
+    /// Add an HTTP header sent with every request of this [`Download`].
+    pub fn with_header<K: IntoHeaderName>(mut self, name: K, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Set the `Referer` header sent with every request of this [`Download`].
+    ///
+    /// Some CDNs check that the referer matches the page linking to the file
+    /// to prevent hotlinking.
+    ///
+    /// ## Errors
+    ///
+    /// Fails with [`Error::InvalidUrl`] if `referer` cannot be parsed.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use trauma::download::Download;
+    ///
+    /// # fn main() -> Result<(), Report> {
+    /// let d = Download::try_from("https://cdn.example.com/file.zip")?
+    ///     .referer("https://example.com/releases.html")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn referer(self, referer: &str) -> Result<Self, Error> {
+        Ok(self.with_header(REFERER, referer_value(referer)?))
+    }
+
+    /// Only download the bytes from `start` to `end`, inclusive.
+    ///
+    /// If `end` is `None`, the file is downloaded until its end.
+    ///
+    /// ## Example
+    ///
+    /// Retrieve the first 512 bytes of a disk image to read its header:
+    ///
+    /// ```no_run
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use trauma::download::Download;
+    ///
+    /// # fn main() -> Result<(), Report> {
+    /// let d = Download::try_from("https://example.com/disk.img")?.range(0, Some(511));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn range(self, start: u64, end: Option<u64>) -> Self {
+        Self {
+            range: Some(ByteRange { start, end }),
+            ..self
+        }
+    }
+
+    /// Attach a metadata entry to this [`Download`].
+    ///
+    /// It is available from the [`Summary`] of the download, which avoids
+    /// correlating the results with an external map.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use trauma::download::{Download, Summary};
+    /// use reqwest::StatusCode;
+    ///
+    /// # fn main() -> Result<(), Report> {
+    /// let d = Download::try_from("https://example.com/file.zip")?.with_metadata("project", "42");
+    /// let summary = Summary::new(d, StatusCode::OK, 0, false);
+    /// assert_eq!(summary.download().metadata["project"], "42");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Verify the digest of this [`Download`] once it is complete.
+    ///
+    /// A corrupted or truncated file is deleted, and its download fails with
+    /// [`Status::ChecksumMismatch`].
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use trauma::{download::Download, hash::Checksum};
+    ///
+    /// # fn main() -> Result<(), Report> {
+    /// let d = Download::try_from("https://example.com/file.zip")?.with_checksum(Checksum::Sha256(
+    ///     "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9".into(),
+    /// ));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_checksum(self, checksum: Checksum) -> Self {
+        Self {
+            checksum: Some(checksum),
+            ..self
+        }
+    }
+
+    /// Verify the pieces of this [`Download`], given the SHA-256 digest of
+    /// each `piece_size` bytes of the file.
+    ///
+    /// The corrupted pieces are downloaded again on their own, and resuming
+    /// the download only keeps the valid pieces of the partial file. Repairing
+    /// the pieces requires the download to be resumable, and the pieces are
+    /// ignored for the downloads of a [`range`](Download::range).
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use trauma::download::Download;
+    ///
+    /// # fn main() -> Result<(), Report> {
+    /// let hashes: Vec<[u8; 32]> = vec![[0; 32], [1; 32]];
+    /// let d = Download::try_from("https://example.com/file.iso")?
+    ///     .with_piece_hashes(4 * 1024 * 1024, hashes);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_piece_hashes(self, piece_size: u64, hashes: Vec<[u8; 32]>) -> Self {
+        Self {
+            piece_hashes: Some(PieceHashes::new(piece_size, hashes)),
+            ..self
+        }
+    }
+
+    /// Download this [`Download`] from its mirrors when it fails.
+    ///
+    /// Once the retries of the URL are exhausted, if it responds with an
+    /// error status, or if the file it serves does not match the checksum, the
+    /// mirrors are tried in order until one of them succeeds. The URL which
+    /// served the file is reported by [`Summary::final_url`].
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use reqwest::Url;
+    /// use trauma::download::Download;
+    ///
+    /// # fn main() -> Result<(), Report> {
+    /// let d = Download::try_from("https://example.com/pkg-1.0.tar.gz")?.with_mirrors(vec![
+    ///     Url::parse("https://mirror.example.org/pkg-1.0.tar.gz")?,
+    /// ]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_mirrors(self, mirrors: Vec<Url>) -> Self {
+        Self { mirrors, ..self }
+    }
+
+    /// Require the response of this [`Download`] to have a media type.
+    ///
+    /// The parameters of the `Content-Type` header, e.g. the charset, are
+    /// ignored, and the media types are compared case-insensitively.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use trauma::download::Download;
+    ///
+    /// # fn main() -> Result<(), Report> {
+    /// let d = Download::try_from("https://example.com/file.zip")?
+    ///     .with_content_type("application/zip");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_content_type(self, content_type: &str) -> Self {
+        Self {
+            expected_content_type: Some(String::from(content_type)),
+            ..self
+        }
+    }
+
+    /// Check whether a `Content-Type` header matches the expected media type
+    /// of the download, if any.
+    pub(crate) fn is_content_type_expected(&self, header: Option<&HeaderValue>) -> bool {
+        let Some(expected) = &self.expected_content_type else {
+            return true;
+        };
+        header
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').next())
+            .is_some_and(|media_type| media_type.trim().eq_ignore_ascii_case(expected.trim()))
+    }
+
+    /// Request the [`Download`] by sending a `multipart/form-data` `POST`.
+    ///
+    /// Since a [`Form`] can only be sent once, the closure is called to build
+    /// a new one for every request.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use reqwest::multipart::Form;
+    /// use trauma::download::Download;
+    ///
+    /// # fn main() -> Result<(), Report> {
+    /// let d = Download::try_from("https://example.com/export.csv")?
+    ///     .with_form(|| Form::new().text("format", "csv"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "multipart")]
+    pub fn with_form(self, form: impl Fn() -> Form + Send + Sync + 'static) -> Self {
+        Self {
+            form: Some(MultipartForm(Arc::new(form))),
+            ..self
         }
     }
 
     /// Check whether the download is resumable.
+    ///
+    /// If the server rejects the `HEAD` request, the first byte of the file is
+    /// requested instead, the download being resumable if the server honors
+    /// the range.
     pub async fn is_resumable(
         &self,
         client: &ClientWithMiddleware,
     ) -> Result<bool, reqwest_middleware::Error> {
-        let res = client.head(self.url.clone()).send().await?;
-        let headers = res.headers();
-        match headers.get(ACCEPT_RANGES) {
-            None => Ok(false),
-            Some(x) if x == "none" => Ok(false),
-            Some(_) => Ok(true),
-        }
+        let prepare = |req: RequestBuilder| req.headers(self.headers.clone());
+        Ok(self.probe_with(client, prepare).await?.0)
     }
 
     /// Retrieve the content_length of the download.
     ///
     /// Returns None if the "content-length" header is missing or if its value
     /// is not a u64.
+    ///
+    /// If the server rejects the `HEAD` request, the first byte of the file is
+    /// requested instead, and the size is read from its "content-range"
+    /// header.
     pub async fn content_length(
         &self,
         client: &ClientWithMiddleware,
     ) -> Result<Option<u64>, reqwest_middleware::Error> {
-        let res = client.head(self.url.clone()).send().await?;
+        self.content_length_with(client, |req| req.headers(self.headers.clone()))
+            .await
+    }
+
+    /// Retrieve the content_length of the download, preparing the requests
+    /// with `prepare`.
+    pub(crate) async fn content_length_with(
+        &self,
+        client: &ClientWithMiddleware,
+        prepare: impl Fn(RequestBuilder) -> RequestBuilder,
+    ) -> Result<Option<u64>, reqwest_middleware::Error> {
+        Ok(self.probe_with(client, prepare).await?.1)
+    }
+
+    /// Check whether the download is resumable and retrieve its size with a
+    /// single request, prepared with `prepare`.
+    pub(crate) async fn probe_with(
+        &self,
+        client: &ClientWithMiddleware,
+        prepare: impl Fn(RequestBuilder) -> RequestBuilder,
+    ) -> Result<(bool, Option<u64>), reqwest_middleware::Error> {
+        let res = prepare(client.head(self.url.clone())).send().await?;
+        if rejects_head(&res) {
+            let res = self.first_byte(client, &prepare).await?;
+            if res.status() != StatusCode::PARTIAL_CONTENT {
+                return Ok((false, None));
+            }
+            let size = res
+                .headers()
+                .get(CONTENT_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.rsplit_once('/'))
+                .and_then(|(_, total)| total.parse().ok());
+            return Ok((true, size));
+        }
         let headers = res.headers();
-        match headers.get(CONTENT_LENGTH) {
-            None => Ok(None),
-            Some(header_value) => match header_value.to_str() {
-                Ok(v) => match v.to_string().parse::<u64>() {
-                    Ok(v) => Ok(Some(v)),
-                    Err(_) => Ok(None),
-                },
-                Err(_) => Ok(None),
-            },
+        let resumable = match headers.get(ACCEPT_RANGES) {
+            None => false,
+            Some(x) if x == "none" => false,
+            Some(_) => true,
+        };
+        let size = headers
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        Ok((resumable, size))
+    }
+
+    /// Request the first byte of the download, for the servers rejecting the
+    /// `HEAD` requests.
+    async fn first_byte(
+        &self,
+        client: &ClientWithMiddleware,
+        prepare: impl Fn(RequestBuilder) -> RequestBuilder,
+    ) -> Result<reqwest::Response, reqwest_middleware::Error> {
+        prepare(client.get(self.url.clone()))
+            .header(RANGE, "bytes=0-0")
+            .send()
+            .await
+    }
+
+    /// Check whether this [`Download`] is already present in `directory`,
+    /// with the expected size.
+    ///
+    /// It only looks at the local disk, which makes it suitable to quickly
+    /// list what is left to download from a manifest.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use std::path::Path;
+    /// use trauma::download::Download;
+    ///
+    /// # fn main() -> Result<(), Report> {
+    /// let d = Download::try_from("https://example.com/file.zip")?;
+    /// if !d.is_complete(Path::new("downloads"), 1024) {
+    ///     println!("{} is missing", d.filename);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn is_complete(&self, directory: &Path, expected_size: u64) -> bool {
+        std::fs::metadata(directory.join(&self.filename))
+            .is_ok_and(|m| m.is_file() && m.len() == expected_size)
+    }
+
+    /// Check whether this [`Download`] is already present in `directory`,
+    /// with the expected digest.
+    ///
+    /// The whole file is read to compute its digest.
+    pub fn is_complete_with_checksum(&self, directory: &Path, checksum: &Checksum) -> bool {
+        let Ok(mut file) = std::fs::File::open(directory.join(&self.filename)) else {
+            return false;
+        };
+        let mut hasher = checksum.algorithm().hasher();
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            match file.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => hasher.update(&buf[..n]),
+                Err(_) => return false,
+            }
         }
+        checksum.matches(&hasher.finalize())
     }
 }
 
+/// Parses a URL into the value of a `Referer` header.
+pub(crate) fn referer_value(referer: &str) -> Result<HeaderValue, Error> {
+    let url = Url::parse(referer).map_err(|e| {
+        Error::InvalidUrl(format!(
+            "the referer \"{}\" cannot be parsed: {}",
+            referer, e
+        ))
+    })?;
+    Ok(HeaderValue::from_str(url.as_str()).expect("a serialized URL is a valid header value"))
+}
+
+/// Check whether a server rejected a `HEAD` request.
+fn rejects_head(res: &reqwest::Response) -> bool {
+    matches!(
+        res.status(),
+        StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+    )
+}
+
 impl TryFrom<&Url> for Download {
     type Error = crate::Error;
 
     fn try_from(value: &Url) -> Result<Self, Self::Error> {
-        value
-            .path_segments()
-            .ok_or_else(|| {
-                Error::InvalidUrl(format!(
-                    "the url \"{}\" does not contain a valid path",
-                    value
-                ))
-            })?
-            .last()
-            .map(String::from)
-            .map(|filename| Download {
-                url: value.clone(),
-                filename: form_urlencoded::parse(filename.as_bytes())
-                    .map(|(key, val)| [key, val].concat())
-                    .collect(),
-            })
+        if value.cannot_be_a_base() {
+            return Err(Error::InvalidUrl(format!(
+                "the url \"{}\" does not contain a valid path",
+                value
+            )));
+        }
+        filename_from_url(value)
+            .map(|filename| Download::new(value, &filename))
             .ok_or_else(|| {
                 Error::InvalidUrl(format!("the url \"{}\" does not contain a filename", value))
             })
@@ -121,13 +643,202 @@ impl TryFrom<&str> for Download {
     }
 }
 
+/// Derive a file name from the last segment of a URL path.
+///
+/// The segment is percent-decoded. Returns `None` if the URL has no path, or if
+/// it ends with a `/`.
+///
+/// ## Example
+///
+/// ```
+/// use reqwest::Url;
+/// use trauma::download::filename_from_url;
+///
+/// let url = Url::parse("https://example.com/files/my%20file.zip?version=2").unwrap();
+/// assert_eq!(filename_from_url(&url), Some("my file.zip".into()));
+/// ```
+pub fn filename_from_url(url: &Url) -> Option<String> {
+    let segment = url.path_segments()?.next_back()?;
+    let filename = percent_decode_str(segment).decode_utf8_lossy();
+    (!filename.is_empty()).then(|| filename.into_owned())
+}
+
+/// Extract the file name of a `Content-Disposition` header value, e.g.
+/// `attachment; filename="report.pdf"`, preferring the RFC 5987 encoded one,
+/// e.g. `filename*=UTF-8''r%C3%A9sum%C3%A9.pdf`.
+///
+/// The directories of the name are stripped, so that it cannot point outside
+/// of the download directory.
+pub(crate) fn disposition_filename(value: &HeaderValue) -> Option<String> {
+    let value = value.to_str().ok()?;
+    let (mut plain, mut extended) = (None, None);
+    for (name, value) in value.split(';').skip(1).filter_map(|p| p.split_once('=')) {
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "filename" => plain = sanitize_filename(value.trim_matches('"')),
+            "filename*" => {
+                extended = decode_ext_value(value)
+                    .as_deref()
+                    .and_then(sanitize_filename)
+            }
+            _ => (),
+        }
+    }
+    extended.or(plain)
+}
+
+/// Decode an RFC 5987 extended value, e.g. `UTF-8'en'caf%C3%A9`.
+fn decode_ext_value(value: &str) -> Option<String> {
+    let (charset, rest) = value.trim_matches('"').split_once('\'')?;
+    let (_language, encoded) = rest.split_once('\'')?;
+    let bytes = percent_decode_str(encoded).collect::<Vec<u8>>();
+    match charset.to_ascii_lowercase().as_str() {
+        "utf-8" => String::from_utf8(bytes).ok(),
+        "iso-8859-1" => Some(bytes.into_iter().map(char::from).collect()),
+        _ => None,
+    }
+}
+
+/// Keep the last component of a file name, without its control characters.
+fn sanitize_filename(filename: &str) -> Option<String> {
+    let name = filename.rsplit(['/', '\\']).next()?;
+    let name = name.chars().filter(|c| !c.is_control()).collect::<String>();
+    let name = name.trim();
+    (!name.is_empty() && name != "." && name != "..").then(|| name.to_string())
+}
+
+/// Range of bytes to download, as sent in the `Range` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    /// Offset of the first byte.
+    pub start: u64,
+    /// Offset of the last byte, inclusive. `None` means the end of the file.
+    pub end: Option<u64>,
+}
+
+impl ByteRange {
+    /// Return the number of bytes in the range, if it is bounded.
+    pub fn size(&self) -> Option<u64> {
+        self.end.map(|end| end.saturating_sub(self.start) + 1)
+    }
+
+    /// Return the value of the `Range` header requesting this range.
+    pub fn header_value(&self) -> String {
+        match self.end {
+            Some(end) => format!("bytes={}-{}", self.start, end),
+            None => format!("bytes={}-", self.start),
+        }
+    }
+}
+
+/// Builds the `multipart/form-data` body used to request a [`Download`].
+#[cfg(feature = "multipart")]
+#[derive(Clone)]
+pub struct MultipartForm(Arc<dyn Fn() -> Form + Send + Sync>);
+
+#[cfg(feature = "multipart")]
+impl MultipartForm {
+    /// Build a new [`Form`].
+    pub fn build(&self) -> Form {
+        (self.0)()
+    }
+}
+
+#[cfg(feature = "multipart")]
+impl fmt::Debug for MultipartForm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("MultipartForm").finish_non_exhaustive()
+    }
+}
+
+/// A stream of chunks providing the content of a [`Download`].
+pub type ByteStream = BoxStream<'static, Result<Bytes, Error>>;
+
+/// Provides the content of a [`Download`] from a stream.
+#[derive(Clone)]
+pub struct StreamSource(Arc<Mutex<Option<ByteStream>>>);
+
+impl StreamSource {
+    /// Take the stream, which is `None` once it was taken.
+    pub fn take(&self) -> Option<ByteStream> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+impl fmt::Debug for StreamSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("StreamSource").finish_non_exhaustive()
+    }
+}
+
+/// Represents the disposition type of a `Content-Disposition` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DispositionType {
+    /// The content is meant to be displayed.
+    Inline,
+    /// The content is meant to be saved.
+    Attachment,
+    /// Any other disposition type, in lowercase.
+    Other(String),
+}
+
+impl DispositionType {
+    /// Parse the disposition type from a `Content-Disposition` header value.
+    ///
+    /// Returns None if the header value is not valid.
+    pub fn from_header(value: &HeaderValue) -> Option<Self> {
+        let value = value.to_str().ok()?;
+        let disposition = value.split(';').next()?.trim().to_ascii_lowercase();
+        match disposition.as_str() {
+            "" => None,
+            "inline" => Some(Self::Inline),
+            "attachment" => Some(Self::Attachment),
+            _ => Some(Self::Other(disposition)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Status {
     Fail(String),
+    ChecksumMismatch(String),
     NotStarted,
-    Skipped(String),
+    Skipped(SkipReason),
     Success,
 }
+
+/// Reason why a [`Download`] was skipped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SkipReason {
+    /// The file was already fully downloaded.
+    AlreadyComplete,
+    /// The download was excluded by a filter.
+    Filtered,
+    /// The content disposition is inline while only attachments are accepted.
+    InlineDisposition,
+    /// Another download of the batch targets the same file.
+    Collision,
+    /// The file on disk was deemed fresh by the user.
+    Fresh,
+    /// The extension of the file is not allowed.
+    ExtensionFiltered,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            Self::AlreadyComplete => "the file was already fully downloaded",
+            Self::Filtered => "filtered",
+            Self::InlineDisposition => "the content disposition is inline",
+            Self::Collision => "another download targets the same file",
+            Self::Fresh => "fresh",
+            Self::ExtensionFiltered => "extension filtered",
+        };
+        f.write_str(reason)
+    }
+}
 /// Represents a [`Download`] summary.
 #[derive(Debug, Clone)]
 pub struct Summary {
@@ -141,6 +852,22 @@ pub struct Summary {
     status: Status,
     /// Resumable.
     resumable: bool,
+    /// Disposition type of the response.
+    disposition: Option<DispositionType>,
+    /// URL which served the response, after the redirections.
+    final_url: Option<Url>,
+    /// Digest of the downloaded file.
+    digest: Option<String>,
+    /// Size of the partial file the download was resumed from.
+    resumed_from: u64,
+    /// Path of the downloaded file.
+    path: Option<PathBuf>,
+    /// Time between the reception of the response and its first chunk.
+    time_to_first_byte: Option<Duration>,
+    /// Headers of the response, if captured.
+    headers: HeaderMap,
+    /// Whether the download failed because its response could not be decoded.
+    undecodable: bool,
 }
 
 impl Summary {
@@ -152,6 +879,14 @@ impl Summary {
             size,
             status: Status::NotStarted,
             resumable,
+            disposition: None,
+            final_url: None,
+            digest: None,
+            resumed_from: 0,
+            path: None,
+            time_to_first_byte: None,
+            headers: HeaderMap::new(),
+            undecodable: false,
         }
     }
 
@@ -175,6 +910,20 @@ impl Summary {
         &self.download
     }
 
+    /// Get a reference to the URL requested by the summary's download.
+    pub fn url(&self) -> &Url {
+        &self.download.url
+    }
+
+    /// Get a reference to the URL which served the download.
+    ///
+    /// It differs from [`Summary::url`] when the request was redirected or
+    /// served by one of the [mirrors](Download::with_mirrors), and falls back
+    /// to it when no response was received.
+    pub fn final_url(&self) -> &Url {
+        self.final_url.as_ref().unwrap_or(&self.download.url)
+    }
+
     /// Get a reference to the summary's status.
     pub fn status(&self) -> &Status {
         &self.status
@@ -197,12 +946,343 @@ impl Summary {
     pub fn resumable(&self) -> bool {
         self.resumable
     }
+
+    /// Set the summary's status code.
+    pub(crate) fn set_statuscode(&mut self, statuscode: StatusCode) {
+        self.statuscode = statuscode;
+    }
+
+    /// Set the summary's size.
+    pub(crate) fn set_size(&mut self, size: u64) {
+        self.size = size;
+    }
+
+    /// Mark the response of the download as impossible to decode.
+    pub(crate) fn set_undecodable(&mut self) {
+        self.undecodable = true;
+    }
+
+    /// Whether the download failed because its response could not be decoded.
+    pub(crate) fn is_undecodable(&self) -> bool {
+        self.undecodable && matches!(self.status, Status::Fail(_))
+    }
+
+    /// Set the URL which served the download.
+    pub(crate) fn set_final_url(&mut self, final_url: Url) {
+        self.final_url = Some(final_url);
+    }
+
+    /// Set the download, e.g. the original one when it was served by a
+    /// mirror.
+    pub(crate) fn set_download(&mut self, download: Download) {
+        self.download = download;
+    }
+
+    /// Set the size of the partial file the download was resumed from.
+    pub(crate) fn set_resumed_from(&mut self, resumed_from: u64) {
+        self.resumed_from = resumed_from;
+    }
+
+    /// Get the number of bytes which were already on disk when the download
+    /// was resumed.
+    ///
+    /// Subtracting it from [`Summary::size`] gives the number of bytes
+    /// actually transferred.
+    pub fn resumed_from(&self) -> u64 {
+        self.resumed_from
+    }
+
+    /// Set the digest of the downloaded file.
+    pub(crate) fn set_digest(&mut self, digest: String) {
+        self.digest = Some(digest);
+    }
+
+    /// Get the digest of the downloaded file.
+    ///
+    /// Returns None if no hasher was configured or if the download did not
+    /// complete.
+    pub fn digest(&self) -> Option<&str> {
+        self.digest.as_deref()
+    }
+
+    /// Set the path of the downloaded file.
+    pub(crate) fn set_path(&mut self, path: PathBuf) {
+        self.path = Some(path);
+    }
+
+    /// Get the path of the downloaded file.
+    ///
+    /// Returns None if the download did not complete.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Set the time between the reception of the response and its first
+    /// chunk.
+    pub(crate) fn set_time_to_first_byte(&mut self, time_to_first_byte: Duration) {
+        self.time_to_first_byte = Some(time_to_first_byte);
+    }
+
+    /// Get the time between the reception of the response headers and the
+    /// first chunk of its body.
+    ///
+    /// This tells the latency of the server apart from the transfer time.
+    /// Returns None if no chunk was received.
+    pub fn time_to_first_byte(&self) -> Option<Duration> {
+        self.time_to_first_byte
+    }
+
+    /// Set the headers of the response.
+    pub(crate) fn set_headers(&mut self, headers: HeaderMap) {
+        self.headers = headers;
+    }
+
+    /// Get the headers of the response which served the download.
+    ///
+    /// They are only captured with
+    /// [`DownloaderBuilder::capture_headers`](crate::downloader::DownloaderBuilder::capture_headers),
+    /// and empty otherwise or if the response was an error.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Set the summary's disposition type.
+    pub(crate) fn set_disposition(&mut self, disposition: Option<DispositionType>) {
+        self.disposition = disposition;
+    }
+
+    /// Get a reference to the disposition type sent by the server.
+    ///
+    /// Returns None if the response did not contain a valid
+    /// `Content-Disposition` header.
+    pub fn disposition(&self) -> Option<&DispositionType> {
+        self.disposition.as_ref()
+    }
+}
+
+/// Represents the aggregated statistics of a batch of [`Download`]s.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BatchStats {
+    /// Number of successful downloads.
+    succeeded: usize,
+    /// Number of failed downloads.
+    failed: usize,
+    /// Number of skipped downloads.
+    skipped: usize,
+    /// Number of downloads which did not start.
+    not_started: usize,
+    /// Total size of the successful downloads in bytes.
+    total_bytes: u64,
+    /// Wall time spent downloading the batch.
+    elapsed: Duration,
+}
+
+impl BatchStats {
+    /// Compute the [`BatchStats`] from the [`Summary`]s of a batch.
+    pub fn new(summaries: &[Summary], elapsed: Duration) -> Self {
+        let mut stats = Self {
+            elapsed,
+            ..Self::default()
+        };
+        for summary in summaries {
+            match summary.status() {
+                Status::Success => {
+                    stats.succeeded += 1;
+                    stats.total_bytes += summary.size();
+                }
+                Status::Fail(_) | Status::ChecksumMismatch(_) => stats.failed += 1,
+                Status::Skipped(_) => stats.skipped += 1,
+                Status::NotStarted => stats.not_started += 1,
+            }
+        }
+        stats
+    }
+
+    /// Get the number of successful downloads.
+    pub fn succeeded(&self) -> usize {
+        self.succeeded
+    }
+
+    /// Get the number of failed downloads.
+    pub fn failed(&self) -> usize {
+        self.failed
+    }
+
+    /// Get the number of skipped downloads.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Get the number of downloads which did not start.
+    pub fn not_started(&self) -> usize {
+        self.not_started
+    }
+
+    /// Get the total size of the successful downloads in bytes.
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    /// Get the wall time spent downloading the batch.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Get the average throughput of the batch in bytes per second.
+    pub fn throughput(&self) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            secs if secs > 0.0 => self.total_bytes as f64 / secs,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Represents the estimated duration of a batch of [`Download`]s.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Estimate {
+    /// Total size of the downloads whose size is known, in bytes.
+    total_bytes: u64,
+    /// Number of downloads whose size is unknown.
+    unknown_sizes: usize,
+    /// Measured throughput in bytes per second, if any.
+    throughput: Option<f64>,
+}
+
+impl Estimate {
+    /// Create a new [`Estimate`].
+    pub fn new(total_bytes: u64, unknown_sizes: usize, throughput: Option<f64>) -> Self {
+        Self {
+            total_bytes,
+            unknown_sizes,
+            throughput,
+        }
+    }
+
+    /// Get the total size of the downloads whose size is known, in bytes.
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    /// Get the number of downloads whose size is unknown, and which are not
+    /// accounted for in the estimate.
+    pub fn unknown_sizes(&self) -> usize {
+        self.unknown_sizes
+    }
+
+    /// Get the measured throughput in bytes per second, if it could be
+    /// measured.
+    pub fn throughput(&self) -> Option<f64> {
+        self.throughput
+    }
+
+    /// Get the estimated duration of the downloads, if the throughput could be
+    /// measured.
+    pub fn duration(&self) -> Option<Duration> {
+        match self.throughput {
+            Some(throughput) if throughput > 0.0 => Some(Duration::from_secs_f64(
+                self.total_bytes as f64 / throughput,
+            )),
+            _ => None,
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn test_referer() {
+        let d = Download::try_from("https://cdn.example.com/file.zip")
+            .unwrap()
+            .referer("https://example.com/releases.html")
+            .unwrap();
+        assert_eq!(d.headers[REFERER], "https://example.com/releases.html");
+        let e = Download::try_from("https://cdn.example.com/file.zip")
+            .unwrap()
+            .referer("releases.html")
+            .unwrap_err();
+        assert!(matches!(e, Error::InvalidUrl(_)));
+    }
+
+    #[test]
+    fn test_is_complete() {
+        let directory = std::env::temp_dir().join("trauma-test-is-complete");
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("hello.txt"), "hello world").unwrap();
+        let d = Download::try_from("https://example.com/hello.txt").unwrap();
+        assert!(d.is_complete(&directory, 11));
+        assert!(!d.is_complete(&directory, 12));
+        assert!(!d.is_complete(&directory.join("missing"), 11));
+
+        let valid = Checksum::Md5("5eb63bbbe01eeed093cb22bb8f5acdc3".into());
+        assert!(d.is_complete_with_checksum(&directory, &valid));
+        let invalid = Checksum::Md5("d41d8cd98f00b204e9800998ecf8427e".into());
+        assert!(!d.is_complete_with_checksum(&directory, &invalid));
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[tokio::test]
+    async fn test_head_rejected() {
+        use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(matchers::method("HEAD"))
+            .respond_with(ResponseTemplate::new(405))
+            .mount(&server)
+            .await;
+        Mock::given(matchers::method("GET"))
+            .and(matchers::header("range", "bytes=0-0"))
+            .respond_with(
+                ResponseTemplate::new(206)
+                    .insert_header("content-range", "bytes 0-0/1024")
+                    .set_body_bytes(vec![0u8]),
+            )
+            .mount(&server)
+            .await;
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new()).build();
+        let d = Download::try_from(format!("{}/file.bin", server.uri()).as_str()).unwrap();
+        assert!(d.is_resumable(&client).await.unwrap());
+        assert_eq!(d.content_length(&client).await.unwrap(), Some(1024));
+    }
+
+    #[test]
+    fn test_is_content_type_expected() {
+        let d = Download::try_from("https://example.com/file.zip").unwrap();
+        assert!(d.is_content_type_expected(None));
+        let d = d.with_content_type("application/zip");
+        let zip = HeaderValue::from_static("Application/ZIP");
+        let html = HeaderValue::from_static("text/html; charset=utf-8");
+        assert!(d.is_content_type_expected(Some(&zip)));
+        assert!(!d.is_content_type_expected(Some(&html)));
+        assert!(!d.is_content_type_expected(None));
+    }
+
+    #[test]
+    fn test_skip_reason_display() {
+        assert_eq!(SkipReason::Filtered.to_string(), "filtered");
+        assert_eq!(
+            SkipReason::AlreadyComplete.to_string(),
+            "the file was already fully downloaded"
+        );
+    }
+
+    #[test]
+    fn test_byte_range() {
+        let range = ByteRange {
+            start: 10,
+            end: Some(19),
+        };
+        assert_eq!(range.size(), Some(10));
+        assert_eq!(range.header_value(), "bytes=10-19");
+        let range = ByteRange {
+            start: 10,
+            end: None,
+        };
+        assert_eq!(range.size(), None);
+        assert_eq!(range.header_value(), "bytes=10-");
+    }
+
     const DOMAIN: &str = "http://domain.com/file.zip";
 
     #[test]
@@ -217,4 +1297,101 @@ mod test {
         let d = Download::try_from(DOMAIN).unwrap();
         assert_eq!(d.filename, "file.zip")
     }
+
+    #[test]
+    fn test_disposition_filename() {
+        let parse = |v| disposition_filename(&HeaderValue::from_static(v));
+        assert_eq!(
+            parse("attachment; filename=\"report.pdf\""),
+            Some("report.pdf".into())
+        );
+        assert_eq!(
+            parse("attachment; FileName=data.csv"),
+            Some("data.csv".into())
+        );
+        assert_eq!(parse("inline"), None);
+
+        // The encoded name is preferred.
+        assert_eq!(
+            parse("attachment; filename=\"resume.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf"),
+            Some("résumé.pdf".into())
+        );
+        assert_eq!(
+            parse("attachment; filename*=iso-8859-1'en'caf%E9.txt"),
+            Some("café.txt".into())
+        );
+        assert_eq!(
+            parse("attachment; filename*=UTF-8''%FF.txt; filename=plain.txt"),
+            Some("plain.txt".into())
+        );
+
+        // The directories are stripped.
+        assert_eq!(
+            parse("attachment; filename=\"../../etc/passwd\""),
+            Some("passwd".into())
+        );
+        assert_eq!(
+            parse("attachment; filename*=UTF-8''..%5C..%5Cboot.ini"),
+            Some("boot.ini".into())
+        );
+        assert_eq!(parse("attachment; filename=\"..\""), None);
+    }
+
+    #[test]
+    fn test_from_base() {
+        let url = |base, relative| {
+            Download::from_base(&Url::parse(base).unwrap(), relative)
+                .unwrap()
+                .url
+                .to_string()
+        };
+        let expected = "https://example.com/files/a/b.zip";
+        assert_eq!(url("https://example.com/files", "a/b.zip"), expected);
+        assert_eq!(url("https://example.com/files/", "a/b.zip"), expected);
+        assert_eq!(url("https://example.com/files/", "/a/b.zip"), expected);
+    }
+
+    #[test]
+    fn test_filename_from_url() {
+        let filename = |url| filename_from_url(&Url::parse(url).unwrap());
+        assert_eq!(
+            filename("https://example.com/a+b%3Dc.zip"),
+            Some("a+b=c.zip".into())
+        );
+        assert_eq!(filename("https://example.com/dir/"), None);
+        assert_eq!(filename("mailto:someone@example.com"), None);
+    }
+
+    #[test]
+    fn test_disposition_type() {
+        let parse = |v| DispositionType::from_header(&HeaderValue::from_static(v));
+        assert_eq!(parse("inline"), Some(DispositionType::Inline));
+        assert_eq!(
+            parse("Attachment; filename=\"file.zip\""),
+            Some(DispositionType::Attachment)
+        );
+        assert_eq!(
+            parse("form-data"),
+            Some(DispositionType::Other("form-data".into()))
+        );
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn test_batch_stats() {
+        let d = Download::try_from(DOMAIN).unwrap();
+        let summaries = vec![
+            Summary::new(d.clone(), StatusCode::OK, 100, false).with_status(Status::Success),
+            Summary::new(d.clone(), StatusCode::OK, 50, false).with_status(Status::Success),
+            Summary::new(d.clone(), StatusCode::NOT_FOUND, 0, false).fail("not found"),
+            Summary::new(d, StatusCode::OK, 10, false)
+                .with_status(Status::Skipped(SkipReason::AlreadyComplete)),
+        ];
+        let stats = BatchStats::new(&summaries, Duration::from_secs(2));
+        assert_eq!(stats.succeeded(), 2);
+        assert_eq!(stats.failed(), 1);
+        assert_eq!(stats.skipped(), 1);
+        assert_eq!(stats.total_bytes(), 150);
+        assert_eq!(stats.throughput(), 75.0);
+    }
 }
@@ -1,17 +1,73 @@
 //! Represents the download controller.
 
-use crate::download::{Download, Status, Summary};
-use futures::stream::{self, StreamExt};
-use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+#[cfg(feature = "serde")]
+use crate::config::DownloaderConfig;
+#[cfg(feature = "rustls")]
+use crate::pinning;
+use crate::{
+    cache::{Content, MemoryCache},
+    compression::Compression,
+    destination::{Destination, ObjectWriter, OneShot},
+    download::{
+        disposition_filename, referer_value, BatchStats, ByteStream, DispositionType, Download,
+        Estimate, SkipReason, Status, Summary,
+    },
+    events::{self, Event, EventWriter},
+    hash::{ChecksumAlgorithm, DynHasher, PieceHashes, PieceVerifier},
+    middleware::{
+        CircuitBreakerMiddleware, RateLimitMiddleware, RetryBudget, RetryMiddleware, RetryStrategy,
+        RETRY_BUDGET,
+    },
+    netrc::Netrc,
+    ranges::Ranges,
+    telemetry,
+    throttle::Throttle,
+    Error,
+};
+use bytes::{Buf, Bytes};
+use encoding_rs::{Encoding, UTF_8};
+use futures::{
+    future,
+    stream::{self, StreamExt, TryStreamExt},
+    Future, Stream,
+};
+use indicatif::{
+    HumanBytes, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressFinish, ProgressStyle,
+};
 use reqwest::{
-    header::{HeaderMap, HeaderValue, IntoHeaderName, RANGE},
-    StatusCode,
+    header::{
+        HeaderMap, HeaderValue, IntoHeaderName, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE,
+        ETAG, IF_NONE_MATCH, RANGE, REFERER,
+    },
+    redirect::Policy,
+    Body, NoProxy, Response, StatusCode, Url,
 };
-use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
-use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, RequestBuilder};
+use reqwest_retry::{policies::ExponentialBackoff, RetryPolicy};
 use reqwest_tracing::TracingMiddleware;
-use std::{fs, path::PathBuf, sync::Arc};
-use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    io::{self, SeekFrom, Write},
+    net::SocketAddr,
+    ops::Range,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    fs::OpenOptions,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt},
+    sync::{mpsc, Notify, Semaphore, SemaphorePermit},
+};
 use tracing::debug;
 
 pub struct TimeTrace;
@@ -33,594 +89,7231 @@ pub struct Downloader {
     directory: PathBuf,
     /// Number of retries per downloaded file.
     retries: u32,
+    /// Policy deciding whether to retry the failed requests, instead of the
+    /// exponential backoff.
+    retry_policy: Option<Hook<dyn RetryPolicy + Send + Sync>>,
     /// Number of maximum concurrent downloads.
     concurrent_downloads: usize,
+    /// Bounds of the number of downloads in flight, adjusted to the load of
+    /// the servers.
+    adaptive_concurrency: Option<(usize, usize)>,
+    /// Maximum number of downloads in flight, adjusted to the throughput of
+    /// the batch.
+    auto_concurrency: Option<usize>,
     /// Downloader style options.
     style_options: StyleOptions,
     /// Resume the download if necessary and possible.
     resumable: bool,
+    /// Hosts supporting range requests, whether they advertise it or not.
+    range_hosts: Vec<String>,
     /// Custom HTTP headers.
     headers: Option<HeaderMap>,
+    /// Number of retries when writing a chunk to disk fails.
+    write_retries: u32,
+    /// Directory where to write the partial files.
+    temp_dir: Option<PathBuf>,
+    /// Salt distinguishing the partial files of this downloader.
+    temp_salt: Option<String>,
+    /// Write the downloads to temporary files, moved in place once complete.
+    use_temp_file: bool,
+    /// Record the number of bytes written to the partial files.
+    resume_state: bool,
+    /// Number of bytes before the end of the partial files compared with the
+    /// remote ones before resuming.
+    resume_overlap: u64,
+    /// Verify the size of the file on disk once downloaded.
+    verify_size: bool,
+    /// Minimum number of bytes received between two progress updates.
+    progress_granularity: u64,
+    /// Number of summaries buffered by the streaming methods.
+    channel_capacity: usize,
+    /// Log receiving the progress of the batches, and its interval.
+    progress_log: Option<(Hook<LogWriter>, Duration)>,
+    /// Writer receiving the lifecycle events of the downloads as JSON lines.
+    jsonl_events: Option<Hook<EventWriter>>,
+    /// Flush the files to the storage device once downloaded.
+    sync_on_complete: bool,
+    /// Request compressed responses and decode them.
+    decompress: bool,
+    /// Decode the files stored compressed, based on their extension, and
+    /// save them without it.
+    auto_decompress: bool,
+    /// Maximum number of requests per period of time sent to each host.
+    rate_limit_per_host: Option<(u32, Duration)>,
+    /// Limiter of the aggregate throughput of the downloads.
+    throttle: Option<Arc<Throttle>>,
+    /// Number of consecutive failures within a period of time after which the
+    /// requests to a host fail fast.
+    circuit_breaker: Option<(u32, Duration)>,
+    /// Credentials read from the netrc file.
+    netrc: Option<Netrc>,
+    /// Hook called on each request before it is sent.
+    inspect_request: Option<Hook<dyn Fn(RequestBuilder) -> RequestBuilder + Send + Sync>>,
+    /// Skip the responses which are not meant to be saved.
+    attachment_only: bool,
+    /// Name the files as suggested by their `Content-Disposition` header.
+    respect_content_disposition: bool,
+    /// File extensions which are downloaded, all if `None`.
+    allow_extensions: Option<Vec<String>>,
+    /// File extensions which are skipped.
+    deny_extensions: Vec<String>,
+    /// Period over which the first downloads are started.
+    ramp_up: Option<Duration>,
+    /// Maximum number of downloads processed at once within a batch.
+    wave_size: Option<usize>,
+    /// Builds the hashers computing the digests of the downloads.
+    hasher: Option<Hook<dyn Fn() -> Box<dyn DynHasher> + Send + Sync>>,
+    /// Name the files after their digest.
+    content_addressed: Option<ChecksumAlgorithm>,
+    /// Length of the digest prefix used as subdirectory in content-addressed
+    /// mode.
+    content_prefix_len: usize,
+    /// Hosts bypassing the proxy.
+    no_proxy: Option<String>,
+    /// Maximum cumulative size of the response headers in bytes.
+    max_header_size: Option<usize>,
+    /// Addresses overriding the DNS resolution of some domains.
+    resolve: Vec<(String, SocketAddr)>,
+    /// Client certificate used for mutual TLS.
+    #[cfg(feature = "native-tls")]
+    identity: Option<reqwest::Identity>,
+    /// SHA-256 digests of the public keys accepted from the servers.
+    #[cfg(feature = "rustls")]
+    certificate_pins: Vec<Vec<u8>>,
+    /// Retry the requests using non-idempotent methods.
+    retry_non_idempotent: bool,
+    /// Status codes which are never retried.
+    no_retry_on: Vec<StatusCode>,
+    /// Maximum time to wait for a chunk of data.
+    read_idle_timeout: Option<Duration>,
+    /// Maximum number of redirects to follow.
+    max_redirects: Option<usize>,
+    /// Allow writing to special files, like FIFOs.
+    allow_special_files: bool,
+    /// Write through the symbolic links within the download directories.
+    follow_symlinks: bool,
+    /// Keep the headers of the responses in the summaries.
+    capture_headers: bool,
+    /// In-memory cache of the content downloaded by
+    /// [`Downloader::download_bytes`].
+    memory_cache: Option<Arc<MemoryCache>>,
+    /// Handling of the responses which cannot be decoded.
+    on_decode_error: DecodeErrorPolicy,
+    /// Destination the downloads are streamed to, if not to the local files.
+    destination: Option<Hook<dyn Destination>>,
+    /// Maximum duration of a request.
+    timeout: Option<Duration>,
+    /// Maximum duration of a download.
+    per_download_timeout: Option<Duration>,
+    /// Maximum number of retries for a whole batch.
+    global_retry_budget: Option<usize>,
+    /// Report the downloads excluded by a filter as skipped.
+    include_filtered: bool,
+    /// Policy applied to the downloads of a batch targeting the same file.
+    collision_policy: CollisionPolicy,
+    /// Hook deciding whether the file on disk is fresh.
+    freshness_check: Option<Hook<FreshnessFn>>,
+    /// Hook post-processing the downloaded files.
+    post_download: Option<Hook<PostDownloadFn>>,
+    /// Hook choosing the path to use when the destination already exists.
+    on_filename_collision: Option<Hook<CollisionFn>>,
+    /// Callback receiving the progress of the downloads.
+    on_progress: Option<Hook<ProgressFn>>,
+    /// Treat the file names differing only by case as colliding. Detected
+    /// from the destination directory if unset.
+    case_insensitive: Option<bool>,
+    /// HTTP client shared by the downloads without proxy, to reuse the
+    /// connections across batches.
+    client: OnceLock<ClientWithMiddleware>,
+    /// Progress bars of the downloads of the latest batch.
+    progress: Arc<Mutex<Vec<(String, ProgressBar)>>>,
 }
 
-impl Downloader {
-    const DEFAULT_RETRIES: u32 = 3;
-    const DEFAULT_CONCURRENT_DOWNLOADS: usize = 32;
-
-    /// Starts the downloads.
-    pub async fn download(&self, downloads: &[Download]) -> Vec<Summary> {
-        self.download_inner(downloads, None).await
-    }
+/// Callback deciding whether the file on disk is fresh.
+type FreshnessFn = dyn Fn(&Download, &Path) -> bool + Send + Sync;
 
-    /// Starts the downloads with proxy.
-    pub async fn download_with_proxy(
-        &self,
-        downloads: &[Download],
-        proxy: reqwest::Proxy,
-    ) -> Vec<Summary> {
-        self.download_inner(downloads, Some(proxy)).await
-    }
+/// Callback post-processing a downloaded file, returning the path to move it
+/// to, if any.
+type PostDownloadFn = dyn Fn(&Summary, &Path) -> Result<Option<PathBuf>, Error> + Send + Sync;
 
-    /// Starts the downloads.
-    pub async fn download_inner(
-        &self,
-        downloads: &[Download],
-        proxy: Option<reqwest::Proxy>,
-    ) -> Vec<Summary> {
-        // Prepare the HTTP client.
-        let retry_policy = ExponentialBackoff::builder().build_with_max_retries(self.retries);
+/// Callback choosing the path to use when the destination already exists.
+type CollisionFn = dyn Fn(&Path) -> PathBuf + Send + Sync;
 
-        let mut inner_client_builder = reqwest::Client::builder();
-        if let Some(proxy) = proxy {
-            inner_client_builder = inner_client_builder.proxy(proxy);
-        }
-        if let Some(headers) = &self.headers {
-            inner_client_builder = inner_client_builder.default_headers(headers.clone());
-        }
+/// Callback receiving the bytes downloaded so far, and the total size if
+/// known.
+type ProgressFn = dyn Fn(&Download, u64, Option<u64>) + Send + Sync;
+type LogWriter = Mutex<dyn Write + Send>;
 
-        let inner_client = inner_client_builder.build().unwrap();
+/// Wraps a user provided callback so it can be stored in a [`Downloader`].
+struct Hook<F: ?Sized>(Arc<F>);
 
-        let client = ClientBuilder::new(inner_client)
-            // Trace HTTP requests. See the tracing crate to make use of these traces.
-            .with(TracingMiddleware::default())
-            // Retry failed requests.
-            .with(RetryTransientMiddleware::new_with_policy(retry_policy))
-            .build();
+impl<F: ?Sized> Clone for Hook<F> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
 
-        // Prepare the progress bar.
-        let multi = match self.style_options.clone().is_enabled() {
-            true => Arc::new(MultiProgress::new()),
-            false => Arc::new(MultiProgress::with_draw_target(ProgressDrawTarget::hidden())),
-        };
-        let main = Arc::new(
-            multi.add(
-                self.style_options
-                    .main
-                    .clone()
-                    .to_progress_bar(downloads.len() as u64),
-            ),
-        );
-        main.tick();
+impl<F: ?Sized> fmt::Debug for Hook<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Hook")
+    }
+}
 
-        // Download the files asynchronously.
-        let summaries = stream::iter(downloads)
-            .map(|d| self.fetch(&client, d, multi.clone(), main.clone()))
-            .buffer_unordered(self.concurrent_downloads)
-            .collect::<Vec<_>>()
-            .await;
+/// Represents the state shared by the downloads of a batch.
+struct Batch {
+    /// HTTP client used for all the requests.
+    client: ClientWithMiddleware,
+    /// HTTP client not decoding the responses, to download the raw content
+    /// of the responses which cannot be decoded.
+    raw_client: Option<ClientWithMiddleware>,
+    /// Container of the progress bars.
+    multi: Arc<MultiProgress>,
+    /// Main progress bar.
+    main: Arc<ProgressBar>,
+    /// Handle controlling the batch.
+    handle: DownloadHandle,
+    /// Unit tracked by the main progress bar.
+    main_progress: MainProgress,
+    /// Number of downloads in the batch.
+    total: usize,
+    /// Number of completed downloads.
+    completed: AtomicUsize,
+    /// Number of bytes received by the downloads.
+    received: AtomicU64,
+    /// Limit of the downloads in flight, adjusted to the load of the servers.
+    adaptive_concurrency: Option<AdaptiveConcurrency>,
+}
 
-        // Finish the progress bar.
-        if self.style_options.main.clear {
-            main.finish_and_clear();
-        } else {
-            main.finish();
+impl Batch {
+    /// Records the number of bytes a download is about to transfer.
+    fn expect_bytes(&self, len: u64) {
+        if self.main_progress == MainProgress::Bytes {
+            self.main.inc_length(len);
         }
-
-        // Return the download summaries.
-        summaries
     }
 
-    /// Fetches the files and write them to disk.
-    async fn fetch(
-        &self,
-        client: &ClientWithMiddleware,
-        download: &Download,
-        multi: Arc<MultiProgress>,
-        main: Arc<ProgressBar>,
-    ) -> Summary {
-        // Create a download summary.
-        let mut size_on_disk: u64 = 0;
-        let mut can_resume = false;
-        let output = self.directory.join(&download.filename);
-        let mut summary = Summary::new(
-            download.clone(),
-            StatusCode::BAD_REQUEST,
-            size_on_disk,
-            can_resume,
-        );
-        let mut content_length: Option<u64> = None;
-
-        // If resumable is turned on...
-        if self.resumable {
-            can_resume = match download.is_resumable(client).await {
-                Ok(r) => r,
-                Err(e) => {
-                    return summary.fail(e);
-                }
-            };
-
-            // Check if there is a file on disk already.
-            if can_resume && output.exists() {
-                debug!("A file with the same name already exists at the destination.");
-                // If so, check file length to know where to restart the download from.
-                size_on_disk = match output.metadata() {
-                    Ok(m) => m.len(),
-                    Err(e) => {
-                        return summary.fail(e);
-                    }
-                };
-
-                // Retrieve the download size from the header if possible.
-                content_length = match download.content_length(client).await {
-                    Ok(l) => l,
-                    Err(e) => {
-                        return summary.fail(e);
-                    }
-                };
-            }
-
-            // Update the summary accordingly.
-            summary.set_resumable(can_resume);
+    /// Records the bytes received by a download.
+    fn receive_bytes(&self, len: u64) {
+        self.received.fetch_add(len, Ordering::SeqCst);
+        if self.main_progress == MainProgress::Bytes {
+            self.main.inc(len);
         }
+    }
 
-        // If resumable is turned on...
-        // Request the file.
-        debug!("Fetching {}", &download.url);
-        let mut req = client.get(download.url.clone());
-        if self.resumable && can_resume {
-            req = req.header(RANGE, format!("bytes={}-", size_on_disk));
+    /// Records a finished download, whatever its outcome.
+    fn complete(&self) {
+        let completed = self.completed.fetch_add(1, Ordering::SeqCst) + 1;
+        match self.main_progress {
+            MainProgress::Files => self.main.inc(1),
+            MainProgress::Bytes => self.main.set_message(files_message(completed, self.total)),
         }
+    }
+}
 
-        // Add extra headers if needed.
-        if let Some(ref h) = self.headers {
-            req = req.headers(h.to_owned());
-        }
+/// Limits the number of downloads in flight, halving the limit when the
+/// servers are overloaded, and increasing it by one on each success.
+struct AdaptiveConcurrency {
+    /// Permits of the downloads in flight.
+    semaphore: Semaphore,
+    /// Current limit, and number of permits to forget once released after
+    /// the limit decreased.
+    state: Mutex<(usize, usize)>,
+    /// Minimum limit.
+    min: usize,
+    /// Maximum limit.
+    max: usize,
+    /// Adjust the limit to the throughput of the batch, instead of the
+    /// outcome of the downloads.
+    by_throughput: bool,
+}
 
-        // Ensure there was no error while sending the request.
-        let res = match req.send().await {
-            Ok(res) => res,
-            Err(e) => {
-                return summary.fail(e);
-            }
-        };
+impl AdaptiveConcurrency {
+    /// Creates a limit starting at `min`.
+    fn new(min: usize, max: usize) -> Self {
+        let min = min.max(1);
+        Self {
+            semaphore: Semaphore::new(min),
+            state: Mutex::new((min, 0)),
+            min,
+            max: max.max(min),
+            by_throughput: false,
+        }
+    }
 
-        // Check wether or not we need to download the file.
-        if let Some(content_length) = content_length {
-            if content_length == size_on_disk {
-                return summary.with_status(Status::Skipped(
-                    "the file was already fully downloaded".into(),
-                ));
-            }
+    /// Creates a limit starting at one, tuned by [`tune_concurrency`].
+    fn by_throughput(max: usize) -> Self {
+        Self {
+            by_throughput: true,
+            ..Self::new(1, max)
         }
+    }
 
-        // Check the status for errors.
-        match res.error_for_status_ref() {
-            Ok(_res) => (),
-            Err(e) => return summary.fail(e),
-        };
+    /// Returns the current limit.
+    fn limit(&self) -> usize {
+        self.state.lock().unwrap().0
+    }
 
-        // Update the summary with the collected details.
-        let size = content_length.unwrap_or_default() + size_on_disk;
-        let status = res.status();
-        summary = Summary::new(download.clone(), status, size, can_resume);
+    /// Changes the limit, computing it from the current one under a single
+    /// lock.
+    fn update_limit(&self, f: impl FnOnce(usize) -> usize) {
+        let mut state = self.state.lock().unwrap();
+        self.resize(&mut state, f);
+    }
 
-        // If there is nothing else to download for this file, we can return.
-        if size_on_disk > 0 && size == size_on_disk {
-            return summary.with_status(Status::Skipped(
-                "the file was already fully downloaded".into(),
-            ));
+    /// Changes the limit of a locked state, within its bounds.
+    ///
+    /// The semaphore grows immediately, but only shrinks as the permits of
+    /// the downloads in flight are released.
+    fn resize(&self, state: &mut (usize, usize), f: impl FnOnce(usize) -> usize) {
+        let (current, debt) = state;
+        let limit = f(*current).clamp(self.min, self.max);
+        if limit > *current {
+            let added = limit - *current;
+            let paid = added.min(*debt);
+            *debt -= paid;
+            self.semaphore.add_permits(added - paid);
+        } else {
+            *debt += *current - limit;
         }
+        *current = limit;
+    }
 
-        // Create the progress bar.
-        // If the download is being resumed, the progress bar position is
-        // updated to start where the download stopped before.
-        let pb = multi.add(
-            self.style_options
-                .child
-                .clone()
-                .to_progress_bar(size)
-                .with_position(size_on_disk),
-        );
-
-        // Prepare the destination directory/file.
-        let output_dir = output.parent().unwrap_or(&output);
-        debug!("Creating destination directory {:?}", output_dir);
-        match fs::create_dir_all(output_dir) {
-            Ok(_res) => (),
-            Err(e) => {
-                return summary.fail(e);
-            }
-        };
-
-        debug!("Creating destination file {:?}", &output);
-        let mut file = match OpenOptions::new()
-            .create(true)
-            .write(true)
-            .append(can_resume)
-            .open(output)
+    /// Waits until a download can start.
+    async fn acquire(&self) -> SemaphorePermit<'_> {
+        self.semaphore
+            .acquire()
             .await
-        {
-            Ok(file) => file,
-            Err(e) => {
-                return summary.fail(e);
-            }
-        };
-
-        let mut final_size = size_on_disk;
-
-        // Download the file chunk by chunk.
-        debug!("Retrieving chunks...");
-        let mut stream = res.bytes_stream();
-        while let Some(item) = stream.next().await {
-            // Retrieve chunk.
-            let mut chunk = match item {
-                Ok(chunk) => chunk,
-                Err(e) => {
-                    return summary.fail(e);
-                }
-            };
-            let chunk_size = chunk.len() as u64;
-            final_size += chunk_size;
-            pb.inc(chunk_size);
+            .expect("the semaphore is never closed")
+    }
 
-            // Write the chunk to disk.
-            match file.write_all_buf(&mut chunk).await {
-                Ok(_res) => (),
-                Err(e) => {
-                    return summary.fail(e);
-                }
-            };
+    /// Adjusts the limit to the outcome of a download, and releases its
+    /// permit.
+    fn release(&self, permit: SemaphorePermit<'_>, summary: &Summary) {
+        let overloaded = matches!(
+            summary.statuscode(),
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+        );
+        let mut state = self.state.lock().unwrap();
+        if overloaded {
+            self.resize(&mut state, |limit| limit / 2);
+        } else if summary.status() == &Status::Success && !self.by_throughput {
+            self.resize(&mut state, |limit| limit + 1);
         }
-
-        // Finish the progress bar once complete, and optionally remove it.
-        if self.style_options.child.clear {
-            pb.finish_and_clear();
-        } else {
-            pb.finish();
+        // Shrink the semaphore as the downloads complete.
+        let (_, debt) = &mut *state;
+        if *debt > 0 {
+            *debt -= 1;
+            permit.forget();
         }
-
-        // Advance the main progress bar.
-        main.inc(1);
-
-        // Create a new summary with the real download size
-        let summary = Summary::new(download.clone(), status, final_size, can_resume);
-        // Return the download summary.
-        summary.with_status(Status::Success)
     }
 }
 
-/// A builder used to create a [`Downloader`].
-///
-/// ```rust
-/// # fn main()  {
-/// use trauma::downloader::DownloaderBuilder;
-///
-/// let d = DownloaderBuilder::new().retries(5).directory("downloads".into()).build();
-/// # }
-/// ```
-pub struct DownloaderBuilder(Downloader);
+/// Searches the concurrency maximizing the throughput, one step at a time:
+/// the limit keeps moving in the same direction while the throughput
+/// improves, and turns back otherwise.
+#[derive(Debug, Default)]
+struct HillClimb {
+    /// Whether the limit is decreasing.
+    falling: bool,
+    /// Throughput measured at the previous limit, in bytes per second.
+    throughput: f64,
+}
 
-impl DownloaderBuilder {
-    /// Creates a builder with the default options.
-    pub fn new() -> Self {
-        DownloaderBuilder::default()
-    }
+impl HillClimb {
+    /// Interval between two throughput measurements.
+    const INTERVAL: Duration = Duration::from_secs(2);
+    /// Relative throughput gain below which the limit turns back.
+    const MIN_GAIN: f64 = 0.05;
 
-    /// Convenience function to hide the progress bars.
-    pub fn hidden() -> Self {
-        let d = DownloaderBuilder::default();
-        d.style_options(StyleOptions::new(
-            ProgressBarOpts::hidden(),
-            ProgressBarOpts::hidden(),
-        ))
+    /// Returns the next limit, given the throughput measured at `limit`.
+    fn next(&mut self, limit: usize, throughput: f64) -> usize {
+        if throughput <= self.throughput * (1.0 + Self::MIN_GAIN) {
+            self.falling = !self.falling;
+        }
+        self.throughput = throughput;
+        match self.falling {
+            true => limit.saturating_sub(1),
+            false => limit + 1,
+        }
     }
+}
 
-    /// Sets the directory where to store the [`Download`]s.
-    pub fn directory(mut self, directory: PathBuf) -> Self {
-        self.0.directory = directory;
-        self
-    }
+/// A handle to pause and resume a batch of downloads while it runs.
+///
+/// While paused, the in-flight downloads stop reading their chunks but keep
+/// their connection open, and no new download starts. If the server closes
+/// the connection in the meantime, the download is resumed with a range
+/// request once the batch is resumed, provided the server supports them.
+/// Otherwise, it fails and can be resumed later by downloading it again.
+#[derive(Debug, Clone, Default)]
+pub struct DownloadHandle {
+    /// Whether the batch is paused.
+    paused: Arc<AtomicBool>,
+    /// Wakes up the paused downloads.
+    notify: Arc<Notify>,
+}
 
-    /// Set the number of retries per [`Download`].
-    pub fn retries(mut self, retries: u32) -> Self {
-        self.0.retries = retries;
-        self
+impl DownloadHandle {
+    /// Pause the downloads.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
     }
 
-    /// Set the number of concurrent [`Download`]s.
-    pub fn concurrent_downloads(mut self, concurrent_downloads: usize) -> Self {
-        self.0.concurrent_downloads = concurrent_downloads;
-        self
+    /// Resume the downloads.
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+        self.notify.notify_waiters();
     }
 
-    /// Set the downloader style options.
-    pub fn style_options(mut self, style_options: StyleOptions) -> Self {
-        self.0.style_options = style_options;
-        self
+    /// Return `true` if the downloads are paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
     }
 
-    fn new_header(&self) -> HeaderMap {
-        match self.0.headers {
-            Some(ref h) => h.to_owned(),
-            _ => HeaderMap::new(),
+    /// Wait until the downloads are resumed, if they are paused.
+    async fn wait_if_paused(&self) {
+        loop {
+            // Register for the notification before checking the flag to
+            // avoid missing a call to `resume()`.
+            let notified = self.notify.notified();
+            if !self.is_paused() {
+                return;
+            }
+            notified.await;
         }
     }
+}
 
-    /// Add the http headers.
+impl Downloader {
+    const DEFAULT_RETRIES: u32 = 3;
+    const DEFAULT_CONCURRENT_DOWNLOADS: usize = 32;
+    const DEFAULT_WRITE_RETRIES: u32 = 3;
+    const WRITE_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+    const MAX_WRITE_RETRY_BACKOFF: Duration = Duration::from_secs(10);
+    const PROBE_SIZE: u64 = 1024 * 1024;
+    const PROBE_DURATION: Duration = Duration::from_secs(2);
+
+    /// Starts the downloads.
     ///
-    /// You need to pass in a `HeaderMap`, not a `HeaderName`.
-    /// `HeaderMap` is a set of http headers.
+    /// The summaries are returned in the order of the downloads.
     ///
-    /// You can call `.headers()` multiple times and all `HeaderMap` will be merged into a single one.
+    /// # Cancellation
     ///
-    /// # Example
+    /// The downloads run within the returned future, without spawning any
+    /// task. Dropping it cancels them: the response streams are dropped,
+    /// closing their connections, and the partially downloaded files are left
+    /// on disk so they can be resumed later.
     ///
-    /// ```
-    /// use reqwest::header::{self, HeaderValue, HeaderMap};
-    /// use trauma::downloader::DownloaderBuilder;
+    /// # Errors
     ///
-    /// let ua = HeaderValue::from_str("curl/7.87").expect("Invalid UA");
+    /// Fails without downloading anything if the HTTP client cannot be built,
+    /// or if the download directory cannot be written. The failures of the
+    /// individual downloads are reported by their [`Summary`] instead.
+    pub async fn download(&self, downloads: &[Download]) -> Result<Vec<Summary>, Error> {
+        self.download_inner(downloads, None).await
+    }
+
+    /// Starts the downloads, calling `on_summary` with each summary as soon as
+    /// its download completes.
     ///
-    /// let builder = DownloaderBuilder::new()
-    ///     .headers(HeaderMap::from_iter([(header::USER_AGENT, ua)]))
-    ///     .build();
-    /// ```
+    /// # Example
     ///
-    /// See also [`header()`].
+    /// ```no_run
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use trauma::{download::Download, downloader::DownloaderBuilder};
     ///
-    /// [`header()`]: DownloaderBuilder::header
-    pub fn headers(mut self, headers: HeaderMap) -> Self {
-        let mut new = self.new_header();
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Report> {
+    /// let downloads = vec![Download::try_from("https://example.com/file.zip")?];
+    /// let downloader = DownloaderBuilder::new().build();
+    /// downloader
+    ///     .download_with_callback(&downloads, |summary| {
+    ///         println!("{}: {:?}", summary.download().filename, summary.status())
+    ///     })
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn download_with_callback(
+        &self,
+        downloads: &[Download],
+        mut on_summary: impl FnMut(Summary),
+    ) -> Result<(), Error> {
+        self.run_with(
+            downloads,
+            None,
+            DownloadHandle::default(),
+            false,
+            |_, summary| {
+                on_summary(summary);
+                future::ready(())
+            },
+        )
+        .await
+    }
+
+    /// Starts the downloads, streaming their summaries in the order of the
+    /// downloads.
+    ///
+    /// The files are still downloaded concurrently, but a summary is only
+    /// yielded once the summaries of the previous downloads were. If the batch
+    /// cannot start, e.g. because the directory is not writable, the stream
+    /// yields the error and ends.
+    ///
+    /// The batch runs on a task of its own, aborted once the stream is
+    /// dropped. Up to [`DownloaderBuilder::channel_capacity`] summaries are
+    /// buffered until they are consumed, after which the downloads wait for
+    /// the stream to be polled.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use futures::StreamExt;
+    /// use trauma::{download::Download, downloader::DownloaderBuilder};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Report> {
+    /// let downloads = vec![
+    ///     Download::try_from("https://example.com/part-1.csv")?,
+    ///     Download::try_from("https://example.com/part-2.csv")?,
+    /// ];
+    /// let downloader = DownloaderBuilder::new().build();
+    /// let mut summaries = downloader.download_ordered_stream(&downloads);
+    /// while let Some(summary) = summaries.next().await {
+    ///     println!("{:?}", summary?.status());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn download_ordered_stream<'a>(
+        &'a self,
+        downloads: &'a [Download],
+    ) -> impl Stream<Item = Result<Summary, Error>> + Unpin + 'a {
+        let (tx, rx) = mpsc::channel(self.channel_capacity.max(1));
+        // Share the HTTP client with the task running the batch.
+        let _ = self.client();
+        let (downloader, downloads) = (self.clone(), downloads.to_vec());
+        let batch = AbortOnDrop(tokio::spawn(async move {
+            let on_summary = move |_, s| {
+                let tx = tx.clone();
+                async move {
+                    let _ = tx.send(s).await;
+                }
+            };
+            downloader
+                .run_with(
+                    &downloads,
+                    None,
+                    DownloadHandle::default(),
+                    true,
+                    on_summary,
+                )
+                .await
+        }));
+        // Yield the summaries, then the failure of the batch once the channel
+        // closes.
+        Box::pin(stream::unfold(Some((rx, batch)), |state| async move {
+            let (mut rx, batch) = state?;
+            if let Some(summary) = rx.recv().await {
+                return Some((Ok(summary), Some((rx, batch))));
+            }
+            match batch.await {
+                Ok(Ok(())) => None,
+                Ok(Err(e)) => Some((Err(e), None)),
+                Err(e) => Some((Err(Error::Internal(e.to_string())), None)),
+            }
+        }))
+    }
+
+    /// Starts the downloads and computes the statistics of the batch.
+    pub async fn download_with_stats(
+        &self,
+        downloads: &[Download],
+    ) -> Result<(Vec<Summary>, BatchStats), Error> {
+        let start = Instant::now();
+        let summaries = self.download(downloads).await?;
+        let stats = BatchStats::new(&summaries, start.elapsed());
+        Ok((summaries, stats))
+    }
+
+    /// Starts the downloads matching a predicate.
+    ///
+    /// The other downloads are reported as skipped, unless
+    /// [`DownloaderBuilder::include_filtered`] is set to `false`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use trauma::{download::Download, downloader::DownloaderBuilder};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Report> {
+    /// let downloads = vec![
+    ///     Download::try_from("https://example.com/debian.iso")?,
+    ///     Download::try_from("https://example.com/debian.iso.sig")?,
+    /// ];
+    /// let downloader = DownloaderBuilder::new().build();
+    /// let summaries = downloader
+    ///     .download_filtered(&downloads, |d| d.filename.ends_with(".iso"))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn download_filtered(
+        &self,
+        downloads: &[Download],
+        predicate: impl Fn(&Download) -> bool,
+    ) -> Result<Vec<Summary>, Error> {
+        let (selected, filtered): (Vec<Download>, Vec<Download>) =
+            downloads.iter().cloned().partition(|d| predicate(d));
+        let mut summaries = self.download(&selected).await?;
+        if self.include_filtered {
+            summaries.extend(filtered.into_iter().map(|d| {
+                Summary::new(d, StatusCode::BAD_REQUEST, 0, false)
+                    .with_status(Status::Skipped(SkipReason::Filtered))
+            }));
+        }
+        Ok(summaries)
+    }
+
+    /// Starts the downloads with a handle to pause and resume them.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use trauma::{download::Download, downloader::DownloaderBuilder};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Report> {
+    /// let downloads = vec![Download::try_from("https://example.com/file.zip")?];
+    /// let downloader = DownloaderBuilder::new().build();
+    /// let (handle, batch) = downloader.download_with_handle(&downloads);
+    /// handle.pause();
+    /// tokio::spawn(async move {
+    ///     tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    ///     handle.resume();
+    /// });
+    /// let summaries = batch.await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn download_with_handle<'a>(
+        &'a self,
+        downloads: &'a [Download],
+    ) -> (
+        DownloadHandle,
+        impl Future<Output = Result<Vec<Summary>, Error>> + 'a,
+    ) {
+        let handle = DownloadHandle::default();
+        let batch = self.run(downloads, None, handle.clone());
+        (handle, batch)
+    }
+
+    /// Starts a single download.
+    ///
+    /// The connections are kept alive between calls, making it suitable for
+    /// polling a URL.
+    pub async fn download_one(&self, download: &Download) -> Result<Summary, Error> {
+        Ok(self
+            .download(std::slice::from_ref(download))
+            .await?
+            .pop()
+            .expect("a summary is returned for every download"))
+    }
+
+    /// Downloads a file in memory.
+    ///
+    /// It is meant for small resources, which do not need to be saved to disk.
+    /// The progress bars are not displayed.
+    ///
+    /// The content is cached if [`DownloaderBuilder::memory_cache`] is set.
+    pub async fn download_bytes(&self, download: &Download) -> Result<Bytes, Error> {
+        let content = self.with_retry_budget(self.content(download)).await?;
+        Ok(content.bytes)
+    }
+
+    /// Downloads a file in memory, along with its `Content-Type`, see
+    /// [`Downloader::download_bytes`].
+    async fn content(&self, download: &Download) -> Result<Content, Error> {
+        let Some(cache) = &self.memory_cache else {
+            let res = self.get(download).await?;
+            let content_type = res.headers().get(CONTENT_TYPE).cloned();
+            let bytes = res.bytes().await?;
+            return Ok(Content {
+                bytes,
+                content_type,
+            });
+        };
+
+        // Revalidate the cached content, if any.
+        let cached = cache.get(&download.url);
+        let mut req = self.prepare_request(self.client()?.get(download.url.clone()), download);
+        if let Some((etag, _)) = &cached {
+            req = req.header(IF_NONE_MATCH, etag);
+        }
+        let res = req.send().await?;
+        if let Some((_, content)) = cached.filter(|_| res.status() == StatusCode::NOT_MODIFIED) {
+            debug!("Serving {} from the memory cache", &download.url);
+            return Ok(content);
+        }
+
+        let res = res.error_for_status()?;
+        let etag = res.headers().get(ETAG).cloned();
+        let content_type = res.headers().get(CONTENT_TYPE).cloned();
+        let content = Content {
+            bytes: res.bytes().await?,
+            content_type,
+        };
+        if let Some(etag) = etag {
+            cache.insert(download.url.clone(), etag, content.clone());
+        }
+        Ok(content)
+    }
+
+    /// Downloads a file in memory and decodes it as text.
+    ///
+    /// The charset of the `Content-Type` header is used if present, UTF-8
+    /// otherwise. The content is cached like the one of
+    /// [`Downloader::download_bytes`].
+    pub async fn download_text(&self, download: &Download) -> Result<String, Error> {
+        let content = self.with_retry_budget(self.content(download)).await?;
+        Ok(decode_text(&content))
+    }
+
+    /// Downloads a file in memory and deserializes it from JSON.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use std::collections::HashMap;
+    /// use trauma::{download::Download, downloader::DownloaderBuilder};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Report> {
+    /// let downloader = DownloaderBuilder::new().build();
+    /// let manifest: HashMap<String, String> = downloader
+    ///     .download_json(&Download::try_from("https://example.com/manifest.json")?)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub async fn download_json<T: DeserializeOwned>(
+        &self,
+        download: &Download,
+    ) -> Result<T, Error> {
+        let bytes = self.download_bytes(download).await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// Downloads the parts of a file concurrently, and concatenates them in
+    /// order into `output`.
+    ///
+    /// The parts are written directly at their offset in the output file,
+    /// which requires their sizes to be known upfront. They are therefore
+    /// retrieved with `HEAD` requests first.
+    ///
+    /// As the output file is preallocated, the byte ranges actually written
+    /// are recorded in an `<output>.state` file, removed once complete. If
+    /// the download is interrupted, calling this method again resumes each
+    /// part after its bytes already written. The download fails if there are
+    /// no parts.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use trauma::{download::Download, downloader::DownloaderBuilder};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Report> {
+    /// let parts = (0..4)
+    ///     .map(|i| Download::try_from(format!("https://example.com/shard-{i}").as_str()))
+    ///     .collect::<Result<Vec<_>, _>>()?;
+    /// let downloader = DownloaderBuilder::new().build();
+    /// let summary = downloader
+    ///     .download_concat(&parts, "dataset.bin".into())
+    ///     .await;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn download_concat(&self, downloads: &[Download], output: PathBuf) -> Summary {
+        self.with_retry_budget(self.concat(downloads, output)).await
+    }
+
+    /// Concatenates the parts of a file, see [`Downloader::download_concat`].
+    async fn concat(&self, downloads: &[Download], output: PathBuf) -> Summary {
+        let filename = output.to_string_lossy().into_owned();
+        let Some(first) = downloads.first() else {
+            let url = Url::parse("data:,").expect("the URL is valid");
+            let target = Download::new(&url, &filename);
+            return Summary::new(target, StatusCode::BAD_REQUEST, 0, false)
+                .fail("there are no parts to concatenate");
+        };
+        let target = Download {
+            filename,
+            ..first.clone()
+        };
+        let mut summary = Summary::new(target, StatusCode::OK, 0, false);
+        let output = self.directory.join(output);
+        let client = match self.client() {
+            Ok(client) => client,
+            Err(e) => return summary.fail(e),
+        };
+
+        // Retrieve the sizes of the parts to compute their offsets.
+        let sizes = stream::iter(downloads)
+            .map(|d| async move {
+                match d
+                    .content_length_with(client, |req| self.prepare_request(req, d))
+                    .await?
+                {
+                    Some(size) => Ok(size),
+                    None => Err(Error::Internal(format!("the size of {} is unknown", d.url))),
+                }
+            })
+            .buffered(self.concurrent_downloads)
+            .collect::<Vec<Result<u64, Error>>>()
+            .await;
+        let sizes = match sizes.into_iter().collect::<Result<Vec<_>, _>>() {
+            Ok(sizes) => sizes,
+            Err(e) => return summary.fail(e),
+        };
+        let total = sizes.iter().sum();
+
+        // Resume the output file if the state of a previous attempt matches,
+        // or preallocate it.
+        let state = state_path(&output);
+        let resumed = fs::read_to_string(&state)
+            .ok()
+            .and_then(|s| s.parse::<Ranges>().ok())
+            .filter(|ranges| ranges.end() <= total)
+            .filter(|_| fs::metadata(&output).is_ok_and(|m| m.len() == total));
+        let ranges = match resumed {
+            Some(ranges) => ranges,
+            None => {
+                if let Some(dir) = output.parent() {
+                    if let Err(e) = fs::create_dir_all(dir) {
+                        return summary.fail(e);
+                    }
+                }
+                if let Err(e) = fs::File::create(&output).and_then(|f| f.set_len(total)) {
+                    return summary.fail(e);
+                }
+                Ranges::default()
+            }
+        };
+        let ranges = tokio::sync::Mutex::new(ranges);
+
+        // Download the parts at their offset.
+        let pb = self.style_options.child.clone().to_progress_bar(total);
+        let offsets = sizes.iter().scan(0, |offset, size| {
+            let start = *offset;
+            *offset += size;
+            Some(start)
+        });
+        let parts = downloads.iter().zip(offsets).zip(&sizes);
+        let results = stream::iter(parts)
+            .map(|((d, offset), size)| {
+                let part = offset..offset + size;
+                self.fetch_part(client, d, &output, part, (&ranges, &state), &pb)
+            })
+            .buffer_unordered(self.concurrent_downloads)
+            .collect::<Vec<_>>()
+            .await;
+        if self.style_options.child.clear {
+            pb.finish_and_clear();
+        } else {
+            pb.finish();
+        }
+        match results.into_iter().collect::<Result<(), _>>() {
+            Ok(()) => {
+                let _ = fs::remove_file(&state);
+                summary.set_size(total);
+                summary.with_status(Status::Success)
+            }
+            Err(e) => summary.fail(e),
+        }
+    }
+
+    /// Downloads a file into a file handle opened by the caller.
+    ///
+    /// The content is written from the current position of the handle, which
+    /// gives full control over how the file is created, e.g. its flags or its
+    /// permissions. The download is never resumed, nor renamed into place:
+    /// the directory and temporary file settings do not apply.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use tokio::fs::OpenOptions;
+    /// use trauma::{download::Download, downloader::DownloaderBuilder};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Report> {
+    /// let file = OpenOptions::new()
+    ///     .create_new(true)
+    ///     .write(true)
+    ///     .open("file.zip")
+    ///     .await?;
+    /// let downloader = DownloaderBuilder::new().build();
+    /// let summary = downloader
+    ///     .download_into_file(&Download::try_from("https://example.com/file.zip")?, file)
+    ///     .await;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn download_into_file(
+        &self,
+        download: &Download,
+        mut file: tokio::fs::File,
+    ) -> Summary {
+        let summary = self.download_to_writer(download, &mut file).await;
+        if self.sync_on_complete && summary.status() == &Status::Success {
+            if let Err(e) = file.sync_all().await {
+                return summary.fail(e);
+            }
+        }
+        summary
+    }
+
+    /// Downloads a file into a writer, e.g. an in-memory buffer or a socket.
+    ///
+    /// The content is streamed into the writer like the other downloads are
+    /// written to their files: with the same retries, ranges, decoding,
+    /// checksum verification, events and progress bar. The download is never
+    /// resumed.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use trauma::{download::Download, downloader::DownloaderBuilder};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Report> {
+    /// let mut manifest = Vec::new();
+    /// let downloader = DownloaderBuilder::new().build();
+    /// let summary = downloader
+    ///     .download_to_writer(
+    ///         &Download::try_from("https://example.com/manifest.json")?,
+    ///         &mut manifest,
+    ///     )
+    ///     .await;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn download_to_writer(
+        &self,
+        download: &Download,
+        mut writer: impl AsyncWrite + Unpin,
+    ) -> Summary {
+        // Stream the object of the download into the writer, through a pipe.
+        let (object, mut content) = tokio::io::duplex(WRITER_BUFFER_SIZE);
+        let downloader = Downloader {
+            destination: Some(Hook(Arc::new(OneShot::new(Box::pin(object))))),
+            ..self.clone()
+        };
+        // The downloader is dropped once done, closing the pipe even if the
+        // object was never opened.
+        let fetch = async move { downloader.download_one(download).await };
+        let copy = async move {
+            tokio::io::copy(&mut content, &mut writer).await?;
+            writer.flush().await
+        };
+        let (summary, copied) = tokio::join!(fetch, copy);
+        let summary = match summary {
+            Ok(summary) => summary,
+            Err(e) => Summary::new(download.clone(), StatusCode::BAD_REQUEST, 0, false).fail(e),
+        };
+        match copied {
+            Err(e) if summary.status() == &Status::Success => summary.fail(e),
+            _ => summary,
+        }
+    }
+
+    /// Downloads a file into memory, through
+    /// [`download_to_writer`](Downloader::download_to_writer).
+    ///
+    /// Unlike [`download_bytes`](Downloader::download_bytes), the progress bar
+    /// is displayed.
+    ///
+    /// # Errors
+    ///
+    /// Fails with the reason of the failure of the download.
+    pub async fn download_to_bytes(&self, download: &Download) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::new();
+        let summary = self.download_to_writer(download, &mut bytes).await;
+        match summary.status() {
+            Status::Success => Ok(bytes),
+            Status::Fail(e) => Err(Error::Internal(e.clone())),
+            status => Err(Error::Internal(format!("{:?}", status))),
+        }
+    }
+
+    /// Estimates how long downloading a batch would take, before starting it.
+    ///
+    /// The sizes of the files are retrieved with `HEAD` requests, and the
+    /// throughput is measured by downloading a sample of the first file whose
+    /// size is known, for up to 1 MiB or 2 seconds. The sample is discarded.
+    ///
+    /// The estimate assumes the whole batch is transferred at the throughput
+    /// of a single connection, which is conservative when the files are
+    /// downloaded concurrently from different hosts.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the HTTP client cannot be built.
+    pub async fn estimate(&self, downloads: &[Download]) -> Result<Estimate, Error> {
+        let client = self.client()?;
+        let sizes = stream::iter(downloads)
+            .map(|d| async move {
+                let prepare = |req| self.prepare_request(req, d);
+                d.content_length_with(client, prepare).await.ok().flatten()
+            })
+            .buffered(self.concurrent_downloads)
+            .collect::<Vec<_>>()
+            .await;
+        let total_bytes = sizes.iter().flatten().sum();
+        let unknown_sizes = sizes.iter().filter(|s| s.is_none()).count();
+
+        // Measure the throughput on a sample of a file.
+        let sample = downloads
+            .iter()
+            .zip(&sizes)
+            .find(|(_, size)| size.is_some_and(|size| size > 0));
+        let throughput = match sample {
+            Some((download, _)) => self.probe(download).await.ok(),
+            None => None,
+        };
+        Ok(Estimate::new(total_bytes, unknown_sizes, throughput))
+    }
+
+    /// Checks the links of the downloads with concurrent `HEAD` requests,
+    /// without downloading them.
+    ///
+    /// A link is broken if the request fails or if the server answers with an
+    /// error status, once the redirections are followed. The statuses are
+    /// returned in the order of the downloads.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the HTTP client cannot be built.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use trauma::{download::Download, downloader::DownloaderBuilder};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Report> {
+    /// let downloads = vec![Download::try_from("https://example.com/file.zip")?];
+    /// let downloader = DownloaderBuilder::new().build();
+    /// for link in downloader.check_links(&downloads).await? {
+    ///     if let Err(reason) = link.result() {
+    ///         println!("{} is broken: {}", link.url(), reason);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn check_links(&self, downloads: &[Download]) -> Result<Vec<LinkStatus>, Error> {
+        let client = self.client()?;
+        let statuses = stream::iter(downloads)
+            .map(|d| async move {
+                let req = self.prepare_request(client.head(d.url.clone()), d);
+                let result = match req.send().await {
+                    Ok(res) => match res.error_for_status_ref() {
+                        Ok(_) => {
+                            // The body of a response to a `HEAD` request is
+                            // empty, the size is read from the header.
+                            let size = res
+                                .headers()
+                                .get(CONTENT_LENGTH)
+                                .and_then(|v| v.to_str().ok())
+                                .and_then(|v| v.parse().ok());
+                            Ok((res.status(), size))
+                        }
+                        Err(e) => Err(e.to_string()),
+                    },
+                    Err(e) => Err(e.to_string()),
+                };
+                LinkStatus {
+                    url: d.url.clone(),
+                    result,
+                }
+            })
+            .buffered(self.concurrent_downloads)
+            .collect()
+            .await;
+        Ok(statuses)
+    }
+
+    /// Starts the downloads with proxy.
+    ///
+    /// The hosts matching the [`DownloaderBuilder::no_proxy`] patterns, or the
+    /// `NO_PROXY` environment variable if unset, bypass the proxy.
+    ///
+    /// # Errors
+    ///
+    /// Fails without downloading anything if the client cannot be built. The
+    /// URL of a proxy is validated when the [`reqwest::Proxy`] is created, so
+    /// a proxy which cannot be reached, or whose URL is only resolved per
+    /// request, fails the affected downloads instead.
+    pub async fn download_with_proxy(
+        &self,
+        downloads: &[Download],
+        proxy: reqwest::Proxy,
+    ) -> Result<Vec<Summary>, Error> {
+        self.download_inner(downloads, Some(proxy)).await
+    }
+
+    /// Starts the downloads.
+    pub async fn download_inner(
+        &self,
+        downloads: &[Download],
+        proxy: Option<reqwest::Proxy>,
+    ) -> Result<Vec<Summary>, Error> {
+        self.run(downloads, proxy, DownloadHandle::default()).await
+    }
+
+    /// Returns a snapshot of the progress of the downloads of the latest
+    /// batch.
+    ///
+    /// The downloads are listed once they start transferring data, and kept
+    /// until the next batch starts. This allows to poll the progress, e.g. to
+    /// serve it from a status endpoint.
+    pub fn progress(&self) -> Vec<ProgressSnapshot> {
+        self.progress
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(filename, pb)| ProgressSnapshot {
+                filename: filename.clone(),
+                downloaded: pb.position(),
+                total: pb.length(),
+            })
+            .collect()
+    }
+
+    /// Runs a batch of downloads, collecting the summaries in the order of the
+    /// downloads.
+    async fn run(
+        &self,
+        downloads: &[Download],
+        proxy: Option<reqwest::Proxy>,
+        handle: DownloadHandle,
+    ) -> Result<Vec<Summary>, Error> {
+        let mut summaries: Vec<Option<Summary>> = downloads.iter().map(|_| None).collect();
+        self.run_with(downloads, proxy, handle, false, |i, summary| {
+            summaries[i] = Some(summary);
+            future::ready(())
+        })
+        .await?;
+        Ok(summaries.into_iter().flatten().collect())
+    }
+
+    /// Runs a batch of downloads, passing the summaries to a callback along
+    /// with the index of their download, as they complete, or in the order of
+    /// the downloads if `ordered` is set.
+    ///
+    /// The next summary is only passed once the future returned by the
+    /// callback completes.
+    ///
+    /// The retries of the batch are drawn from a budget of its own.
+    async fn run_with<F>(
+        &self,
+        downloads: &[Download],
+        proxy: Option<reqwest::Proxy>,
+        handle: DownloadHandle,
+        ordered: bool,
+        on_summary: impl FnMut(usize, Summary) -> F,
+    ) -> Result<(), Error>
+    where
+        F: Future<Output = ()>,
+    {
+        let batch = self.run_batch(downloads, proxy, handle, ordered, on_summary);
+        self.with_retry_budget(batch).await
+    }
+
+    /// Runs a batch of downloads, see [`Downloader::run_with`].
+    async fn run_batch<F>(
+        &self,
+        downloads: &[Download],
+        proxy: Option<reqwest::Proxy>,
+        handle: DownloadHandle,
+        ordered: bool,
+        mut on_summary: impl FnMut(usize, Summary) -> F,
+    ) -> Result<(), Error>
+    where
+        F: Future<Output = ()>,
+    {
+        // Fail fast if the files cannot be written.
+        if !downloads.is_empty() && self.destination.is_none() {
+            check_writable(&self.directory)?;
+        }
+
+        // Forget the progress of the previous batch.
+        self.progress.lock().unwrap().clear();
+
+        // Prepare the HTTP clients, downloading the raw content if it cannot be
+        // decoded and the policy allows it.
+        let raw_client = match (self.decompress, self.on_decode_error) {
+            (true, DecodeErrorPolicy::FallBackToRaw) => {
+                let raw = Downloader {
+                    decompress: false,
+                    ..self.clone()
+                };
+                Some(raw.build_client(proxy.clone())?)
+            }
+            _ => None,
+        };
+        let client = match proxy {
+            Some(proxy) => self.build_client(Some(proxy))?,
+            None => self.client()?.clone(),
+        };
+
+        // Prepare the progress bar.
+        let multi = match self.style_options.is_enabled() {
+            true => Arc::new(MultiProgress::new()),
+            false => Arc::new(MultiProgress::with_draw_target(ProgressDrawTarget::hidden())),
+        };
+        let main_progress = self.style_options.main_progress;
+        let main_len = match main_progress {
+            MainProgress::Files => downloads.len() as u64,
+            MainProgress::Bytes => 0,
+        };
+        let main = Arc::new(multi.add(self.style_options.main.clone().to_progress_bar(main_len)));
+        if main_progress == MainProgress::Bytes {
+            main.set_message(files_message(0, downloads.len()));
+        }
+        main.tick();
+
+        // Download the files asynchronously.
+        let batch = Batch {
+            client,
+            multi,
+            main,
+            handle,
+            main_progress,
+            total: downloads.len(),
+            completed: AtomicUsize::new(0),
+            received: AtomicU64::new(0),
+            raw_client,
+            adaptive_concurrency: match (self.adaptive_concurrency, self.auto_concurrency) {
+                (Some((min, max)), _) => Some(AdaptiveConcurrency::new(min, max)),
+                (None, Some(max)) => Some(AdaptiveConcurrency::by_throughput(max)),
+                (None, None) => None,
+            },
+        };
+        let batch = &batch;
+        let targets = self.resolve_collisions(downloads);
+        let mut jobs = targets.into_iter().enumerate();
+        let wave_size = self.wave_size.unwrap_or(downloads.len()).max(1);
+        let concurrency = match (self.adaptive_concurrency, self.auto_concurrency) {
+            (Some((_, max)), _) | (None, Some(max)) => max.max(1),
+            (None, None) => self.concurrent_downloads,
+        };
+        let started = Instant::now();
+        let waves = async {
+            loop {
+                // Process the batch in waves, one after the other.
+                let wave: Vec<_> = jobs.by_ref().take(wave_size).collect();
+                if wave.is_empty() {
+                    break;
+                }
+                let summaries = stream::iter(wave).map(|(i, target)| async move {
+                    let d = &downloads[i];
+                    let d = match target {
+                        Target::Unique => Cow::Borrowed(d),
+                        Target::Renamed(filename) => Cow::Owned(Download {
+                            filename,
+                            ..d.clone()
+                        }),
+                        Target::Duplicate => {
+                            let summary =
+                                Summary::new(d.clone(), StatusCode::BAD_REQUEST, 0, false)
+                                    .with_status(Status::Skipped(SkipReason::Collision));
+                            telemetry::download_finished(&summary, Duration::ZERO);
+                            self.emit(&d.filename, Event::Finished(&summary)).await;
+                            batch.complete();
+                            return (i, summary);
+                        }
+                    };
+                    let delay = self.ramp_up_delay(i);
+                    if !delay.is_zero() {
+                        tokio::time::sleep(delay).await;
+                    }
+                    let permit = match &batch.adaptive_concurrency {
+                        Some(limit) => Some(limit.acquire().await),
+                        None => None,
+                    };
+                    let _in_flight = telemetry::download_started();
+                    let start = Instant::now();
+                    let summary = self.fetch_with_timeout(batch, &d).await;
+                    telemetry::download_finished(&summary, start.elapsed());
+                    self.emit(&d.filename, Event::Finished(&summary)).await;
+                    batch.complete();
+                    if let Some((limit, permit)) = batch.adaptive_concurrency.as_ref().zip(permit) {
+                        limit.release(permit, &summary);
+                    }
+                    (i, summary)
+                });
+                match ordered {
+                    true => {
+                        summaries
+                            .buffered(concurrency)
+                            .for_each(|(i, summary)| on_summary(i, summary))
+                            .await
+                    }
+                    false => {
+                        summaries
+                            .buffer_unordered(concurrency)
+                            .for_each(|(i, summary)| on_summary(i, summary))
+                            .await
+                    }
+                }
+            }
+        };
+
+        // Tune the concurrency to the throughput while the waves run.
+        let waves = async {
+            tokio::select! {
+                _ = waves => (),
+                _ = tune_concurrency(batch) => (),
+            }
+        };
+
+        // Log the progress periodically while the waves run.
+        match &self.progress_log {
+            Some((Hook(log), interval)) => {
+                tokio::select! {
+                    _ = waves => (),
+                    _ = log_progress(batch, log, *interval) => (),
+                }
+                // End with the average throughput of the batch.
+                let received = batch.received.load(Ordering::SeqCst);
+                write_progress_line(batch, log, received, started.elapsed());
+            }
+            None => waves.await,
+        }
+
+        // Finish the progress bar.
+        if self.style_options.main.clear {
+            batch.main.finish_and_clear();
+        } else {
+            batch.main.finish();
+        }
+        Ok(())
+    }
+
+    /// Runs a call, drawing the retries of its requests from a budget of its
+    /// own if [`DownloaderBuilder::global_retry_budget`] is set.
+    async fn with_retry_budget<F: Future>(&self, call: F) -> F::Output {
+        match self.global_retry_budget {
+            Some(budget) => {
+                RETRY_BUDGET
+                    .scope(Arc::new(AtomicUsize::new(budget)), call)
+                    .await
+            }
+            None => call.await,
+        }
+    }
+
+    /// Returns the HTTP client shared by the downloads without proxy,
+    /// building it on first use.
+    fn client(&self) -> Result<&ClientWithMiddleware, Error> {
+        if let Some(client) = self.client.get() {
+            return Ok(client);
+        }
+        let client = self.build_client(None)?;
+        Ok(self.client.get_or_init(|| client))
+    }
+
+    /// Builds the HTTP client used to send the requests.
+    fn build_client(&self, proxy: Option<reqwest::Proxy>) -> Result<ClientWithMiddleware, Error> {
+        let policy: Arc<dyn RetryPolicy + Send + Sync> = match &self.retry_policy {
+            Some(Hook(policy)) => policy.clone(),
+            None => Arc::new(ExponentialBackoff::builder().build_with_max_retries(self.retries)),
+        };
+        let retry_policy = RetryBudget { policy };
+
+        let mut inner_client_builder = reqwest::Client::builder();
+        if let Some(proxy) = proxy {
+            let no_proxy = match &self.no_proxy {
+                Some(no_proxy) => NoProxy::from_string(no_proxy),
+                None => NoProxy::from_env(),
+            };
+            inner_client_builder = inner_client_builder.proxy(proxy.no_proxy(no_proxy));
+        }
+        if let Some(headers) = &self.headers {
+            inner_client_builder = inner_client_builder.default_headers(headers.clone());
+        }
+        if let Some(max_redirects) = self.max_redirects {
+            inner_client_builder = inner_client_builder.redirect(Policy::limited(max_redirects));
+        }
+        for (domain, addr) in &self.resolve {
+            inner_client_builder = inner_client_builder.resolve(domain, *addr);
+        }
+        if let Some(timeout) = self.timeout {
+            inner_client_builder = inner_client_builder.timeout(timeout);
+        }
+        #[cfg(feature = "gzip")]
+        {
+            inner_client_builder = inner_client_builder.gzip(self.decompress);
+        }
+        #[cfg(feature = "native-tls")]
+        if let Some(identity) = &self.identity {
+            inner_client_builder = inner_client_builder.identity(identity.clone());
+        }
+        #[cfg(all(feature = "native-tls", feature = "rustls"))]
+        if self.identity.is_some() && !self.certificate_pins.is_empty() {
+            return Err(Error::Internal(
+                "a client identity cannot be combined with certificate pins".into(),
+            ));
+        }
+        #[cfg(feature = "rustls")]
+        if !self.certificate_pins.is_empty() {
+            let tls = pinning::tls_config(&self.certificate_pins)?;
+            inner_client_builder = inner_client_builder.use_preconfigured_tls(tls);
+        }
+
+        let inner_client = inner_client_builder.build()?;
+
+        let mut client_builder = ClientBuilder::new(inner_client)
+            // Trace HTTP requests. See the tracing crate to make use of these traces.
+            .with(TracingMiddleware::default());
+        // Stop hitting the failing hosts, counting a retried request once.
+        if let Some((failures, window)) = self.circuit_breaker {
+            client_builder = client_builder.with(CircuitBreakerMiddleware::new(failures, window));
+        }
+        // Retry failed requests.
+        client_builder = client_builder.with(
+            RetryMiddleware::new(
+                retry_policy,
+                RetryStrategy {
+                    no_retry_on: self.no_retry_on.clone(),
+                },
+            )
+            .retry_non_idempotent(self.retry_non_idempotent),
+        );
+        // Throttle the requests, retries included.
+        if let Some((requests, per)) = self.rate_limit_per_host {
+            client_builder = client_builder.with(RateLimitMiddleware::new(requests, per));
+        }
+        Ok(client_builder.build())
+    }
+
+    /// Fetches a file, failing if it takes longer than the per-download
+    /// timeout.
+    ///
+    /// The bytes written by a download which timed out are discarded, keeping
+    /// the ones of the partial file it was resumed from.
+    async fn fetch_with_timeout(&self, batch: &Batch, download: &Download) -> Summary {
+        let written = OnceLock::new();
+        let bar = OnceLock::new();
+        let fetch = self.fetch_from_mirrors(batch, download, &written, &bar);
+        let summary = match self.per_download_timeout {
+            None => fetch.await,
+            Some(timeout) => match tokio::time::timeout(timeout, fetch).await {
+                Ok(summary) => summary,
+                Err(_) => {
+                    if let Some((partial, resumed_from)) = written.get() {
+                        debug!(
+                            "Discarding the bytes written to {:?} after a timeout",
+                            partial
+                        );
+                        let _ = self.discard_attempt(partial, *resumed_from).await;
+                    }
+                    return Summary::new(download.clone(), StatusCode::REQUEST_TIMEOUT, 0, false)
+                        .fail("per-download timeout");
+                }
+            },
+        };
+
+        // Remove the temporary file of a failed download, unless it can be
+        // resumed.
+        let failed = matches!(summary.status(), Status::Fail(_));
+        if failed && !summary.resumable() && self.uses_temp_files() {
+            if let Some((partial, _)) = written.get() {
+                debug!("Removing {:?} after a failure", partial);
+                let _ = tokio::fs::remove_file(partial).await;
+            }
+        }
+        summary
+    }
+
+    /// Fetches a file from its URL, then from its mirrors in order until one
+    /// of them succeeds.
+    ///
+    /// The mirrors share the partial file, so a download interrupted on one
+    /// of them is resumed from the next one, and the progress bar, so the
+    /// download is only reported as started once.
+    async fn fetch_from_mirrors(
+        &self,
+        batch: &Batch,
+        download: &Download,
+        written: &OnceLock<(PathBuf, u64)>,
+        bar: &OnceLock<ProgressBar>,
+    ) -> Summary {
+        let mut summary = self.fetch_or_fall_back(batch, download, written, bar).await;
+        for mirror in &download.mirrors {
+            if !matches!(
+                summary.status(),
+                Status::Fail(_) | Status::ChecksumMismatch(_)
+            ) {
+                break;
+            }
+            debug!("Fetching {} from {}", &download.filename, mirror);
+            let from_mirror = Download {
+                url: mirror.clone(),
+                ..download.clone()
+            };
+            summary = self
+                .fetch_or_fall_back(batch, &from_mirror, written, bar)
+                .await;
+            // Report the original download, served by the mirror.
+            let served_by = summary.final_url().clone();
+            summary.set_final_url(served_by);
+            summary.set_download(download.clone());
+        }
+        summary
+    }
+
+    /// Fetches a file, downloading its raw content if it cannot be decoded
+    /// and the policy allows it.
+    async fn fetch_or_fall_back(
+        &self,
+        batch: &Batch,
+        download: &Download,
+        written: &OnceLock<(PathBuf, u64)>,
+        bar: &OnceLock<ProgressBar>,
+    ) -> Summary {
+        let summary = self
+            .fetch(batch, &batch.client, download, written, bar)
+            .await;
+        match &batch.raw_client {
+            Some(raw_client) if summary.is_undecodable() => {
+                debug!("Downloading the raw content of {}", &download.url);
+                self.fetch(batch, raw_client, download, written, bar).await
+            }
+            _ => summary,
+        }
+    }
+
+    /// Fetches the files and write them to disk.
+    ///
+    /// The path of the partial file, and the number of bytes it was resumed
+    /// from, are recorded in `written` once it is opened for writing. The
+    /// progress bar is kept in `bar`, to be reused by the next attempts of the
+    /// download.
+    async fn fetch(
+        &self,
+        batch: &Batch,
+        client: &ClientWithMiddleware,
+        download: &Download,
+        written: &OnceLock<(PathBuf, u64)>,
+        bar: &OnceLock<ProgressBar>,
+    ) -> Summary {
+        // Wait for the batch to be resumed before starting.
+        batch.handle.wait_if_paused().await;
+
+        // Create a download summary.
+        let mut size_on_disk: u64 = 0;
+        let mut can_resume = false;
+        let mut pieces = download
+            .piece_hashes
+            .as_ref()
+            .filter(|_| download.range.is_none())
+            .map(PieceVerifier::new);
+        // Save the files stored compressed without their extension.
+        let compression = self
+            .auto_decompress
+            .then(|| Compression::from_filename(&download.filename))
+            .flatten();
+        let target = match compression {
+            Some((_, stem)) => Cow::Owned(Download {
+                filename: stem.to_string(),
+                ..download.clone()
+            }),
+            None => Cow::Borrowed(download),
+        };
+        let compression = compression.map(|(compression, _)| compression);
+        let mut output = self.directory.join(&target.filename);
+        let mut partial = self.partial_path(&target);
+        let mut summary = Summary::new(
+            download.clone(),
+            StatusCode::BAD_REQUEST,
+            size_on_disk,
+            can_resume,
+        );
+
+        // Skip the download if the user considers the file fresh.
+        if let Some(Hook(freshness_check)) = &self.freshness_check {
+            if freshness_check(download, &output) {
+                return summary.with_status(Status::Skipped(SkipReason::Fresh));
+            }
+        }
+
+        // Let the user pick another destination if the file already exists.
+        if let Some(Hook(on_filename_collision)) = &self.on_filename_collision {
+            // The partial file of a resumed download is no collision.
+            let own_partial = partial == output && self.may_resume(download, compression.is_some());
+            if self.collision_policy != CollisionPolicy::Overwrite
+                && output.exists()
+                && !own_partial
+            {
+                let renamed = self.directory.join(on_filename_collision(&output));
+                debug!("{:?} already exists, using {:?} instead", &output, &renamed);
+                if partial == output {
+                    partial = renamed.clone();
+                }
+                output = renamed;
+            }
+        }
+
+        // Refuse to write through the symbolic links if requested.
+        if !self.follow_symlinks && self.destination.is_none() {
+            let temp_dir = self.temp_dir.as_ref().unwrap_or(&self.directory);
+            for (path, base) in [(&output, &self.directory), (&partial, temp_dir)] {
+                if let Some(link) = symlinked_component(path, base) {
+                    return summary.fail(format!("{:?} is a symbolic link", link));
+                }
+            }
+        }
+
+        // Stream the special files, like FIFOs, directly without resuming.
+        let special = self.destination.is_none() && is_special_file(&output);
+        if special {
+            if !self.allow_special_files {
+                return summary.fail(format!("{:?} is not a regular file", &output));
+            }
+            partial = output.clone();
+        }
+
+        let mut content_length: Option<u64> = None;
+        let prepare = |req| self.prepare_request(req, download);
+        let resumable = self.may_resume(download, compression.is_some()) && !special;
+        let state = (self.resume_state && resumable).then(|| state_path(&partial));
+
+        // If resumable is turned on...
+        if resumable {
+            // Show a spinner while checking the download, since the requests
+            // can take a while to complete for large batches. It is cleared
+            // once dropped.
+            let spinner = batch.multi.add(
+                self.style_options
+                    .child
+                    .clone()
+                    .to_spinner(format!("checking {}", download.filename)),
+            );
+
+            // Retrieve whether the server supports ranges, along with the size
+            // of the file, unless the ranges are assumed.
+            let probe = match self.assumes_ranges(&download.url) {
+                true => None,
+                false => match download.probe_with(client, prepare).await {
+                    Ok(probe) => Some(probe),
+                    Err(e) => {
+                        return summary.fail(e);
+                    }
+                },
+            };
+            can_resume = probe.is_none_or(|(ranges, _)| ranges);
+            // Size of the remote file, requested at most once.
+            let mut remote_size = probe.map(|(_, size)| size);
+
+            // The files are only moved in place once complete, so skip them
+            // if they have the size of the remote ones.
+            let in_place = fs::metadata(&output)
+                .ok()
+                .filter(|m| m.is_file() && partial != output && !partial.exists());
+            if let Some(metadata) = in_place {
+                let size = match remote_size {
+                    Some(size) => size,
+                    None => match download.content_length_with(client, prepare).await {
+                        Ok(size) => size,
+                        Err(e) => return summary.fail(e),
+                    },
+                };
+                if size == Some(metadata.len()) {
+                    return summary.with_status(Status::Skipped(SkipReason::AlreadyComplete));
+                }
+                remote_size = Some(size);
+            }
+
+            // Check if there is a file on disk already.
+            if can_resume && partial.exists() {
+                debug!("A file with the same name already exists at the destination.");
+                // If so, check file length to know where to restart the download from.
+                size_on_disk = match partial.metadata() {
+                    Ok(m) => m.len(),
+                    Err(e) => {
+                        return summary.fail(e);
+                    }
+                };
+
+                // Only keep the bytes recorded, since the ones written after
+                // them may not have reached the file. Restart from scratch if
+                // the partial file is shorter, since it may be corrupted.
+                if let Some(state) = &state {
+                    let recorded = fs::read_to_string(state)
+                        .ok()
+                        .and_then(|s| s.trim().parse::<u64>().ok());
+                    match recorded {
+                        Some(recorded) if recorded <= size_on_disk => {
+                            if recorded < size_on_disk {
+                                debug!(
+                                    "{:?} has {} bytes, only keeping the {} recorded.",
+                                    &partial, size_on_disk, recorded
+                                );
+                                let truncated = fs::OpenOptions::new()
+                                    .write(true)
+                                    .open(&partial)
+                                    .and_then(|f| f.set_len(recorded));
+                                if let Err(e) = truncated {
+                                    return summary.fail(e);
+                                }
+                            }
+                            size_on_disk = recorded;
+                        }
+                        _ => {
+                            debug!(
+                                "{:?} has {} bytes instead of the {:?} recorded, restarting.",
+                                &partial, size_on_disk, recorded
+                            );
+                            if let Err(e) = fs::remove_file(&partial) {
+                                return summary.fail(e);
+                            }
+                            size_on_disk = 0;
+                        }
+                    }
+                }
+
+                // Retrieve the download size from the header if possible.
+                content_length = match remote_size {
+                    Some(size) => size,
+                    None => match download.content_length_with(client, prepare).await {
+                        Ok(l) => l,
+                        Err(e) => {
+                            return summary.fail(e);
+                        }
+                    },
+                };
+
+                // Restart from scratch if the remote file is now smaller than
+                // the partial one, since it was replaced.
+                if content_length.is_some_and(|l| l < size_on_disk) {
+                    debug!("{:?} is larger than the remote file, restarting.", &partial);
+                    if let Err(e) = fs::remove_file(&partial) {
+                        return summary.fail(e);
+                    }
+                    size_on_disk = 0;
+                }
+
+                // Restart from scratch if the end of the partial file differs
+                // from the remote one.
+                if self.resume_overlap > 0 && size_on_disk > 0 {
+                    match self
+                        .overlap_matches(client, download, &partial, size_on_disk)
+                        .await
+                    {
+                        Ok(true) => (),
+                        Ok(false) => {
+                            debug!("{:?} differs from the remote file, restarting.", &partial);
+                            if let Err(e) = fs::remove_file(&partial) {
+                                return summary.fail(e);
+                            }
+                            size_on_disk = 0;
+                        }
+                        Err(e) => return summary.fail(e),
+                    }
+                }
+
+                // Only keep the complete pieces, verified from disk, since the
+                // last one is downloaded again from its start.
+                if let Some(pieces) = pieces.as_mut().filter(|_| size_on_disk > 0) {
+                    let complete = pieces.complete_len(size_on_disk);
+                    if let Err(e) =
+                        verify_pieces(&partial, complete, pieces, state.as_deref()).await
+                    {
+                        return summary.fail(e);
+                    }
+                    size_on_disk = complete;
+                }
+            }
+
+            // Update the summary accordingly.
+            spinner.finish_and_clear();
+            summary.set_resumable(can_resume);
+            summary.set_resumed_from(size_on_disk);
+        }
+
+        // Request the file, or read it from its stream.
+        let res = match &download.source {
+            Some(source) => match source.take() {
+                Some(stream) => Response::from(http::Response::new(Body::wrap_stream(stream))),
+                None => return summary.fail("the stream of the download was already consumed"),
+            },
+            None => {
+                debug!("Fetching {}", &download.url);
+                let mut req = client.get(download.url.clone());
+                #[cfg(feature = "multipart")]
+                if let Some(form) = &download.form {
+                    req = client.post(download.url.clone()).multipart(form.build());
+                }
+                if resumable && can_resume {
+                    req = req.header(RANGE, format!("bytes={}-", size_on_disk));
+                }
+                if let Some(range) = &download.range {
+                    req = req.header(RANGE, range.header_value());
+                }
+                let req = self.prepare_request(req, download);
+
+                // Ensure there was no error while sending the request.
+                match req.send().await {
+                    Ok(res) => res,
+                    #[cfg(feature = "rustls")]
+                    Err(e) if pinning::is_pin_mismatch(&e) => {
+                        return summary.fail(pinning::PIN_MISMATCH);
+                    }
+                    Err(e) => {
+                        return summary.fail(e);
+                    }
+                }
+            }
+        };
+        let received_at = Instant::now();
+
+        // Reject the oversized headers before reading the body.
+        if let Some(max_header_size) = self.max_header_size {
+            let header_size = header_size(res.headers());
+            if header_size > max_header_size {
+                return summary.fail(format!(
+                    "the response headers are too large: {} bytes, the limit is {} bytes",
+                    header_size, max_header_size
+                ));
+            }
+        }
+
+        // Check wether or not we need to download the file.
+        if let Some(content_length) = content_length {
+            if content_length == size_on_disk {
+                return summary.with_status(Status::Skipped(SkipReason::AlreadyComplete));
+            }
+        }
+
+        // Report the status of the response, even an error one, which the
+        // adaptive concurrency relies on.
+        summary.set_statuscode(res.status());
+
+        // Check the status for errors.
+        match res.error_for_status_ref() {
+            Ok(_res) => (),
+            Err(e) => return summary.fail(e),
+        };
+
+        // Restart from scratch if the server ignored the range and sent the
+        // whole file.
+        if size_on_disk > 0 && res.status() != StatusCode::PARTIAL_CONTENT {
+            debug!("{} ignored the range, restarting.", &download.url);
+            size_on_disk = 0;
+            can_resume = false;
+            summary.set_resumed_from(0);
+            if let Some(pieces) = pieces.as_mut() {
+                pieces.reset();
+            }
+        }
+
+        // Reject the responses of an unexpected type, e.g. error pages.
+        if !download.is_content_type_expected(res.headers().get(CONTENT_TYPE)) {
+            return summary.fail("unexpected content type");
+        }
+
+        // Update the summary with the collected details.
+        if self.capture_headers {
+            summary.set_headers(res.headers().clone());
+        }
+        let status = res.status();
+        let size = match &download.range {
+            Some(range) => {
+                // Servers ignoring the range send the whole file.
+                let available = match status {
+                    StatusCode::PARTIAL_CONTENT => res.content_length(),
+                    _ => res.content_length().map(|l| l.saturating_sub(range.start)),
+                };
+                match (range.size(), available) {
+                    (Some(len), Some(available)) => len.min(available),
+                    (len, available) => len.or(available).unwrap_or_default(),
+                }
+            }
+            // The size of a decoded file is unknown until it is received.
+            None if compression.is_some() => 0,
+            None => content_length
+                .unwrap_or_else(|| res.content_length().unwrap_or_default() + size_on_disk),
+        };
+        summary.set_statuscode(status);
+        summary.set_size(size);
+        if download.source.is_none() {
+            summary.set_final_url(res.url().clone());
+        }
+        let disposition_header = res.headers().get(CONTENT_DISPOSITION);
+        let disposition = disposition_header.and_then(DispositionType::from_header);
+        summary.set_disposition(disposition);
+
+        // Skip the content which is meant to be displayed if requested.
+        if self.attachment_only && summary.disposition() == Some(&DispositionType::Inline) {
+            return summary.with_status(Status::Skipped(SkipReason::InlineDisposition));
+        }
+
+        // Skip the files whose extension is filtered out, preferring the name
+        // suggested by the server.
+        let suggested = disposition_header.and_then(disposition_filename);
+        let filename = suggested.as_ref().unwrap_or(&download.filename);
+        if !self.is_extension_allowed(filename) {
+            return summary.with_status(Status::Skipped(SkipReason::ExtensionFiltered));
+        }
+
+        // Name the file as suggested by the server if requested. The partial
+        // file keeps the name of the download, so it can be resumed.
+        let suggested = suggested
+            .filter(|_| self.respect_content_disposition && !special)
+            .map(|suggested| self.directory.join(suggested))
+            .filter(|suggested| *suggested != output);
+        if let Some(suggested) = suggested {
+            output = suggested;
+
+            // Check the new destination like the one of the download.
+            if let Some(Hook(on_filename_collision)) = &self.on_filename_collision {
+                if self.collision_policy != CollisionPolicy::Overwrite && output.exists() {
+                    let renamed = self.directory.join(on_filename_collision(&output));
+                    debug!("{:?} already exists, using {:?} instead", &output, &renamed);
+                    output = renamed;
+                }
+            }
+            if !self.follow_symlinks {
+                if let Some(link) = symlinked_component(&output, &self.directory) {
+                    return summary.fail(format!("{:?} is a symbolic link", link));
+                }
+            }
+            if is_special_file(&output) {
+                return summary.fail(format!("{:?} is not a regular file", &output));
+            }
+            let in_place = fs::metadata(&output)
+                .is_ok_and(|m| m.is_file() && size > 0 && m.len() == size && size_on_disk == 0);
+            if in_place {
+                return summary.with_status(Status::Skipped(SkipReason::AlreadyComplete));
+            }
+        }
+
+        // If there is nothing else to download for this file, we can return.
+        if size_on_disk > 0 && size == size_on_disk {
+            return summary.with_status(Status::Skipped(SkipReason::AlreadyComplete));
+        }
+
+        // Create the progress bar, or reuse the one of a previous attempt.
+        // If the download is being resumed, the progress bar position is
+        // updated to start where the download stopped before.
+        let pb = match bar.get() {
+            Some(pb) => {
+                pb.set_length(size);
+                pb.set_position(size_on_disk);
+                pb.clone()
+            }
+            None => {
+                batch.expect_bytes(size.saturating_sub(size_on_disk));
+                let pb = batch.multi.add(
+                    self.style_options
+                        .child
+                        .clone()
+                        .to_progress_bar(size)
+                        .with_position(size_on_disk),
+                );
+                self.progress
+                    .lock()
+                    .unwrap()
+                    .push((download.filename.clone(), pb.clone()));
+                let started = Event::Started {
+                    bytes: size_on_disk,
+                    total: (size > 0).then_some(size),
+                };
+                self.emit(&download.filename, started).await;
+                bar.get_or_init(|| pb).clone()
+            }
+        };
+
+        let mut sink = match &self.destination {
+            // Open the object of the destination.
+            Some(Hook(destination)) => {
+                let size_hint = (size > 0).then_some(size);
+                match destination.open(&target.filename, size_hint).await {
+                    Ok(object) => Sink::Object(object),
+                    Err(e) => return summary.fail(e),
+                }
+            }
+            None => {
+                // Prepare the destination directories/file.
+                for path in [&partial, &output] {
+                    let dir = path.parent().unwrap_or(path);
+                    debug!("Creating destination directory {:?}", dir);
+                    match fs::create_dir_all(dir) {
+                        Ok(_res) => (),
+                        Err(e) => {
+                            return summary.fail(e);
+                        }
+                    };
+                }
+
+                debug!("Creating destination file {:?}", &partial);
+                let file = match OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .append(can_resume)
+                    .truncate(!can_resume && !special)
+                    .open(&partial)
+                    .await
+                {
+                    Ok(file) => file,
+                    Err(e) => {
+                        return summary.fail(e);
+                    }
+                };
+                if !special {
+                    let resumed_from = if can_resume { size_on_disk } else { 0 };
+                    let _ = written.set((partial.clone(), resumed_from));
+                }
+                Sink::File(file)
+            }
+        };
+
+        let mut final_size = size_on_disk;
+        let mut recorded = size_on_disk;
+
+        // Prepare the hasher, starting with the bytes which are already on disk.
+        let new_hasher = || match (self.content_addressed, &self.hasher) {
+            (Some(algorithm), _) => Some(algorithm.hasher()),
+            (None, Some(Hook(hasher))) => Some(hasher()),
+            (None, None) => None,
+        };
+        let mut hasher = new_hasher();
+        let mut verifier = download
+            .checksum
+            .as_ref()
+            .map(|checksum| (checksum, checksum.algorithm().hasher()));
+        let hashers = hasher.iter_mut().chain(verifier.iter_mut().map(|(_, h)| h));
+        for hasher in hashers.filter(|_| size_on_disk > 0) {
+            if let Err(e) = hash_file(&partial, hasher.as_mut()).await {
+                return summary.fail(e);
+            }
+        }
+
+        // Only keep the requested range if the server sent the whole file.
+        let (mut skip, mut limit) = match &download.range {
+            Some(range) if status != StatusCode::PARTIAL_CONTENT => (range.start, range.size()),
+            _ => (0, None),
+        };
+
+        // Download the file chunk by chunk.
+        debug!("Retrieving chunks...");
+        let mut stream = res.bytes_stream().map_err(Error::from).boxed();
+        #[cfg(feature = "auto-decompress")]
+        if let Some(compression) = compression {
+            stream = compression.decode(stream);
+        }
+        let mut unreported = 0;
+        let mut stalls = 0;
+        loop {
+            // Stop reading the chunks while the batch is paused.
+            let paused = batch.handle.is_paused();
+            batch.handle.wait_if_paused().await;
+            let next = match self.read_idle_timeout {
+                Some(idle) => match tokio::time::timeout(idle, stream.next()).await {
+                    Ok(next) => next,
+                    // Resume the stalled download after the bytes received.
+                    Err(_) if resumable && can_resume && stalls < self.retries => {
+                        stalls += 1;
+                        debug!("No data received for {:?}, resuming.", idle);
+                        match self.resume_stream(client, download, final_size).await {
+                            Ok(resumed) => stream = resumed,
+                            Err(e) => return summary.fail(e),
+                        }
+                        continue;
+                    }
+                    Err(_) => {
+                        return summary.fail(format!("no data received for {:?}", idle));
+                    }
+                },
+                None => stream.next().await,
+            };
+            let Some(item) = next else {
+                break;
+            };
+
+            // Retrieve chunk.
+            let mut chunk = match item {
+                Ok(chunk) => chunk,
+                // Resume with a range request once the batch is resumed if
+                // the connection was closed while paused.
+                Err(e) if (paused || batch.handle.is_paused()) && resumable && can_resume => {
+                    debug!("Interrupted while paused, resuming: {}", e);
+                    batch.handle.wait_if_paused().await;
+                    match self.resume_stream(client, download, final_size).await {
+                        Ok(resumed) => stream = resumed,
+                        Err(e) => return summary.fail(e),
+                    }
+                    continue;
+                }
+                Err(Error::Reqwest { source }) if source.is_decode() => {
+                    summary.set_undecodable();
+                    return summary.fail(DECODE_ERROR);
+                }
+                Err(e) => {
+                    return summary.fail(e);
+                }
+            };
+            if summary.time_to_first_byte().is_none() {
+                summary.set_time_to_first_byte(received_at.elapsed());
+            }
+            if skip > 0 {
+                let n = skip.min(chunk.len() as u64);
+                chunk.advance(n as usize);
+                skip -= n;
+            }
+            if let Some(limit) = limit.as_mut() {
+                chunk.truncate((*limit).min(chunk.len() as u64) as usize);
+                *limit -= chunk.len() as u64;
+            }
+            let chunk_size = chunk.len() as u64;
+            final_size += chunk_size;
+            unreported += chunk_size;
+            if unreported >= self.progress_granularity {
+                pb.inc(unreported);
+                batch.receive_bytes(unreported);
+                unreported = 0;
+                if let Some(Hook(on_progress)) = &self.on_progress {
+                    on_progress(download, final_size, (size > 0).then_some(size));
+                }
+                let progress = Event::Progress {
+                    bytes: final_size,
+                    total: (size > 0).then_some(size),
+                };
+                self.emit(&download.filename, progress).await;
+            }
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(&chunk);
+            }
+            if let Some((_, verifier)) = verifier.as_mut() {
+                verifier.update(&chunk);
+            }
+            if let Some(pieces) = pieces.as_mut() {
+                pieces.update(&chunk);
+            }
+
+            // Write the chunk to disk.
+            match self.write_chunk(&mut sink, &mut chunk).await {
+                Ok(_res) => (),
+                Err(e) => {
+                    return summary.fail(e);
+                }
+            };
+
+            // Record the number of bytes written so far, every so often.
+            if let Some(state) = state
+                .as_ref()
+                .filter(|_| final_size - recorded >= STATE_INTERVAL)
+            {
+                if let Err(e) = record_state(&mut sink, state, final_size).await {
+                    return summary.fail(e);
+                }
+                recorded = final_size;
+            }
+
+            // Stop once the requested range is complete.
+            if limit == Some(0) {
+                break;
+            }
+        }
+        pb.inc(unreported);
+        batch.receive_bytes(unreported);
+        if let Some(Hook(on_progress)) = self.on_progress.as_ref().filter(|_| unreported > 0) {
+            on_progress(download, final_size, (size > 0).then_some(size));
+        }
+
+        // Ensure all the chunks reached the disk.
+        if let Err(e) = sink.flush().await {
+            return summary.fail(e);
+        }
+
+        // Download the corrupted pieces again, then hash the repaired file.
+        let corrupted = pieces.map(PieceVerifier::finish).unwrap_or_default();
+        if !corrupted.is_empty() {
+            debug!(
+                "{} has {} corrupted pieces.",
+                &download.url,
+                corrupted.len()
+            );
+            if !resumable {
+                return summary.fail(format!("{} corrupted pieces", corrupted.len()));
+            }
+            let pieces = download
+                .piece_hashes
+                .as_ref()
+                .expect("the pieces were verified");
+            for index in corrupted {
+                let repaired = self.repair_piece(client, download, &partial, pieces, index);
+                if let Err(e) = repaired.await {
+                    return summary.fail(e);
+                }
+            }
+            hasher = new_hasher();
+            verifier = verifier.map(|(checksum, _)| (checksum, checksum.algorithm().hasher()));
+            let hashers = hasher.iter_mut().chain(verifier.iter_mut().map(|(_, h)| h));
+            for hasher in hashers {
+                if let Err(e) = hash_file(&partial, hasher.as_mut()).await {
+                    return summary.fail(e);
+                }
+            }
+        }
+        let digest = hasher.map(|hasher| hasher.finalize());
+
+        // Discard the content whose digest does not match the expected one.
+        if let Some((checksum, verifier)) = verifier {
+            let actual = verifier.finalize();
+            if !checksum.matches(&actual) {
+                drop(sink);
+                if self.destination.is_none() && !special {
+                    let _ = fs::remove_file(&partial);
+                    if let Some(state) = &state {
+                        let _ = fs::remove_file(state);
+                    }
+                }
+                return summary.with_status(Status::ChecksumMismatch(format!(
+                    "expected the {:?} digest {}, got {}",
+                    checksum.algorithm(),
+                    checksum.expected(),
+                    actual
+                )));
+            }
+        }
+
+        // The object is complete once the writer is shut down, while the
+        // local files are synced, then moved in place.
+        let local = match sink {
+            Sink::File(file) => {
+                if self.sync_on_complete && !special {
+                    if let Err(e) = file.sync_all().await {
+                        return summary.fail(e);
+                    }
+                }
+                !special
+            }
+            Sink::Object(mut object) => {
+                if let Err(e) = object.shutdown().await {
+                    return summary.fail(e);
+                }
+                false
+            }
+        };
+        let output = match &self.destination {
+            Some(_) => PathBuf::from(&target.filename),
+            None => output,
+        };
+
+        // Name the file after its digest in content-addressed mode.
+        let output = match (&digest, self.content_addressed) {
+            (Some(digest), Some(_)) if local => {
+                let path = self
+                    .directory
+                    .join(content_addressed_path(digest, self.content_prefix_len));
+                if let Some(dir) = path.parent() {
+                    if let Err(e) = fs::create_dir_all(dir) {
+                        return summary.fail(e);
+                    }
+                }
+                path
+            }
+            _ => output,
+        };
+
+        // Move the partial file to its final destination if needed.
+        if local && partial != output {
+            debug!("Moving {:?} to {:?}", &partial, &output);
+            if let Err(e) = move_file(&partial, &output).await {
+                return summary.fail(e);
+            }
+        }
+
+        // Sync the directory entry of the file too, so it survives a power
+        // loss. A file copied across file systems is synced again.
+        if self.sync_on_complete && local {
+            if let Err(e) = sync_entry(&output, partial != output).await {
+                return summary.fail(e);
+            }
+        }
+
+        // The download is complete, its state is not needed anymore.
+        if let Some(state) = &state {
+            let _ = fs::remove_file(state);
+        }
+
+        // Verify the size of the file if requested.
+        if self.verify_size && local {
+            if let Err(e) = verify_size(&output, final_size).await {
+                return summary.fail(e);
+            }
+        }
+
+        // Update the summary with the real download size and digest.
+        summary.set_size(final_size);
+        if let Some(digest) = digest {
+            summary.set_digest(digest);
+        }
+        if local {
+            summary.set_path(output.clone());
+        }
+
+        // Let the user post-process the file, possibly moving it. The objects
+        // of a destination are passed by name, and cannot be moved.
+        if let Some(Hook(post_download)) = &self.post_download {
+            if !special {
+                match post_download(&summary, &output) {
+                    Ok(Some(_)) if !local => {
+                        return summary.fail("the objects of a destination cannot be moved");
+                    }
+                    Ok(Some(path)) => {
+                        let path = self.directory.join(path);
+                        debug!("Moving {:?} to {:?}", &output, &path);
+                        if let Some(dir) = path.parent() {
+                            if let Err(e) = fs::create_dir_all(dir) {
+                                return summary.fail(e);
+                            }
+                        }
+                        if let Err(e) = move_file(&output, &path).await {
+                            return summary.fail(e);
+                        }
+                        summary.set_path(path);
+                    }
+                    Ok(None) => (),
+                    Err(e) => return summary.fail(e),
+                }
+            }
+        }
+
+        // Finish the progress bar once complete, and optionally remove it.
+        if self.style_options.child.clear {
+            pb.finish_and_clear();
+        } else {
+            pb.finish();
+        }
+
+        // Return the download summary.
+        summary.with_status(Status::Success)
+    }
+
+    /// Fetches a part of a file, and writes it at its offset.
+    async fn fetch_part(
+        &self,
+        client: &ClientWithMiddleware,
+        download: &Download,
+        path: &Path,
+        part: Range<u64>,
+        (ranges, state): (&tokio::sync::Mutex<Ranges>, &Path),
+        pb: &ProgressBar,
+    ) -> Result<(), Error> {
+        let (offset, size) = (part.start, part.end - part.start);
+        debug!("Fetching part {} at offset {}", &download.url, offset);
+
+        // Skip the bytes written by a previous attempt.
+        let done = ranges.lock().await.written_from(offset).min(size);
+        if done == size {
+            pb.inc(size);
+            return Ok(());
+        }
+        let mut req = client.get(download.url.clone());
+        if done > 0 {
+            req = req.header(RANGE, format!("bytes={}-", done));
+        }
+        let req = self.prepare_request(req, download);
+        let res = req.send().await?.error_for_status()?;
+        // Servers ignoring the range send the whole part again.
+        let mut written = match res.status() {
+            StatusCode::PARTIAL_CONTENT => done,
+            _ => 0,
+        };
+        pb.inc(written);
+        let mut file = OpenOptions::new().write(true).open(path).await?;
+        file.seek(SeekFrom::Start(offset + written)).await?;
+
+        let mut stream = res.bytes_stream();
+        let mut recorded = written;
+        while let Some(chunk) = stream.next().await {
+            let mut chunk = chunk?;
+            written += chunk.len() as u64;
+            // Never overwrite the next part.
+            if written > size {
+                break;
+            }
+            pb.inc(chunk.len() as u64);
+            self.write_chunk(&mut file, &mut chunk).await?;
+
+            // Record the range written so far, every so often.
+            if written - recorded >= STATE_INTERVAL || written == size {
+                record_range(
+                    &mut file,
+                    ranges,
+                    state,
+                    offset + recorded..offset + written,
+                )
+                .await?;
+                recorded = written;
+            }
+        }
+
+        if written != size {
+            return Err(Error::Internal(format!(
+                "expected {} bytes from {}, received {}",
+                size, download.url, written
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks whether the last bytes of a partial file match the remote ones,
+    /// requesting them again.
+    async fn overlap_matches(
+        &self,
+        client: &ClientWithMiddleware,
+        download: &Download,
+        partial: &Path,
+        size_on_disk: u64,
+    ) -> Result<bool, Error> {
+        let start = size_on_disk.saturating_sub(self.resume_overlap);
+        let req = client
+            .get(download.url.clone())
+            .header(RANGE, format!("bytes={}-{}", start, size_on_disk - 1));
+        let res = self
+            .prepare_request(req, download)
+            .send()
+            .await?
+            .error_for_status()?;
+        if res.status() != StatusCode::PARTIAL_CONTENT {
+            return Ok(false);
+        }
+        let remote = res.bytes().await?;
+        let mut local = vec![0; (size_on_disk - start) as usize];
+        let mut file = tokio::fs::File::open(partial).await?;
+        file.seek(SeekFrom::Start(start)).await?;
+        file.read_exact(&mut local).await?;
+        Ok(remote == local)
+    }
+
+    /// Requests the rest of a stalled download, starting at `offset`.
+    async fn resume_stream(
+        &self,
+        client: &ClientWithMiddleware,
+        download: &Download,
+        offset: u64,
+    ) -> Result<ByteStream, Error> {
+        let req = client
+            .get(download.url.clone())
+            .header(RANGE, format!("bytes={}-", offset));
+        let res = self
+            .prepare_request(req, download)
+            .send()
+            .await?
+            .error_for_status()?;
+        if res.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(Error::Internal(format!(
+                "{} ignored the range of the stalled download",
+                download.url
+            )));
+        }
+        Ok(res.bytes_stream().map_err(Error::from).boxed())
+    }
+
+    /// Downloads a corrupted piece of a file again, and writes it at its
+    /// offset.
+    async fn repair_piece(
+        &self,
+        client: &ClientWithMiddleware,
+        download: &Download,
+        path: &Path,
+        pieces: &PieceHashes,
+        index: usize,
+    ) -> Result<(), Error> {
+        let piece = pieces.range(index);
+        debug!("Fetching piece {} of {} again", index, &download.url);
+        let req = client
+            .get(download.url.clone())
+            .header(RANGE, format!("bytes={}-{}", piece.start, piece.end - 1));
+        let res = self
+            .prepare_request(req, download)
+            .send()
+            .await?
+            .error_for_status()?;
+        if res.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(Error::Internal(format!(
+                "{} ignored the range of piece {}",
+                download.url, index
+            )));
+        }
+        let mut data = res.bytes().await?;
+        if !pieces.verify(index, &data) {
+            return Err(Error::Internal(format!(
+                "piece {} of {} is corrupted",
+                index, download.url
+            )));
+        }
+        let mut file = OpenOptions::new().write(true).open(path).await?;
+        file.seek(SeekFrom::Start(piece.start)).await?;
+        self.write_chunk(&mut file, &mut data).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    /// Adds the extra headers to a request, and lets the user tweak it.
+    fn prepare_request(&self, mut req: RequestBuilder, download: &Download) -> RequestBuilder {
+        // Authenticate with the netrc credentials of the host, if any.
+        let credentials = self
+            .netrc
+            .as_ref()
+            .zip(download.url.host_str())
+            .and_then(|(netrc, host)| netrc.credentials(host));
+        if let Some(credentials) = credentials {
+            req = req.basic_auth(&credentials.login, credentials.password.as_ref());
+        }
+
+        // Add extra headers if needed.
+        if let Some(ref h) = self.headers {
+            req = req.headers(h.to_owned());
+        }
+        req = req.headers(download.headers.clone());
+
+        // Let the user tweak the request.
+        if let Some(Hook(inspect_request)) = &self.inspect_request {
+            req = inspect_request(req);
+        }
+        req
+    }
+
+    /// Downloads the beginning of a file, returning the throughput in bytes
+    /// per second.
+    async fn probe(&self, download: &Download) -> Result<f64, Error> {
+        let req = self
+            .client()?
+            .get(download.url.clone())
+            .header(RANGE, format!("bytes=0-{}", Self::PROBE_SIZE - 1));
+        let req = self.prepare_request(req, download);
+        let start = Instant::now();
+        let res = req.send().await?.error_for_status()?;
+        let mut stream = res.bytes_stream();
+        let mut received = 0;
+        while let Some(chunk) = stream.next().await {
+            received += chunk?.len() as u64;
+            if received >= Self::PROBE_SIZE || start.elapsed() >= Self::PROBE_DURATION {
+                break;
+            }
+        }
+        match start.elapsed().as_secs_f64() {
+            secs if received > 0 && secs > 0.0 => Ok(received as f64 / secs),
+            _ => Err(Error::Internal(format!(
+                "no data received from {}",
+                download.url
+            ))),
+        }
+    }
+
+    /// Checks whether the host of a URL is assumed to support range requests.
+    fn assumes_ranges(&self, url: &Url) -> bool {
+        url.host_str().is_some_and(|host| {
+            self.range_hosts
+                .iter()
+                .any(|h| h.eq_ignore_ascii_case(host))
+        })
+    }
+
+    /// Writes an event of a download to the JSON lines stream, if any.
+    async fn emit(&self, filename: &str, event: Event<'_>) {
+        if let Some(Hook(writer)) = &self.jsonl_events {
+            events::emit(writer, filename, event).await;
+        }
+    }
+
+    /// Sends the request of a download to retrieve its content in memory.
+    async fn get(&self, download: &Download) -> Result<reqwest::Response, Error> {
+        let req = self.prepare_request(self.client()?.get(download.url.clone()), download);
+        let res = req.send().await?;
+        Ok(res.error_for_status()?)
+    }
+
+    /// Detects the downloads of a batch targeting the same file, and applies
+    /// the collision policy to them.
+    fn resolve_collisions(&self, downloads: &[Download]) -> Vec<Target> {
+        if self.collision_policy == CollisionPolicy::Overwrite {
+            return downloads.iter().map(|_| Target::Unique).collect();
+        }
+        let case_insensitive = self
+            .case_insensitive
+            .unwrap_or_else(|| is_case_insensitive(&self.directory));
+        let key = |filename: &str| match case_insensitive {
+            true => filename.to_lowercase(),
+            false => filename.to_string(),
+        };
+
+        let mut seen = HashSet::new();
+        downloads
+            .iter()
+            .map(|d| {
+                if seen.insert(key(&d.filename)) {
+                    return Target::Unique;
+                }
+                if self.collision_policy == CollisionPolicy::Skip {
+                    return Target::Duplicate;
+                }
+                let filename = (1..)
+                    .map(|n| numbered_filename(&d.filename, n))
+                    .find(|f| seen.insert(key(f)))
+                    .expect("a free file name is eventually found");
+                Target::Renamed(filename)
+            })
+            .collect()
+    }
+
+    /// Returns `true` if the extension of a file passes the allow and deny
+    /// lists.
+    fn is_extension_allowed(&self, filename: &str) -> bool {
+        let extension = Path::new(filename)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let matches = |list: &[String]| {
+            list.iter()
+                .any(|e| e.trim_start_matches('.').to_lowercase() == extension)
+        };
+        self.allow_extensions.as_deref().is_none_or(matches) && !matches(&self.deny_extensions)
+    }
+
+    /// Returns how long to wait before starting the download at `index`.
+    ///
+    /// The starts of the first downloads are evenly spread over the ramp-up
+    /// period, until the concurrency target is reached.
+    fn ramp_up_delay(&self, index: usize) -> Duration {
+        match self.ramp_up {
+            Some(ramp_up) if index < self.concurrent_downloads => {
+                ramp_up.mul_f64(index as f64 / self.concurrent_downloads as f64)
+            }
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Returns the path where the partial file of a [`Download`] is written.
+    ///
+    /// Without a temporary directory nor salt, the download is written in
+    /// place if the temporary files are disabled.
+    fn partial_path(&self, download: &Download) -> PathBuf {
+        let dir = self.temp_dir.as_ref().unwrap_or(&self.directory);
+        match (&self.temp_dir, &self.temp_salt) {
+            (_, Some(salt)) => dir.join(format!("{}.{}.part", download.filename, salt)),
+            (None, None) if !self.use_temp_file => dir.join(&download.filename),
+            _ => dir.join(format!("{}.part", download.filename)),
+        }
+    }
+
+    /// Discards the bytes written to a partial file since it was resumed,
+    /// removing it if it was not.
+    async fn discard_attempt(&self, partial: &Path, resumed_from: u64) -> io::Result<()> {
+        let state = state_path(partial);
+        if resumed_from == 0 {
+            let _ = tokio::fs::remove_file(&state).await;
+            return tokio::fs::remove_file(partial).await;
+        }
+        let file = OpenOptions::new().write(true).open(partial).await?;
+        file.set_len(resumed_from).await?;
+        if self.resume_state {
+            tokio::fs::write(&state, resumed_from.to_string()).await?;
+        }
+        Ok(())
+    }
+
+    /// Checks whether a download may be resumed from its partial file.
+    fn may_resume(&self, download: &Download, compressed: bool) -> bool {
+        let resumable = download.resumable_override.unwrap_or(self.resumable)
+            && download.range.is_none()
+            && download.source.is_none()
+            && !self.decompress
+            && self.destination.is_none()
+            && !compressed;
+        #[cfg(feature = "multipart")]
+        let resumable = resumable && download.form.is_none();
+        resumable
+    }
+
+    /// Checks whether the partial files are distinct from the final ones.
+    fn uses_temp_files(&self) -> bool {
+        self.use_temp_file || self.temp_dir.is_some() || self.temp_salt.is_some()
+    }
+
+    /// Writes a chunk, retrying with an exponential backoff on I/O errors.
+    ///
+    /// Since `write_all_buf` advances the buffer as bytes are written, a retry
+    /// only writes what is left of the chunk. The chunk first waits for its
+    /// share of the bandwidth if the throughput is capped.
+    async fn write_chunk<W, B>(&self, writer: &mut W, chunk: &mut B) -> io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+        B: Buf,
+    {
+        if let Some(throttle) = &self.throttle {
+            throttle.consume(chunk.remaining() as u64).await;
+        }
+        let mut attempt = 0;
+        loop {
+            match writer.write_all_buf(chunk).await {
+                Ok(_res) => return Ok(()),
+                Err(e) if attempt < self.write_retries => {
+                    debug!("Failed to write chunk (attempt {}): {}", attempt + 1, e);
+                    let backoff = 2u32.checked_pow(attempt).unwrap_or(u32::MAX);
+                    let backoff = Self::WRITE_RETRY_BACKOFF.saturating_mul(backoff);
+                    tokio::time::sleep(backoff.min(Self::MAX_WRITE_RETRY_BACKOFF)).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Number of bytes written to a partial file between two records of its
+/// state.
+const STATE_INTERVAL: u64 = 1024 * 1024;
+
+/// Task aborted once dropped.
+struct AbortOnDrop<T>(tokio::task::JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+impl<T> Future for AbortOnDrop<T> {
+    type Output = Result<T, tokio::task::JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0).poll(cx)
+    }
+}
+
+/// Size of the pipe streaming a download into a writer.
+const WRITER_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Failure of the downloads whose response cannot be decoded.
+const DECODE_ERROR: &str = "content-encoding decode error";
+
+/// Writer receiving the content of a download.
+enum Sink {
+    /// Local file.
+    File(tokio::fs::File),
+    /// Object of a [`Destination`].
+    Object(ObjectWriter),
+}
+
+impl AsyncWrite for Sink {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Sink::File(file) => Pin::new(file).poll_write(cx, buf),
+            Sink::Object(object) => object.as_mut().poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Sink::File(file) => Pin::new(file).poll_flush(cx),
+            Sink::Object(object) => object.as_mut().poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Sink::File(file) => Pin::new(file).poll_shutdown(cx),
+            Sink::Object(object) => object.as_mut().poll_shutdown(cx),
+        }
+    }
+}
+
+/// Outcome of the collision detection for a download.
+enum Target {
+    /// No other download targets the same file.
+    Unique,
+    /// The download was renamed to avoid a collision.
+    Renamed(String),
+    /// Another download targets the same file.
+    Duplicate,
+}
+
+/// Inserts a number before the extension of a file name: `file (1).txt`.
+fn numbered_filename(filename: &str, n: usize) -> String {
+    let path = Path::new(filename);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{} ({}).{}", stem, n, ext.to_string_lossy()),
+        None => format!("{} ({})", stem, n),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// Checks whether the file system of a directory is case-insensitive, by
+/// looking up a probe file with a different case.
+///
+/// The closest existing ancestor is checked if the directory does not exist
+/// yet. It is assumed to be case-sensitive if the check fails. The result is
+/// cached per directory, so the file system is only probed once.
+fn is_case_insensitive(directory: &Path) -> bool {
+    static CHECKED: OnceLock<Mutex<HashMap<PathBuf, bool>>> = OnceLock::new();
+    let checked = CHECKED.get_or_init(Default::default);
+    if let Some(insensitive) = checked.lock().unwrap().get(directory) {
+        return *insensitive;
+    }
+    match probe_case_insensitive(directory) {
+        Some(insensitive) => {
+            checked
+                .lock()
+                .unwrap()
+                .insert(directory.to_path_buf(), insensitive);
+            insensitive
+        }
+        None => false,
+    }
+}
+
+/// Probes whether the file system of a directory is case-insensitive, see
+/// [`is_case_insensitive`].
+///
+/// Every probe uses a file of its own, so the concurrent batches do not
+/// remove each other's.
+fn probe_case_insensitive(directory: &Path) -> Option<bool> {
+    static PROBES: AtomicUsize = AtomicUsize::new(0);
+    let dir = directory.ancestors().find(|d| d.is_dir())?;
+    let name = format!(
+        ".trauma-case-probe-{}-{}",
+        std::process::id(),
+        PROBES.fetch_add(1, Ordering::Relaxed)
+    );
+    let probe = dir.join(&name);
+    fs::File::create(&probe).ok()?;
+    let insensitive = dir.join(name.to_uppercase()).exists();
+    let _ = fs::remove_file(&probe);
+    Some(insensitive)
+}
+
+/// Checks whether a path points to an existing special file, like a FIFO or a
+/// character device.
+fn is_special_file(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|m| !m.is_file() && !m.is_dir())
+}
+
+/// Returns the first component of a path below a base directory which is a
+/// symbolic link, if any.
+fn symlinked_component<'a>(path: &'a Path, base: &Path) -> Option<&'a Path> {
+    let mut components: Vec<_> = path
+        .ancestors()
+        .take_while(|p| *p != base && p.starts_with(base))
+        .collect();
+    components.reverse();
+    components
+        .into_iter()
+        .find(|p| fs::symlink_metadata(p).is_ok_and(|m| m.file_type().is_symlink()))
+}
+
+/// Decodes a text with the charset of its `Content-Type`, or UTF-8, replacing
+/// the invalid sequences.
+fn decode_text(content: &Content) -> String {
+    let charset = content
+        .content_type
+        .as_ref()
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            value.split(';').skip(1).find_map(|param| {
+                let (name, charset) = param.split_once('=')?;
+                name.trim()
+                    .eq_ignore_ascii_case("charset")
+                    .then(|| charset.trim().trim_matches('"'))
+            })
+        });
+    let encoding = charset
+        .and_then(|label| Encoding::for_label(label.as_bytes()))
+        .unwrap_or(UTF_8);
+    let (text, _, _) = encoding.decode(&content.bytes);
+    text.into_owned()
+}
+
+/// Formats the number of completed downloads of a batch.
+fn files_message(completed: usize, total: usize) -> String {
+    format!("{}/{} files", completed, total)
+}
+
+/// Adjusts the concurrency of a batch to its throughput, forever.
+///
+/// Never completes if the concurrency is not tuned by throughput.
+async fn tune_concurrency(batch: &Batch) {
+    let Some(limit) = batch
+        .adaptive_concurrency
+        .as_ref()
+        .filter(|l| l.by_throughput)
+    else {
+        return future::pending().await;
+    };
+    let mut ticks = tokio::time::interval(HillClimb::INTERVAL);
+    // The first tick completes immediately.
+    ticks.tick().await;
+    let mut climb = HillClimb::default();
+    let mut previous = 0;
+    loop {
+        let start = Instant::now();
+        ticks.tick().await;
+        let received = batch.received.load(Ordering::SeqCst);
+        let throughput = (received - previous) as f64 / start.elapsed().as_secs_f64();
+        previous = received;
+        limit.update_limit(|current| climb.next(current, throughput));
+        debug!("Tuned the concurrency to {}", limit.limit());
+    }
+}
+
+/// Writes a line of progress to the progress log at every interval, forever.
+async fn log_progress(batch: &Batch, log: &LogWriter, interval: Duration) {
+    let mut ticks = tokio::time::interval(interval);
+    // The first tick completes immediately.
+    ticks.tick().await;
+    let mut previous = 0;
+    loop {
+        let start = Instant::now();
+        ticks.tick().await;
+        let received = batch.received.load(Ordering::SeqCst);
+        write_progress_line(batch, log, received - previous, start.elapsed());
+        previous = received;
+    }
+}
+
+/// Writes a line of progress like `[1700000000] 12/50 files, 3.40 GiB,
+/// 45.00 MiB/s` to the progress log, the timestamp being in seconds since the
+/// Unix epoch.
+///
+/// The errors are ignored, the progress log being informative only.
+fn write_progress_line(batch: &Batch, log: &LogWriter, bytes: u64, elapsed: Duration) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let throughput = bytes as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+    let mut log = log.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = writeln!(
+        log,
+        "[{}] {}, {}, {}/s",
+        timestamp,
+        files_message(batch.completed.load(Ordering::SeqCst), batch.total),
+        HumanBytes(batch.received.load(Ordering::SeqCst)),
+        HumanBytes(throughput as u64)
+    );
+    let _ = log.flush();
+}
+
+/// Moves a file, falling back to a copy when a rename is not possible.
+///
+/// A rename cannot cross file systems, in which case the file is copied then
+/// removed. The destination is therefore not updated atomically.
+async fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if tokio::fs::rename(from, to).await.is_ok() {
+        return Ok(());
+    }
+    tokio::fs::copy(from, to).await?;
+    tokio::fs::remove_file(from).await
+}
+
+/// Flushes a file, if requested, and its directory entry to the storage
+/// device.
+///
+/// Directories cannot be synced on Windows, where the entry is left to the
+/// file system.
+async fn sync_entry(path: &Path, sync_file: bool) -> io::Result<()> {
+    if sync_file {
+        tokio::fs::File::open(path).await?.sync_all().await?;
+    }
+    #[cfg(unix)]
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        tokio::fs::File::open(dir).await?.sync_all().await?;
+    }
+    Ok(())
+}
+
+/// Returns the path of a file named after its digest, relative to the
+/// download directory.
+///
+/// The first `prefix_len` characters of the digest are used as subdirectory,
+/// e.g. `ab/cdef…`, to avoid having too many files in a single directory.
+fn content_addressed_path(digest: &str, prefix_len: usize) -> PathBuf {
+    match prefix_len {
+        n if n > 0 && n < digest.len() => Path::new(&digest[..n]).join(&digest[n..]),
+        _ => PathBuf::from(digest),
+    }
+}
+
+/// Returns the cumulative size of headers, as sent on the wire.
+fn header_size(headers: &HeaderMap) -> usize {
+    headers
+        .iter()
+        // Account for the `: ` separator and the line break.
+        .map(|(name, value)| name.as_str().len() + value.len() + 4)
+        .sum()
+}
+
+/// Checks that files can be written to a directory, creating it if needed.
+///
+/// Every check uses a probe file of its own, so the concurrent batches do not
+/// remove each other's.
+fn check_writable(directory: &Path) -> io::Result<()> {
+    static PROBES: AtomicUsize = AtomicUsize::new(0);
+    fs::create_dir_all(directory)?;
+    let probe = directory.join(format!(
+        ".trauma-write-check-{}-{}",
+        std::process::id(),
+        PROBES.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::File::create(&probe)?;
+    fs::remove_file(&probe)
+}
+
+/// Returns the path of the file recording the state of a partial file.
+fn state_path(partial: &Path) -> PathBuf {
+    let mut path = partial.as_os_str().to_owned();
+    path.push(".state");
+    PathBuf::from(path)
+}
+
+/// Records a range of bytes written to a preallocated file, once they reached
+/// the file. The lock serializes the writes of the state file.
+async fn record_range(
+    file: &mut tokio::fs::File,
+    ranges: &tokio::sync::Mutex<Ranges>,
+    state: &Path,
+    range: Range<u64>,
+) -> io::Result<()> {
+    file.flush().await?;
+    let mut ranges = ranges.lock().await;
+    ranges.insert(range);
+    tokio::fs::write(state, ranges.to_string()).await
+}
+
+/// Records the number of bytes written to a partial file, once they reached
+/// the file.
+async fn record_state<W>(file: &mut W, state: &Path, written: u64) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    file.flush().await?;
+    tokio::fs::write(state, written.to_string()).await
+}
+
+/// Feeds the complete pieces of a partial file into a verifier, then truncates
+/// it after them.
+async fn verify_pieces(
+    path: &Path,
+    len: u64,
+    pieces: &mut PieceVerifier<'_>,
+    state: Option<&Path>,
+) -> io::Result<()> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path).await?;
+    let mut buf = vec![0; 64 * 1024];
+    let mut remaining = len;
+    while remaining > 0 {
+        let n = remaining.min(buf.len() as u64) as usize;
+        let n = file.read(&mut buf[..n]).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        pieces.update(&buf[..n]);
+        remaining -= n as u64;
+    }
+    file.set_len(len).await?;
+    if let Some(state) = state {
+        record_state(&mut file, state, len).await?;
+    }
+    Ok(())
+}
+
+/// Feeds the content of a file into a hasher.
+async fn hash_file(path: &Path, hasher: &mut dyn DynHasher) -> io::Result<()> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        hasher.update(&buf[..n]);
+    }
+}
+
+/// Verifies that the size of a file on disk matches the expected one.
+async fn verify_size(path: &Path, expected: u64) -> io::Result<()> {
+    let actual = tokio::fs::metadata(path).await?.len();
+    if actual != expected {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "size mismatch: expected {} bytes, found {} bytes on disk",
+                expected, actual
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Represents the progress of a download at a point in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgressSnapshot {
+    /// Name of the downloaded file.
+    filename: String,
+    /// Number of bytes downloaded, including the resumed ones.
+    downloaded: u64,
+    /// Size of the file in bytes, if known.
+    total: Option<u64>,
+}
+
+impl ProgressSnapshot {
+    /// Get the name of the downloaded file.
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// Get the number of bytes downloaded, including the resumed ones.
+    pub fn downloaded(&self) -> u64 {
+        self.downloaded
+    }
+
+    /// Get the size of the file in bytes, if known.
+    pub fn total(&self) -> Option<u64> {
+        self.total
+    }
+}
+
+/// Represents the outcome of checking a link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkStatus {
+    /// URL of the link.
+    url: Url,
+    /// Status and size of the file, or the reason why the link is broken.
+    result: Result<(StatusCode, Option<u64>), String>,
+}
+
+impl LinkStatus {
+    /// Get the URL of the link.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Get the status and the size of the file, if known, or the reason why
+    /// the link is broken.
+    pub fn result(&self) -> &Result<(StatusCode, Option<u64>), String> {
+        &self.result
+    }
+
+    /// Return `true` if the link is not broken.
+    pub fn is_ok(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+/// A builder used to create a [`Downloader`].
+///
+/// ```rust
+/// # fn main()  {
+/// use trauma::downloader::DownloaderBuilder;
+///
+/// let d = DownloaderBuilder::new().retries(5).directory("downloads".into()).build();
+/// # }
+/// ```
+///
+/// ## Environment variables
+///
+/// The defaults of some options are read from the environment, which allows
+/// to tune a deployed tool without rebuilding it:
+///
+/// | Variable              | Option                                      |
+/// |-----------------------|---------------------------------------------|
+/// | `TRAUMA_TIMEOUT_SECS` | [`DownloaderBuilder::timeout`]              |
+/// | `TRAUMA_RETRIES`      | [`DownloaderBuilder::retries`]              |
+/// | `TRAUMA_CONCURRENCY`  | [`DownloaderBuilder::concurrent_downloads`] |
+///
+/// The builder methods take precedence over the environment, which takes
+/// precedence over the built-in defaults. The values which are not positive
+/// integers, or `0` for the timeout and the concurrency, are ignored.
+pub struct DownloaderBuilder(Downloader);
+
+impl DownloaderBuilder {
+    /// Creates a builder with the default options.
+    pub fn new() -> Self {
+        DownloaderBuilder::default()
+    }
+
+    /// Overrides the defaults with the environment variables, read through
+    /// `var`.
+    fn env_defaults(mut self, var: impl Fn(&str) -> Option<String>) -> Self {
+        let parse = |name| var(name).and_then(|v| v.trim().parse::<u64>().ok());
+        if let Some(secs) = parse("TRAUMA_TIMEOUT_SECS").filter(|&s| s > 0) {
+            self.0.timeout = Some(Duration::from_secs(secs));
+        }
+        if let Some(retries) = parse("TRAUMA_RETRIES").and_then(|r| u32::try_from(r).ok()) {
+            self.0.retries = retries;
+        }
+        if let Some(concurrency) = parse("TRAUMA_CONCURRENCY").filter(|&c| c > 0) {
+            self.0.concurrent_downloads = concurrency as usize;
+        }
+        self
+    }
+
+    /// Convenience function to hide the progress bars.
+    pub fn hidden() -> Self {
+        let d = DownloaderBuilder::default();
+        d.style_options(StyleOptions::new(
+            ProgressBarOpts::hidden(),
+            ProgressBarOpts::hidden(),
+        ))
+    }
+
+    /// Creates a builder with the options of a configuration file.
+    ///
+    /// The options missing from the configuration keep their defaults, and
+    /// can still be set with the other methods of the builder.
+    ///
+    /// This method is only available with the `serde` feature.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a header is invalid.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use trauma::{config::DownloaderConfig, downloader::DownloaderBuilder};
+    ///
+    /// # fn main() -> Result<(), Report> {
+    /// let config: DownloaderConfig = serde_json::from_str(
+    ///     r#"{"directory": "downloads", "retries": 5, "headers": {"user-agent": "my-tool/1.0"}}"#,
+    /// )?;
+    /// let downloader = DownloaderBuilder::from_config(config)?.build();
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn from_config(config: DownloaderConfig) -> Result<Self, Error> {
+        let mut builder = DownloaderBuilder::new();
+        if let Some(directory) = config.directory {
+            builder = builder.directory(directory);
+        }
+        if let Some(retries) = config.retries {
+            builder = builder.retries(retries);
+        }
+        if let Some(concurrent_downloads) = config.concurrent_downloads {
+            builder = builder.concurrent_downloads(concurrent_downloads);
+        }
+        if let Some(secs) = config.timeout_secs {
+            builder = builder.timeout(Duration::from_secs(secs));
+        }
+        if let Some(secs) = config.per_download_timeout_secs {
+            builder = builder.per_download_timeout(Duration::from_secs(secs));
+        }
+        if let Some(secs) = config.read_idle_timeout_secs {
+            builder = builder.read_idle_timeout(Duration::from_secs(secs));
+        }
+        if let Some(resumable) = config.resumable {
+            builder = builder.resumable(resumable);
+        }
+        if let Some(use_temp_file) = config.use_temp_file {
+            builder = builder.use_temp_file(use_temp_file);
+        }
+        if let Some(temp_dir) = config.temp_dir {
+            builder = builder.temp_dir(temp_dir);
+        }
+        if let Some(max_redirects) = config.max_redirects {
+            builder = builder.max_redirects(max_redirects);
+        }
+        if let Some(bytes_per_second) = config.max_bytes_per_second {
+            builder = builder.max_bytes_per_second(bytes_per_second);
+        }
+        if let Some(respect) = config.respect_content_disposition {
+            builder = builder.respect_content_disposition(respect);
+        }
+        let mut headers = HeaderMap::new();
+        for (name, value) in &config.headers {
+            let name = reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| Error::Internal(format!("invalid header {:?}: {}", name, e)))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|e| Error::Internal(format!("invalid value of {}: {}", name, e)))?;
+            headers.insert(name, value);
+        }
+        if !headers.is_empty() {
+            builder = builder.headers(headers);
+        }
+        Ok(builder)
+    }
+
+    /// Sets the directory where to store the [`Download`]s.
+    pub fn directory(mut self, directory: PathBuf) -> Self {
+        self.0.directory = directory;
+        self
+    }
+
+    /// Set the number of retries per [`Download`].
+    ///
+    /// The failed requests are retried with an exponential backoff, unless a
+    /// [`DownloaderBuilder::retry_policy`] is set.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.0.retries = retries;
+        self
+    }
+
+    /// Decide whether and when to retry the failed requests with a policy of
+    /// `reqwest_retry`, instead of the exponential backoff built from
+    /// [`DownloaderBuilder::retries`].
+    ///
+    /// The policy only sees the number of retries so far, not the responses:
+    /// the `Retry-After` header is not honored, the delay being only chosen by
+    /// the policy. The status codes retried are still chosen by
+    /// [`DownloaderBuilder::no_retry_on`], and the retries are still drawn
+    /// from the [`DownloaderBuilder::global_retry_budget`], if any.
+    ///
+    /// The policy only retries the failed requests. It does not control:
+    /// - the requests resuming a stalled download, bounded by
+    ///   [`DownloaderBuilder::retries`] (see
+    ///   [`DownloaderBuilder::read_idle_timeout`]),
+    /// - the single pass downloading the corrupted pieces again, after
+    ///   verifying [`Download::with_piece_hashes`],
+    /// - the chunk writes, retried by [`DownloaderBuilder::write_retries`],
+    /// - the fallback to the mirrors of [`Download::with_mirrors`].
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// use reqwest_retry::{policies::ExponentialBackoff, Jitter};
+    /// use std::time::Duration;
+    /// use trauma::downloader::DownloaderBuilder;
+    ///
+    /// let policy = ExponentialBackoff::builder()
+    ///     .retry_bounds(Duration::from_millis(500), Duration::from_secs(30))
+    ///     .jitter(Jitter::Full)
+    ///     .build_with_max_retries(8);
+    /// let d = DownloaderBuilder::new().retry_policy(policy).build();
+    /// ```
+    pub fn retry_policy(mut self, policy: impl RetryPolicy + Send + Sync + 'static) -> Self {
+        self.0.retry_policy = Some(Hook(Arc::new(policy)));
+        self
+    }
+
+    /// Resume the partial [`Download`]s if the servers support it. Defaults
+    /// to `true`.
+    ///
+    /// It can be overridden per download with [`Download::with_resumable`].
+    pub fn resumable(mut self, resumable: bool) -> Self {
+        self.0.resumable = resumable;
+        self
+    }
+
+    /// Retry the requests using a non-idempotent method, like `POST`.
+    ///
+    /// By default, only the requests using an idempotent method (`GET`,
+    /// `HEAD`, `PUT`, `DELETE`, etc.) are retried, since retrying a `POST`
+    /// could submit it twice.
+    pub fn retry_non_idempotent(mut self, retry_non_idempotent: bool) -> Self {
+        self.0.retry_non_idempotent = retry_non_idempotent;
+        self
+    }
+
+    /// Never retry the responses with the listed status codes.
+    ///
+    /// The other transient failures, like server errors, are still retried.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// use reqwest::StatusCode;
+    /// use trauma::downloader::DownloaderBuilder;
+    ///
+    /// let d = DownloaderBuilder::new()
+    ///     .no_retry_on(&[StatusCode::SERVICE_UNAVAILABLE])
+    ///     .build();
+    /// ```
+    pub fn no_retry_on(mut self, status_codes: &[StatusCode]) -> Self {
+        self.0.no_retry_on = status_codes.to_vec();
+        self
+    }
+
+    /// Fail a download when no data is received for the given duration.
+    ///
+    /// Unlike an overall timeout, it does not interrupt long downloads which
+    /// are progressing. If the server supports range requests, the stalled
+    /// download is first resumed with a new request, up to the number of
+    /// [`DownloaderBuilder::retries`]. The partial file is kept on disk, so the
+    /// download can be resumed.
+    pub fn read_idle_timeout(mut self, read_idle_timeout: Duration) -> Self {
+        self.0.read_idle_timeout = Some(read_idle_timeout);
+        self
+    }
+
+    /// Set the maximum number of redirects to follow. Defaults to 10.
+    ///
+    /// With `0`, the downloads being redirected fail.
+    pub fn max_redirects(mut self, max_redirects: usize) -> Self {
+        self.0.max_redirects = Some(max_redirects);
+        self
+    }
+
+    /// Fail the requests taking longer than the given duration, from
+    /// connecting until their body is fully received.
+    ///
+    /// A failed request is retried like the other transient failures.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.0.timeout = Some(timeout);
+        self
+    }
+
+    /// Fail the downloads taking longer than the given duration, retries
+    /// included, and discard the bytes they wrote.
+    ///
+    /// The partial file a download was resumed from is kept as it was, so
+    /// the next attempt resumes from it again.
+    ///
+    /// Unlike [`DownloaderBuilder::read_idle_timeout`], it also interrupts the
+    /// downloads which are progressing.
+    pub fn per_download_timeout(mut self, per_download_timeout: Duration) -> Self {
+        self.0.per_download_timeout = Some(per_download_timeout);
+        self
+    }
+
+    /// Cap the total number of retries of a batch.
+    ///
+    /// When many downloads fail at once, they stop retrying once the budget is
+    /// exhausted, to avoid hammering a struggling server. Every batch, like
+    /// every call to [`Downloader::download_bytes`], gets a budget of its own.
+    pub fn global_retry_budget(mut self, global_retry_budget: usize) -> Self {
+        self.0.global_retry_budget = Some(global_retry_budget);
+        self
+    }
+
+    /// Allow downloading to existing special files, like FIFOs or character
+    /// devices.
+    ///
+    /// The data is streamed into them, without resuming nor verifying the
+    /// size. Otherwise, such downloads fail. Defaults to `false`.
+    pub fn allow_special_files(mut self, allow_special_files: bool) -> Self {
+        self.0.allow_special_files = allow_special_files;
+        self
+    }
+
+    /// Write through the symbolic links found within the download directory
+    /// and the temporary directory.
+    ///
+    /// When disabled, the downloads whose output or partial file, or one of
+    /// their parent directories below these directories, is a symbolic link
+    /// fail instead, so file names like `link/file` cannot escape the
+    /// directories. Defaults to `true`.
+    ///
+    /// The paths are checked before the files are opened, which does not
+    /// protect against the links created in the meantime.
+    pub fn follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.0.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Keep the headers of the responses, available from
+    /// [`Summary::headers`].
+    ///
+    /// They are not kept by default, to save memory on large batches.
+    pub fn capture_headers(mut self, capture_headers: bool) -> Self {
+        self.0.capture_headers = capture_headers;
+        self
+    }
+
+    /// Stream the downloads to a [`Destination`], as an opt-in alternative to
+    /// the local files.
+    ///
+    /// The content is streamed to the objects of the destination, named after
+    /// the file names of the downloads. Since the content is not on disk, the
+    /// downloads are never resumed, and the options handling the local files,
+    /// like [`DownloaderBuilder::temp_dir`],
+    /// [`DownloaderBuilder::content_addressed`],
+    /// [`DownloaderBuilder::verify_size`] or
+    /// [`DownloaderBuilder::sync_on_complete`], do not apply. The checksums
+    /// are still verified, and [`DownloaderBuilder::post_download`] receives
+    /// the name of the object.
+    ///
+    /// Without a destination, which is the default, the downloader writes the
+    /// local files itself, which allows to resume them.
+    pub fn destination(mut self, destination: impl Destination + 'static) -> Self {
+        self.0.destination = Some(Hook(Arc::new(destination)));
+        self
+    }
+
+    /// Report the downloads excluded by [`Downloader::download_filtered`] as
+    /// skipped.
+    ///
+    /// Set to `false` to omit them from the summaries instead. Defaults to
+    /// `true`.
+    pub fn include_filtered(mut self, include_filtered: bool) -> Self {
+        self.0.include_filtered = include_filtered;
+        self
+    }
+
+    /// Set the policy applied to the downloads of a batch targeting the same
+    /// file.
+    pub fn collision_policy(mut self, collision_policy: CollisionPolicy) -> Self {
+        self.0.collision_policy = collision_policy;
+        self
+    }
+
+    /// Set a hook called after each successful download, with its summary and
+    /// the path of the file.
+    ///
+    /// Returning a path moves the file there, and records it in the
+    /// [`Summary`]. A relative path is resolved from the destination
+    /// directory. Returning an error fails the download.
+    ///
+    /// # Example
+    ///
+    /// Store the files by their checksum:
+    ///
+    /// ```
+    /// use trauma::{downloader::DownloaderBuilder, hash::Sha256Hasher};
+    ///
+    /// let d = DownloaderBuilder::new()
+    ///     .hasher(|| Box::new(Sha256Hasher::default()))
+    ///     .post_download(|summary, _path| {
+    ///         Ok(summary.digest().map(|digest| format!("cas/{digest}").into()))
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn post_download(
+        mut self,
+        post_download: impl Fn(&Summary, &Path) -> Result<Option<PathBuf>, Error>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.0.post_download = Some(Hook(Arc::new(post_download)));
+        self
+    }
+
+    /// Set a hook deciding whether the file already on disk is fresh, in which
+    /// case the download is skipped without sending any request.
+    ///
+    /// The hook receives the download and the path of its destination.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use trauma::downloader::DownloaderBuilder;
+    ///
+    /// // Consider the files modified within the last hour as fresh.
+    /// let d = DownloaderBuilder::new()
+    ///     .freshness_check(|_download, path| {
+    ///         path.metadata()
+    ///             .and_then(|m| m.modified())
+    ///             .map(|t| t.elapsed().unwrap_or_default() < Duration::from_secs(3600))
+    ///             .unwrap_or(false)
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn freshness_check(
+        mut self,
+        freshness_check: impl Fn(&Download, &Path) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.0.freshness_check = Some(Hook(Arc::new(freshness_check)));
+        self
+    }
+
+    /// Set a hook choosing the path to use instead when the destination of a
+    /// download already exists on disk.
+    ///
+    /// It is only called if the [`CollisionPolicy`] is not
+    /// [`CollisionPolicy::Overwrite`]. A relative path is resolved from the
+    /// destination directory.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use trauma::downloader::{CollisionPolicy, DownloaderBuilder};
+    ///
+    /// let d = DownloaderBuilder::new()
+    ///     .collision_policy(CollisionPolicy::Rename)
+    ///     .on_filename_collision(|path| path.with_extension("new"))
+    ///     .build();
+    /// ```
+    pub fn on_filename_collision(
+        mut self,
+        on_filename_collision: impl Fn(&Path) -> PathBuf + Send + Sync + 'static,
+    ) -> Self {
+        self.0.on_filename_collision = Some(Hook(Arc::new(on_filename_collision)));
+        self
+    }
+
+    /// Treat the file names differing only by case, like `File.txt` and
+    /// `file.txt`, as colliding.
+    ///
+    /// By default, it is detected from the destination directory, since they
+    /// collide on case-insensitive file systems like the ones of macOS or
+    /// Windows.
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.0.case_insensitive = Some(case_insensitive);
+        self
+    }
+
+    /// Set the `Referer` header sent with every request.
+    ///
+    /// Use [`Download::referer`] to set it for a single download instead.
+    ///
+    /// ## Errors
+    ///
+    /// Fails with [`Error::InvalidUrl`] if `referer` cannot be parsed.
+    pub fn referer(self, referer: &str) -> Result<Self, Error> {
+        Ok(self.header(REFERER, referer_value(referer)?))
+    }
+
+    /// Set the number of retries when writing a chunk to disk fails.
+    ///
+    /// Network errors are retried by the HTTP client, but a transient I/O
+    /// error while writing (e.g. on a network file system) would otherwise
+    /// fail the whole [`Download`]. The delay between the attempts doubles
+    /// from 100 ms, up to 10 s.
+    pub fn write_retries(mut self, write_retries: u32) -> Self {
+        self.0.write_retries = write_retries;
+        self
+    }
+
+    /// Record the number of bytes written to each partial file, to verify it
+    /// before resuming.
+    ///
+    /// The count is written to a `<partial>.state` file every MiB, once the
+    /// bytes were flushed, and removed once the download completes. When
+    /// resuming, the bytes written after the recorded ones are discarded,
+    /// since they may not have reached the file. A partial file shorter than
+    /// recorded, e.g. because another process truncated it, is downloaded
+    /// again from scratch. So is a partial file without state.
+    pub fn resume_state(mut self, resume_state: bool) -> Self {
+        self.0.resume_state = resume_state;
+        self
+    }
+
+    /// Compare the last `resume_overlap` bytes of each partial file with the
+    /// remote ones before resuming.
+    ///
+    /// They are requested again, and the download restarts from scratch if
+    /// they differ, e.g. because the remote file was replaced. This is cheaper
+    /// than hashing the whole partial file, and works with the servers which
+    /// do not send ETags. Defaults to `0`, which trusts the length of the
+    /// partial files.
+    pub fn resume_overlap(mut self, resume_overlap: u64) -> Self {
+        self.0.resume_overlap = resume_overlap;
+        self
+    }
+
+    /// Attempt to resume the [`Download`]s from these hosts, even if they do
+    /// not advertise their support of range requests with an `Accept-Ranges`
+    /// header.
+    ///
+    /// The ranged requests are validated by the response code: if a server
+    /// sends the whole file instead of a `206 Partial Content` response, the
+    /// download restarts from scratch.
+    pub fn assume_ranges_for_hosts(mut self, hosts: Vec<String>) -> Self {
+        self.0.range_hosts = hosts;
+        self
+    }
+
+    /// Sets the directory where to write the partial files.
+    ///
+    /// The [`Download`]s are written as `<filename>.part` in this directory,
+    /// then moved to the final directory once complete. This is useful when
+    /// the final directory is on a slow network mount while fast local
+    /// storage is available.
+    ///
+    /// If both directories are not on the same file system, the file is
+    /// copied then removed instead of being renamed, meaning the final file
+    /// does not appear atomically.
+    pub fn temp_dir(mut self, temp_dir: PathBuf) -> Self {
+        self.0.temp_dir = Some(temp_dir);
+        self
+    }
+
+    /// Sets a salt distinguishing the partial files of this downloader.
+    ///
+    /// The [`Download`]s are written as `<filename>.<salt>.part`, in the
+    /// temporary directory if any, or in the final directory otherwise, then
+    /// moved once complete. This prevents several processes downloading to a
+    /// shared directory, e.g. a cluster of workers using an NFS mount, from
+    /// writing to the same partial files.
+    ///
+    /// Only the partial files with the same salt are resumed, so a salt
+    /// should be stable across the restarts of a worker, e.g. its name.
+    pub fn temp_salt(mut self, salt: String) -> Self {
+        self.0.temp_salt = Some(salt);
+        self
+    }
+
+    /// Write the downloads to a `<filename>.part` file, only moved in place
+    /// once complete.
+    ///
+    /// This way, an interrupted download never leaves a truncated file at its
+    /// final path. The temporary file of a failed download is removed, unless
+    /// the download can be resumed from it. Defaults to `true`.
+    ///
+    /// A file already in place is skipped if it has the size of the remote
+    /// one, whether the server supports range requests or not.
+    ///
+    /// Set to `false` to write the downloads in place, unless
+    /// [`DownloaderBuilder::temp_dir`] or [`DownloaderBuilder::temp_salt`]
+    /// is set.
+    pub fn use_temp_file(mut self, use_temp_file: bool) -> Self {
+        self.0.use_temp_file = use_temp_file;
+        self
+    }
+
+    /// Verify the size of the files once downloaded.
+    ///
+    /// The file is re-read from the disk and its size is compared with the
+    /// number of bytes received. The download fails if they differ.
+    pub fn verify_size(mut self, verify_size: bool) -> Self {
+        self.0.verify_size = verify_size;
+        self
+    }
+
+    /// Request gzip-compressed responses, and decode them transparently.
+    ///
+    /// This saves bandwidth for compressible files. Since the size of a
+    /// decoded file is unknown until it is fully received, the progress bars
+    /// track the decoded bytes written without a total, and the downloads are
+    /// never resumed. The responses which are not compressed are handled as
+    /// usual.
+    ///
+    /// This method is only available with the `gzip` feature.
+    #[cfg(feature = "gzip")]
+    pub fn decompress(mut self, decompress: bool) -> Self {
+        self.0.decompress = decompress;
+        self
+    }
+
+    /// Decode the files stored compressed, detecting their compression from
+    /// their extension: `.gz`, `.zst`, `.bz2` or `.xz`.
+    ///
+    /// The files are saved without the extension, e.g. `data.json.zst` is
+    /// saved as `data.json`. Like with [`DownloaderBuilder::decompress`], the
+    /// progress bars track the decoded bytes without a total, and the
+    /// downloads are never resumed.
+    ///
+    /// This is unrelated to the HTTP content encoding: the files are
+    /// compressed on the server.
+    ///
+    /// This method is only available with the `auto-decompress` feature.
+    #[cfg(feature = "auto-decompress")]
+    pub fn auto_decompress(mut self, auto_decompress: bool) -> Self {
+        self.0.auto_decompress = auto_decompress;
+        self
+    }
+
+    /// Only update the progress after receiving at least `bytes` since the
+    /// previous update.
+    ///
+    /// By default, the progress is updated on every chunk, which is wasteful
+    /// for high-throughput downloads made of many small chunks.
+    pub fn progress_granularity(mut self, bytes: u64) -> Self {
+        self.0.progress_granularity = bytes;
+        self
+    }
+
+    /// Set a callback receiving the progress of each [`Download`]: the bytes
+    /// downloaded so far, including the resumed ones, and the total size if
+    /// known.
+    ///
+    /// It is called at the pace of the progress bars, and independently of
+    /// them, which allows to display the progress in a GUI while the progress
+    /// bars are hidden. It is called from the tasks downloading the files, so
+    /// it should return quickly.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use trauma::downloader::DownloaderBuilder;
+    ///
+    /// let d = DownloaderBuilder::hidden()
+    ///     .on_progress(|download, bytes, total| {
+    ///         println!("{}: {}/{:?}", download.filename, bytes, total);
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn on_progress(
+        mut self,
+        on_progress: impl Fn(&Download, u64, Option<u64>) + Send + Sync + 'static,
+    ) -> Self {
+        self.0.on_progress = Some(Hook(Arc::new(on_progress)));
+        self
+    }
+
+    /// Write the progress of the batches to a log, at every `interval`.
+    ///
+    /// Each line reports the completed downloads, the bytes received so far
+    /// and the throughput over the last interval, the timestamp being in
+    /// seconds since the Unix epoch:
+    ///
+    /// ```text
+    /// [1700000000] 12/50 files, 3.40 GiB, 45.00 MiB/s
+    /// ```
+    ///
+    /// A last line with the average throughput is written once the batch
+    /// ends. This is meant for the non-interactive environments, e.g. CI
+    /// runs, where the progress bars are better hidden.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use std::{fs::File, time::Duration};
+    /// use trauma::downloader::DownloaderBuilder;
+    ///
+    /// # fn main() -> Result<(), Report> {
+    /// let log = File::create("progress.log")?;
+    /// let d = DownloaderBuilder::hidden()
+    ///     .progress_log(log, Duration::from_secs(10))
+    ///     .build();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn progress_log(mut self, log: impl Write + Send + 'static, interval: Duration) -> Self {
+        let log: Arc<LogWriter> = Arc::new(Mutex::new(log));
+        self.0.progress_log = Some((Hook(log), interval));
+        self
+    }
+
+    /// Write the lifecycle events of the [`Download`]s to `writer` as JSON
+    /// lines, like `{"file":"x.zip","event":"progress","bytes":123,"total":456}`.
+    ///
+    /// The events are `started`, `progress` at the pace of the progress bars,
+    /// then `completed`, `failed` with an `error`, or `skipped` with a
+    /// `reason`. The `bytes` include the resumed ones, and the `total` is
+    /// `null` if unknown. The write errors are ignored.
+    ///
+    /// This method is only available with the `serde` feature.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use color_eyre::{eyre::Report, Result};
+    /// use tokio::fs::File;
+    /// use trauma::downloader::DownloaderBuilder;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Report> {
+    /// let events = File::create("events.jsonl").await?;
+    /// let d = DownloaderBuilder::hidden().jsonl_events(events).build();
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn jsonl_events(mut self, writer: impl AsyncWrite + Send + 'static) -> Self {
+        let writer: Arc<EventWriter> = Arc::new(tokio::sync::Mutex::new(Box::pin(writer)));
+        self.0.jsonl_events = Some(Hook(writer));
+        self
+    }
+
+    /// Buffer up to `capacity` summaries in the streaming methods, like
+    /// [`Downloader::download_ordered_stream`], until they are consumed.
+    ///
+    /// Once the buffer is full, the downloads wait for the stream to be
+    /// polled: a small buffer slows the downloads down to the pace of the
+    /// consumer, a large one decouples them at the cost of memory. Defaults to
+    /// 32, and is at least 1.
+    pub fn channel_capacity(mut self, capacity: usize) -> Self {
+        self.0.channel_capacity = capacity;
+        self
+    }
+
+    /// Flush the files to the storage device once downloaded.
+    ///
+    /// The data of each file, then the directory entry it was moved to, are
+    /// synced before the download is reported as successful, so it survives a
+    /// power loss, at the cost of slower downloads. Otherwise, it may only be
+    /// in the operating system cache. The directories are not synced on
+    /// Windows.
+    pub fn sync_on_complete(mut self, sync_on_complete: bool) -> Self {
+        self.0.sync_on_complete = sync_on_complete;
+        self
+    }
+
+    /// Limit the number of requests sent to each host over a period of time.
+    ///
+    /// This limits the rate of the requests independently of the number of
+    /// concurrent downloads, which helps staying within documented API limits.
+    /// All the requests are counted, including the ones checking whether a
+    /// download can be resumed, and the retries.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use trauma::downloader::DownloaderBuilder;
+    ///
+    /// // Send at most 10 requests per second to each host.
+    /// let d = DownloaderBuilder::new()
+    ///     .rate_limit_per_host(10, Duration::from_secs(1))
+    ///     .build();
+    /// ```
+    pub fn rate_limit_per_host(mut self, requests: u32, per: Duration) -> Self {
+        self.0.rate_limit_per_host = Some((requests, per));
+        self
+    }
+
+    /// Cap the aggregate throughput of all the concurrent [`Download`]s, in
+    /// bytes per second.
+    ///
+    /// The limit is best-effort, and smooth: each chunk waits for its share
+    /// of the bandwidth before being written. It is shared by the batches
+    /// running concurrently with this downloader. A value of 0 means
+    /// unlimited, which is the default.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use trauma::downloader::DownloaderBuilder;
+    ///
+    /// // Leave some room on a 100 Mbit/s link.
+    /// let d = DownloaderBuilder::new()
+    ///     .max_bytes_per_second(8 * 1024 * 1024)
+    ///     .build();
+    /// ```
+    pub fn max_bytes_per_second(mut self, bytes_per_second: u64) -> Self {
+        self.0.throttle = (bytes_per_second > 0).then(|| Arc::new(Throttle::new(bytes_per_second)));
+        self
+    }
+
+    /// Authenticate the downloads using the credentials of a netrc file.
+    ///
+    /// Like curl's `--netrc`, the file pointed by the `NETRC` environment
+    /// variable, or `~/.netrc` by default, is read when this method is called.
+    /// The credentials of the machine matching the host of each download are
+    /// sent using basic authentication, unless an `Authorization` header is
+    /// set explicitly. A missing file is ignored.
+    pub fn use_netrc(mut self, use_netrc: bool) -> Self {
+        self.0.netrc = match use_netrc {
+            true => Netrc::from_env(),
+            false => None,
+        };
+        self
+    }
+
+    /// Fail fast the downloads from a host after repeated failures.
+    ///
+    /// Once a host has failed `failures` consecutive times within `window`,
+    /// the remaining downloads from it fail without sending any request,
+    /// until the window elapses. The downloads from other hosts continue
+    /// normally.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use trauma::downloader::DownloaderBuilder;
+    ///
+    /// // Give up on a host after 5 failures within a minute.
+    /// let d = DownloaderBuilder::new()
+    ///     .circuit_breaker(5, Duration::from_secs(60))
+    ///     .build();
+    /// ```
+    pub fn circuit_breaker(mut self, failures: u32, window: Duration) -> Self {
+        self.0.circuit_breaker = Some((failures, window));
+        self
+    }
+
+    /// Set a hook to inspect or modify each request before it is sent.
+    ///
+    /// This is a lightweight alternative to writing a middleware, e.g. to add
+    /// a per-request nonce or signature header. The hook receives the request
+    /// builder and returns the one to send.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use trauma::downloader::DownloaderBuilder;
+    ///
+    /// let d = DownloaderBuilder::new()
+    ///     .inspect_request(|req| req.header("x-nonce", "42"))
+    ///     .build();
+    /// ```
+    pub fn inspect_request(
+        mut self,
+        inspect_request: impl Fn(RequestBuilder) -> RequestBuilder + Send + Sync + 'static,
+    ) -> Self {
+        self.0.inspect_request = Some(Hook(Arc::new(inspect_request)));
+        self
+    }
+
+    /// Only download the files with one of these extensions, e.g. `zip`,
+    /// case insensitively.
+    ///
+    /// Only the last extension is considered, e.g. `gz` for `archive.tar.gz`.
+    /// The extension is taken from the file name suggested by the
+    /// `Content-Disposition` header if any, or from the [`Download`] file
+    /// name otherwise. The other downloads are marked as skipped before any
+    /// byte is written.
+    pub fn allow_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.0.allow_extensions = Some(extensions);
+        self
+    }
+
+    /// Skip the files with one of these extensions, case insensitively.
+    ///
+    /// The extension is determined like for
+    /// [`DownloaderBuilder::allow_extensions`], and the denied extensions take
+    /// precedence over the allowed ones.
+    pub fn deny_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.0.deny_extensions = extensions;
+        self
+    }
+
+    /// Name the files as suggested by the `Content-Disposition` header of the
+    /// responses, e.g. `attachment; filename="report.pdf"`.
+    ///
+    /// This is useful for the opaque URLs, like `/download?id=1234`. Both the
+    /// `filename` and the RFC 5987 `filename*` parameters are supported, the
+    /// latter being preferred, and their directories are stripped. The name
+    /// derived from the URL is kept if the header is absent or malformed.
+    /// Defaults to `false`.
+    pub fn respect_content_disposition(mut self, respect_content_disposition: bool) -> Self {
+        self.0.respect_content_disposition = respect_content_disposition;
+        self
+    }
+
+    /// Skip the responses whose `Content-Disposition` is `inline`.
+    ///
+    /// Inline content is meant to be displayed rather than saved, which is
+    /// useful to filter out when crawling mixed endpoints. These downloads are
+    /// marked as skipped.
+    pub fn attachment_only(mut self, attachment_only: bool) -> Self {
+        self.0.attachment_only = attachment_only;
+        self
+    }
+
+    /// Stagger the start of the first [`Download`]s over a period of time.
+    ///
+    /// Instead of starting all the concurrent downloads at once, their starts
+    /// are spread evenly over the ramp-up period, smoothing the initial load
+    /// on the servers and the local resources.
+    pub fn ramp_up(mut self, ramp_up: Duration) -> Self {
+        self.0.ramp_up = Some(ramp_up);
+        self
+    }
+
+    /// Process the large batches in waves of `wave_size` downloads.
+    ///
+    /// Each wave is fully downloaded, including the `HEAD` requests checking
+    /// whether the files can be resumed, before the next one starts. This
+    /// bounds the resources used by very large batches. The summaries are
+    /// still returned together, in the order of the downloads, once all the
+    /// waves are done.
+    pub fn wave_size(mut self, wave_size: usize) -> Self {
+        self.0.wave_size = Some(wave_size);
+        self
+    }
+
+    /// Name the downloaded files after their digest, to build a
+    /// content-addressed store.
+    ///
+    /// The files are hashed while they are downloaded to their partial file,
+    /// which is then moved to a path named after the digest within the
+    /// download directory. [`Summary::path`] reports this final path, and
+    /// [`Summary::digest`] the digest. This takes precedence over
+    /// [`DownloaderBuilder::hasher`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use trauma::{downloader::DownloaderBuilder, hash::ChecksumAlgorithm};
+    ///
+    /// // Write the files to `cas/ab/cdef…`.
+    /// let d = DownloaderBuilder::new()
+    ///     .directory("cas".into())
+    ///     .content_addressed(ChecksumAlgorithm::Sha256)
+    ///     .content_prefix_len(2)
+    ///     .build();
+    /// ```
+    pub fn content_addressed(mut self, algorithm: ChecksumAlgorithm) -> Self {
+        self.0.content_addressed = Some(algorithm);
+        self
+    }
+
+    /// Use the first `prefix_len` characters of the digests as subdirectory
+    /// in content-addressed mode.
+    ///
+    /// Defaults to 0, i.e. all the files are written in the download
+    /// directory.
+    pub fn content_prefix_len(mut self, prefix_len: usize) -> Self {
+        self.0.content_prefix_len = prefix_len;
+        self
+    }
+
+    /// Compute the digest of each [`Download`] with a custom hasher.
+    ///
+    /// The closure builds a new hasher for every download, which is fed with
+    /// the chunks as they are received. The resulting digest is available via
+    /// [`Summary::digest`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use trauma::{downloader::DownloaderBuilder, hash::Sha256Hasher};
+    ///
+    /// let d = DownloaderBuilder::new()
+    ///     .hasher(|| Box::new(Sha256Hasher::default()))
+    ///     .build();
+    /// ```
+    pub fn hasher(
+        mut self,
+        hasher: impl Fn() -> Box<dyn DynHasher> + Send + Sync + 'static,
+    ) -> Self {
+        self.0.hasher = Some(Hook(Arc::new(hasher)));
+        self
+    }
+
+    /// Set the hosts which must bypass the proxy.
+    ///
+    /// The patterns are separated by commas and follow the `NO_PROXY`
+    /// environment variable conventions, e.g. `localhost,.internal,10.0.0.0/8`.
+    /// They apply to the proxy given to [`Downloader::download_with_proxy`],
+    /// and take precedence over the `NO_PROXY` environment variable.
+    pub fn no_proxy(mut self, no_proxy: &str) -> Self {
+        self.0.no_proxy = Some(no_proxy.to_string());
+        self
+    }
+
+    /// Fail the downloads whose response headers are larger than
+    /// `max_header_size` bytes in total.
+    ///
+    /// This is a defense against malicious servers when downloading from
+    /// untrusted URLs, checked before reading the body of the responses.
+    pub fn max_header_size(mut self, max_header_size: usize) -> Self {
+        self.0.max_header_size = Some(max_header_size);
+        self
+    }
+
+    /// Resolve a domain to a specific address, bypassing the DNS.
+    ///
+    /// This is useful to reach a staging server using the production host
+    /// name, without editing `/etc/hosts`. The port of the address is ignored
+    /// in favor of the port of the URLs. It can be called several times to
+    /// override several domains.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use trauma::downloader::DownloaderBuilder;
+    ///
+    /// let d = DownloaderBuilder::new()
+    ///     .resolve("example.com", "192.0.2.10:443".parse().unwrap())
+    ///     .build();
+    /// ```
+    pub fn resolve(mut self, domain: &str, addr: SocketAddr) -> Self {
+        self.0.resolve.push((domain.to_string(), addr));
+        self
+    }
+
+    /// Set the client certificate presented to the servers requiring mutual
+    /// TLS.
+    ///
+    /// This method is only available with the `native-tls` feature.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use trauma::downloader::DownloaderBuilder;
+    ///
+    /// let der = std::fs::read("client.p12")?;
+    /// let identity = reqwest::Identity::from_pkcs12_der(&der, "password")?;
+    /// let d = DownloaderBuilder::new().identity(identity).build();
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "native-tls")]
+    pub fn identity(mut self, identity: reqwest::Identity) -> Self {
+        self.0.identity = Some(identity);
+        self
+    }
+
+    /// Only accept the servers whose public key has this SHA-256 digest,
+    /// defending against a compromised certificate authority.
+    ///
+    /// The digest is computed over the DER-encoded `SubjectPublicKeyInfo` of
+    /// the server certificate, e.g. with `openssl x509 -pubkey -noout -in
+    /// cert.pem | openssl pkey -pubin -outform DER | openssl dgst -sha256`.
+    /// Calling this method several times accepts any of the keys, which
+    /// allows to pin a backup key. The certificate chain is still validated,
+    /// against the Mozilla root certificates bundled with the crate rather
+    /// than the ones of the system, and the downloads from a server with
+    /// another key fail with a `certificate pin mismatch`.
+    ///
+    /// The TLS connections are then handled by rustls, which cannot present a
+    /// [`DownloaderBuilder::identity`]: the batches fail upfront if both are
+    /// set.
+    ///
+    /// This method is only available with the `rustls` feature.
+    #[cfg(feature = "rustls")]
+    pub fn pin_certificate(mut self, sha256_spki: Vec<u8>) -> Self {
+        self.0.certificate_pins.push(sha256_spki);
+        self
+    }
+
+    /// Set the number of concurrent [`Download`]s.
+    pub fn concurrent_downloads(mut self, concurrent_downloads: usize) -> Self {
+        self.0.concurrent_downloads = concurrent_downloads;
+        self
+    }
+
+    /// Define how the responses which cannot be decoded are handled, e.g.
+    /// when a server sends uncompressed data along with a `Content-Encoding:
+    /// gzip` header.
+    ///
+    /// By default, such downloads fail with a `content-encoding decode error`.
+    ///
+    /// This method is only available with the `gzip` feature.
+    #[cfg(feature = "gzip")]
+    pub fn on_decode_error(mut self, policy: DecodeErrorPolicy) -> Self {
+        self.0.on_decode_error = policy;
+        self
+    }
+
+    /// Adjust the number of concurrent [`Download`]s to the load of the
+    /// servers, between `min` and `max`, instead of a fixed number.
+    ///
+    /// The limit starts at `min` and increases by one on each successful
+    /// download. It is halved whenever a download fails with a `429 Too Many
+    /// Requests` or a `503 Service Unavailable` status, once its retries are
+    /// exhausted. This overrides [`DownloaderBuilder::concurrent_downloads`].
+    pub fn adaptive_concurrency(mut self, min: usize, max: usize) -> Self {
+        self.0.adaptive_concurrency = Some((min, max));
+        self.0.auto_concurrency = None;
+        self
+    }
+
+    /// Search the number of concurrent [`Download`]s maximizing the
+    /// throughput of the batch, up to `max`, instead of a fixed number.
+    ///
+    /// The limit starts at one, and the throughput is measured every 2
+    /// seconds: the limit keeps moving in the same direction while the
+    /// throughput improves by at least 5%, and turns back otherwise. It
+    /// therefore settles around the point where more connections stop
+    /// speeding the batch up. Like with
+    /// [`DownloaderBuilder::adaptive_concurrency`], the limit is halved when
+    /// the servers are overloaded. This overrides
+    /// [`DownloaderBuilder::concurrent_downloads`].
+    pub fn auto_concurrency(mut self, max: usize) -> Self {
+        self.0.auto_concurrency = Some(max);
+        self.0.adaptive_concurrency = None;
+        self
+    }
+
+    /// Cache the content downloaded in memory, up to `max_bytes`.
+    ///
+    /// [`Downloader::download_bytes`] and its variants serve the cached
+    /// content of a URL once revalidated with an `If-None-Match` request
+    /// answered with `304 Not Modified`. Only the responses carrying an
+    /// `ETag` header are cached, and the least recently used ones are evicted
+    /// when the cache is full.
+    pub fn memory_cache(mut self, max_bytes: usize) -> Self {
+        self.0.memory_cache = Some(Arc::new(MemoryCache::new(max_bytes)));
+        self
+    }
+
+    /// Set the downloader style options.
+    pub fn style_options(mut self, style_options: StyleOptions) -> Self {
+        self.0.style_options = style_options;
+        self
+    }
+
+    fn new_header(&self) -> HeaderMap {
+        match self.0.headers {
+            Some(ref h) => h.to_owned(),
+            _ => HeaderMap::new(),
+        }
+    }
+
+    /// Add the http headers.
+    ///
+    /// You need to pass in a `HeaderMap`, not a `HeaderName`.
+    /// `HeaderMap` is a set of http headers.
+    ///
+    /// You can call `.headers()` multiple times and all `HeaderMap` will be merged into a single one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use reqwest::header::{self, HeaderValue, HeaderMap};
+    /// use trauma::downloader::DownloaderBuilder;
+    ///
+    /// let ua = HeaderValue::from_str("curl/7.87").expect("Invalid UA");
+    ///
+    /// let builder = DownloaderBuilder::new()
+    ///     .headers(HeaderMap::from_iter([(header::USER_AGENT, ua)]))
+    ///     .build();
+    /// ```
+    ///
+    /// See also [`header()`].
+    ///
+    /// [`header()`]: DownloaderBuilder::header
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        let mut new = self.new_header();
         new.extend(headers);
 
-        self.0.headers = Some(new);
-        self
+        self.0.headers = Some(new);
+        self
+    }
+
+    /// Add the http header
+    ///
+    /// # Example
+    ///
+    /// You can use the `.header()` chain to add multiple headers
+    ///
+    /// ```
+    /// use reqwest::header::{self, HeaderValue};
+    /// use trauma::downloader::DownloaderBuilder;
+    ///
+    /// const FIREFOX_UA: &str =
+    /// "Mozilla/5.0 (X11; Linux x86_64; rv:109.0) Gecko/20100101 Firefox/109.0";
+    ///
+    /// let ua = HeaderValue::from_str(FIREFOX_UA).expect("Invalid UA");
+    /// let auth = HeaderValue::from_str("Basic aGk6MTIzNDU2Cg==").expect("Invalid auth");
+    ///
+    /// let builder = DownloaderBuilder::new()
+    ///     .header(header::USER_AGENT, ua)
+    ///     .header(header::AUTHORIZATION, auth)
+    ///     .build();
+    /// ```
+    ///
+    /// If you need to pass in a `HeaderMap`, instead of calling `.header()` multiple times.
+    /// See also [`headers()`].
+    ///
+    /// [`headers()`]: DownloaderBuilder::headers
+    pub fn header<K: IntoHeaderName>(mut self, name: K, value: HeaderValue) -> Self {
+        let mut new = self.new_header();
+
+        new.insert(name, value);
+
+        self.0.headers = Some(new);
+        self
+    }
+
+    /// Create the [`Downloader`] with the specified options.
+    pub fn build(self) -> Downloader {
+        Downloader {
+            directory: self.0.directory,
+            retries: self.0.retries,
+            retry_policy: self.0.retry_policy,
+            concurrent_downloads: self.0.concurrent_downloads,
+            adaptive_concurrency: self.0.adaptive_concurrency,
+            auto_concurrency: self.0.auto_concurrency,
+            memory_cache: self.0.memory_cache,
+            style_options: self.0.style_options,
+            resumable: self.0.resumable,
+            range_hosts: self.0.range_hosts,
+            headers: self.0.headers,
+            write_retries: self.0.write_retries,
+            temp_dir: self.0.temp_dir,
+            temp_salt: self.0.temp_salt,
+            use_temp_file: self.0.use_temp_file,
+            resume_state: self.0.resume_state,
+            resume_overlap: self.0.resume_overlap,
+            verify_size: self.0.verify_size,
+            progress_granularity: self.0.progress_granularity,
+            progress_log: self.0.progress_log,
+            jsonl_events: self.0.jsonl_events,
+            channel_capacity: self.0.channel_capacity,
+            sync_on_complete: self.0.sync_on_complete,
+            decompress: self.0.decompress,
+            auto_decompress: self.0.auto_decompress,
+            rate_limit_per_host: self.0.rate_limit_per_host,
+            throttle: self.0.throttle,
+            circuit_breaker: self.0.circuit_breaker,
+            netrc: self.0.netrc,
+            inspect_request: self.0.inspect_request,
+            attachment_only: self.0.attachment_only,
+            respect_content_disposition: self.0.respect_content_disposition,
+            allow_extensions: self.0.allow_extensions,
+            deny_extensions: self.0.deny_extensions,
+            ramp_up: self.0.ramp_up,
+            wave_size: self.0.wave_size,
+            hasher: self.0.hasher,
+            content_addressed: self.0.content_addressed,
+            content_prefix_len: self.0.content_prefix_len,
+            no_proxy: self.0.no_proxy,
+            resolve: self.0.resolve,
+            max_header_size: self.0.max_header_size,
+            #[cfg(feature = "native-tls")]
+            identity: self.0.identity,
+            #[cfg(feature = "rustls")]
+            certificate_pins: self.0.certificate_pins,
+            retry_non_idempotent: self.0.retry_non_idempotent,
+            no_retry_on: self.0.no_retry_on,
+            read_idle_timeout: self.0.read_idle_timeout,
+            max_redirects: self.0.max_redirects,
+            allow_special_files: self.0.allow_special_files,
+            follow_symlinks: self.0.follow_symlinks,
+            capture_headers: self.0.capture_headers,
+            on_decode_error: self.0.on_decode_error,
+            destination: self.0.destination,
+            timeout: self.0.timeout,
+            per_download_timeout: self.0.per_download_timeout,
+            global_retry_budget: self.0.global_retry_budget,
+            include_filtered: self.0.include_filtered,
+            collision_policy: self.0.collision_policy,
+            freshness_check: self.0.freshness_check,
+            on_filename_collision: self.0.on_filename_collision,
+            on_progress: self.0.on_progress,
+            post_download: self.0.post_download,
+            case_insensitive: self.0.case_insensitive,
+            client: OnceLock::new(),
+            progress: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl Default for DownloaderBuilder {
+    fn default() -> Self {
+        DownloaderBuilder::without_env().env_defaults(|name| std::env::var(name).ok())
+    }
+}
+
+impl DownloaderBuilder {
+    /// Creates a builder with the built-in defaults, ignoring the environment
+    /// variables.
+    fn without_env() -> Self {
+        Self(Downloader {
+            directory: std::env::current_dir().unwrap_or_default(),
+            retries: Downloader::DEFAULT_RETRIES,
+            retry_policy: None,
+            concurrent_downloads: Downloader::DEFAULT_CONCURRENT_DOWNLOADS,
+            adaptive_concurrency: None,
+            auto_concurrency: None,
+            memory_cache: None,
+            style_options: StyleOptions::default(),
+            resumable: true,
+            range_hosts: Vec::new(),
+            headers: None,
+            write_retries: Downloader::DEFAULT_WRITE_RETRIES,
+            temp_dir: None,
+            temp_salt: None,
+            use_temp_file: true,
+            resume_state: false,
+            resume_overlap: 0,
+            verify_size: false,
+            progress_granularity: 0,
+            progress_log: None,
+            jsonl_events: None,
+            channel_capacity: 32,
+            sync_on_complete: false,
+            decompress: false,
+            auto_decompress: false,
+            rate_limit_per_host: None,
+            throttle: None,
+            circuit_breaker: None,
+            netrc: None,
+            inspect_request: None,
+            attachment_only: false,
+            respect_content_disposition: false,
+            allow_extensions: None,
+            deny_extensions: Vec::new(),
+            ramp_up: None,
+            wave_size: None,
+            hasher: None,
+            content_addressed: None,
+            content_prefix_len: 0,
+            no_proxy: None,
+            resolve: Vec::new(),
+            max_header_size: None,
+            #[cfg(feature = "native-tls")]
+            identity: None,
+            #[cfg(feature = "rustls")]
+            certificate_pins: Vec::new(),
+            retry_non_idempotent: false,
+            no_retry_on: Vec::new(),
+            read_idle_timeout: None,
+            max_redirects: None,
+            allow_special_files: false,
+            follow_symlinks: true,
+            capture_headers: false,
+            on_decode_error: DecodeErrorPolicy::default(),
+            destination: None,
+            timeout: None,
+            per_download_timeout: None,
+            global_retry_budget: None,
+            include_filtered: true,
+            collision_policy: CollisionPolicy::default(),
+            freshness_check: None,
+            on_filename_collision: None,
+            on_progress: None,
+            post_download: None,
+            case_insensitive: None,
+            client: OnceLock::new(),
+            progress: Arc::new(Mutex::new(Vec::new())),
+        })
+    }
+}
+
+/// Define the [`Downloader`] options.
+///
+/// By default, the main progress bar will stay on the screen upon completion,
+/// but the child ones will be cleared once complete.
+#[derive(Debug, Clone)]
+pub struct StyleOptions {
+    /// Style options for the main progress bar.
+    main: ProgressBarOpts,
+    /// Style options for the child progress bar(s).
+    child: ProgressBarOpts,
+    /// Unit tracked by the main progress bar.
+    main_progress: MainProgress,
+}
+
+/// Define how the downloads of a batch targeting the same file are handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// Let the downloads overwrite each other.
+    #[default]
+    Overwrite,
+    /// Only keep the first download, and skip the others.
+    Skip,
+    /// Rename the downloads after the first one by appending a number to their
+    /// file name: `file (1).txt`.
+    Rename,
+}
+
+/// Define how the responses which cannot be decoded are handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DecodeErrorPolicy {
+    /// Fail the download.
+    #[default]
+    Fail,
+    /// Download the file again, without decoding it.
+    FallBackToRaw,
+}
+
+/// Define what the main progress bar tracks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MainProgress {
+    /// Track the number of finished downloads, whatever their outcome.
+    #[default]
+    Files,
+    /// Track the number of bytes received by all the downloads.
+    ///
+    /// The length of the bar grows as the sizes of the downloads become known,
+    /// allowing templates like `{bytes_per_sec}` or `{eta}` to show the
+    /// throughput and the estimated time remaining for the whole batch. The
+    /// number of finished downloads is available as `{msg}`.
+    Bytes,
+}
+
+impl Default for StyleOptions {
+    fn default() -> Self {
+        Self {
+            main: ProgressBarOpts {
+                template: Some(ProgressBarOpts::TEMPLATE_BAR_WITH_POSITION.into()),
+                progress_chars: Some(ProgressBarOpts::CHARS_FINE.into()),
+                enabled: true,
+                clear: false,
+            },
+            child: ProgressBarOpts::with_pip_style(),
+            main_progress: MainProgress::default(),
+        }
+    }
+}
+
+impl StyleOptions {
+    /// Create new [`Downloader`] [`StyleOptions`].
+    pub fn new(main: ProgressBarOpts, child: ProgressBarOpts) -> Self {
+        Self {
+            main,
+            child,
+            main_progress: MainProgress::default(),
+        }
+    }
+
+    /// Create [`StyleOptions`] showing the throughput and the estimated time
+    /// remaining for the whole batch on the main progress bar.
+    ///
+    /// `━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━ 3.42 GiB/8.10 GiB 45.20 MiB/s eta 2m`
+    pub fn with_batch_eta() -> Self {
+        Self {
+            main: ProgressBarOpts {
+                template: Some(ProgressBarOpts::TEMPLATE_BATCH_ETA.into()),
+                progress_chars: Some(ProgressBarOpts::CHARS_LINE.into()),
+                enabled: true,
+                clear: false,
+            },
+            main_progress: MainProgress::Bytes,
+            ..Self::default()
+        }
+    }
+
+    /// Create compact [`StyleOptions`], showing the progress of the whole batch
+    /// on a single line, without any per-file progress bar.
+    ///
+    /// `━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━ 12/50 files, 3.42 GiB/8.10 GiB, 45.20 MiB/s`
+    pub fn compact() -> Self {
+        Self {
+            main: ProgressBarOpts {
+                template: Some(ProgressBarOpts::TEMPLATE_COMPACT.into()),
+                progress_chars: Some(ProgressBarOpts::CHARS_LINE.into()),
+                enabled: true,
+                clear: false,
+            },
+            child: ProgressBarOpts::hidden(),
+            main_progress: MainProgress::Bytes,
+        }
+    }
+
+    /// Set what the main progress bar tracks.
+    pub fn set_main_progress(&mut self, main_progress: MainProgress) {
+        self.main_progress = main_progress;
+    }
+
+    /// Set the options for the main progress bar.
+    pub fn set_main(&mut self, main: ProgressBarOpts) {
+        self.main = main;
+    }
+
+    /// Set the options for the child progress bar.
+    pub fn set_child(&mut self, child: ProgressBarOpts) {
+        self.child = child;
+    }
+
+    /// Return `false` if neither the main nor the child bar is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.main.enabled || self.child.enabled
+    }
+}
+
+/// Define the options for a progress bar.
+#[derive(Debug, Clone)]
+pub struct ProgressBarOpts {
+    /// Progress bar template string.
+    template: Option<String>,
+    /// Progression characters set.
+    ///
+    /// There must be at least 3 characters for the following states:
+    /// "filled", "current", and "to do".
+    progress_chars: Option<String>,
+    /// Enable or disable the progress bar.
+    enabled: bool,
+    /// Clear the progress bar once completed.
+    clear: bool,
+}
+
+impl Default for ProgressBarOpts {
+    fn default() -> Self {
+        Self {
+            template: None,
+            progress_chars: None,
+            enabled: true,
+            clear: true,
+        }
+    }
+}
+
+impl ProgressBarOpts {
+    /// Template representing the bar and its position.
+    ///
+    ///`███████████████████████████████████████ 11/12 (99%) eta 00:00:02`
+    pub const TEMPLATE_BAR_WITH_POSITION: &'static str =
+        "{bar:40.blue} {pos:>}/{len} ({percent}%) eta {eta_precise:.blue}";
+    /// Template which looks like the Python package installer pip.
+    ///
+    /// `━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━ 211.23 KiB/211.23 KiB 1008.31 KiB/s eta 0s`
+    pub const TEMPLATE_PIP: &'static str =
+        "{bar:40.green/black} {bytes:>11.green}/{total_bytes:<11.green} {bytes_per_sec:>13.red} eta {eta:.blue}";
+    /// Template showing the bytes received by a batch, its throughput, and its
+    /// estimated time remaining.
+    ///
+    /// It must be used with [`MainProgress::Bytes`].
+    ///
+    /// `━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━ 3.42 GiB/8.10 GiB 45.20 MiB/s eta 2m`
+    pub const TEMPLATE_BATCH_ETA: &'static str =
+        "{bar:40.blue} {bytes:>11}/{total_bytes:<11} {bytes_per_sec:>13} eta {eta:.blue}";
+    /// Template showing the downloads and the bytes completed by a batch, and
+    /// its throughput.
+    ///
+    /// It must be used with [`MainProgress::Bytes`].
+    ///
+    /// `━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━ 12/50 files, 3.42 GiB/8.10 GiB, 45.20 MiB/s`
+    pub const TEMPLATE_COMPACT: &'static str =
+        "{bar:40.blue} {msg}, {bytes}/{total_bytes}, {bytes_per_sec}";
+    /// Template showing a spinner and a message.
+    ///
+    /// `⠙ checking file.zip`
+    pub const TEMPLATE_SPINNER: &'static str = "{spinner:.blue} {msg}";
+    /// Use increasing quarter blocks as progress characters: `"█▛▌▖  "`.
+    pub const CHARS_BLOCKY: &'static str = "█▛▌▖  ";
+    /// Use fade-in blocks as progress characters: `"█▓▒░  "`.
+    pub const CHARS_FADE_IN: &'static str = "█▓▒░  ";
+    /// Use fine blocks as progress characters: `"█▉▊▋▌▍▎▏  "`.
+    pub const CHARS_FINE: &'static str = "█▉▊▋▌▍▎▏  ";
+    /// Use a line as progress characters: `"━╾─"`.
+    pub const CHARS_LINE: &'static str = "━╾╴─";
+    /// Use rough blocks as progress characters: `"█  "`.
+    pub const CHARS_ROUGH: &'static str = "█  ";
+    /// Use increasing height blocks as progress characters: `"█▇▆▅▄▃▂▁  "`.
+    pub const CHARS_VERTICAL: &'static str = "█▇▆▅▄▃▂▁  ";
+
+    /// Create a new [`ProgressBarOpts`].
+    pub fn new(
+        template: Option<String>,
+        progress_chars: Option<String>,
+        enabled: bool,
+        clear: bool,
+    ) -> Self {
+        Self {
+            template,
+            progress_chars,
+            enabled,
+            clear,
+        }
+    }
+
+    /// Create a [`ProgressStyle`] based on the provided options.
+    pub fn to_progress_style(self) -> ProgressStyle {
+        let mut style = ProgressStyle::default_bar();
+        if let Some(template) = self.template {
+            style = style.template(&template).unwrap();
+        }
+        if let Some(progress_chars) = self.progress_chars {
+            style = style.progress_chars(&progress_chars);
+        }
+        style
+    }
+
+    /// Create a [`ProgressBar`] based on the provided options.
+    pub fn to_progress_bar(self, len: u64) -> ProgressBar {
+        // Return a hidden Progress bar if we disabled it. It still tracks the
+        // progress, which can be polled.
+        if !self.enabled {
+            return ProgressBar::with_draw_target(Some(len), ProgressDrawTarget::hidden());
+        }
+
+        // Otherwise returns a ProgressBar with the style.
+        let style = self.to_progress_style();
+        ProgressBar::new(len).with_style(style)
+    }
+
+    /// Create a spinner based on the provided options, showing a message.
+    ///
+    /// The spinner is cleared once finished or dropped.
+    pub fn to_spinner(self, msg: String) -> ProgressBar {
+        // Return a hidden spinner if we disabled it.
+        if !self.enabled {
+            return ProgressBar::hidden();
+        }
+
+        let style = ProgressStyle::default_spinner()
+            .template(ProgressBarOpts::TEMPLATE_SPINNER)
+            .unwrap();
+        let spinner = ProgressBar::new_spinner()
+            .with_style(style)
+            .with_message(msg)
+            .with_finish(ProgressFinish::AndClear);
+        spinner.enable_steady_tick(Duration::from_millis(100));
+        spinner
+    }
+
+    /// Create a new [`ProgressBarOpts`] which looks like Python pip.
+    pub fn with_pip_style() -> Self {
+        Self {
+            template: Some(ProgressBarOpts::TEMPLATE_PIP.into()),
+            progress_chars: Some(ProgressBarOpts::CHARS_LINE.into()),
+            enabled: true,
+            clear: true,
+        }
+    }
+
+    /// Set to `true` to clear the progress bar upon completion.
+    pub fn set_clear(&mut self, clear: bool) {
+        self.clear = clear;
+    }
+
+    /// Create a new [`ProgressBarOpts`] which hides the progress bars.
+    pub fn hidden() -> Self {
+        Self {
+            enabled: false,
+            ..ProgressBarOpts::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_builder_defaults() {
+        let d = DownloaderBuilder::without_env().build();
+        assert_eq!(d.retries, Downloader::DEFAULT_RETRIES);
+        assert_eq!(
+            d.concurrent_downloads,
+            Downloader::DEFAULT_CONCURRENT_DOWNLOADS
+        );
+        assert_eq!(d.write_retries, Downloader::DEFAULT_WRITE_RETRIES);
+    }
+
+    #[test]
+    fn test_compact_style() {
+        let style = StyleOptions::compact();
+        assert!(!style.child.enabled);
+        assert_eq!(style.main_progress, MainProgress::Bytes);
+        // The template must be valid.
+        style.main.to_progress_style();
+    }
+
+    #[test]
+    fn test_numbered_filename() {
+        assert_eq!(numbered_filename("file.txt", 1), "file (1).txt");
+        assert_eq!(numbered_filename("dir/README", 2), "dir/README (2)");
+    }
+
+    #[test]
+    fn test_resolve_collisions() {
+        let downloads = ["https://a.com/File.txt", "https://b.com/file.txt"]
+            .map(|url| Download::try_from(url).unwrap());
+        let filenames = |d: &Downloader| {
+            d.resolve_collisions(&downloads)
+                .into_iter()
+                .map(|t| match t {
+                    Target::Unique => "unique".to_string(),
+                    Target::Renamed(filename) => filename,
+                    Target::Duplicate => "duplicate".to_string(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let d = DownloaderBuilder::new()
+            .collision_policy(CollisionPolicy::Rename)
+            .case_insensitive(true)
+            .build();
+        assert_eq!(filenames(&d), ["unique", "file (1).txt"]);
+        let d = DownloaderBuilder::new()
+            .collision_policy(CollisionPolicy::Skip)
+            .case_insensitive(true)
+            .build();
+        assert_eq!(filenames(&d), ["unique", "duplicate"]);
+        let d = DownloaderBuilder::new()
+            .collision_policy(CollisionPolicy::Skip)
+            .case_insensitive(false)
+            .build();
+        assert_eq!(filenames(&d), ["unique", "unique"]);
+    }
+
+    #[test]
+    fn test_probe_case_insensitive_concurrently() {
+        let directory = std::env::temp_dir().join("trauma-test-case-probe");
+        fs::create_dir_all(&directory).unwrap();
+        let expected = probe_case_insensitive(&directory);
+        assert!(expected.is_some());
+        let probes: Vec<_> = (0..8)
+            .map(|_| {
+                let directory = directory.clone();
+                std::thread::spawn(move || {
+                    (0..50).all(|_| probe_case_insensitive(&directory) == expected)
+                })
+            })
+            .collect();
+        for probe in probes {
+            assert!(probe.join().unwrap());
+        }
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 0);
+        assert_eq!(is_case_insensitive(&directory), expected.unwrap());
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn test_check_writable_concurrently() {
+        let directory = std::env::temp_dir().join("trauma-test-writable");
+        let checks: Vec<_> = (0..8)
+            .map(|_| {
+                let directory = directory.clone();
+                std::thread::spawn(move || (0..50).try_for_each(|_| check_writable(&directory)))
+            })
+            .collect();
+        for check in checks {
+            check.join().unwrap().unwrap();
+        }
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 0);
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn test_ramp_up_delay() {
+        let d = DownloaderBuilder::new()
+            .concurrent_downloads(4)
+            .ramp_up(Duration::from_secs(4))
+            .build();
+        assert_eq!(d.ramp_up_delay(0), Duration::ZERO);
+        assert_eq!(d.ramp_up_delay(3), Duration::from_secs(3));
+        assert_eq!(d.ramp_up_delay(4), Duration::ZERO);
+    }
+
+    /// A writer failing a given number of times before accepting the data.
+    struct FlakyWriter {
+        failures: u32,
+        written: Vec<u8>,
+    }
+
+    impl AsyncWrite for FlakyWriter {
+        fn poll_write(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<io::Result<usize>> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return std::task::Poll::Ready(Err(io::Error::other("transient failure")));
+            }
+            self.written.extend_from_slice(buf);
+            std::task::Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_chunk_retries() {
+        let d = DownloaderBuilder::new().write_retries(2).build();
+        let mut writer = FlakyWriter {
+            failures: 2,
+            written: Vec::new(),
+        };
+        let mut chunk = bytes::Bytes::from_static(b"chunk");
+        d.write_chunk(&mut writer, &mut chunk).await.unwrap();
+        assert_eq!(writer.written, b"chunk");
+    }
+
+    #[tokio::test]
+    async fn test_write_chunk_gives_up() {
+        let d = DownloaderBuilder::new().write_retries(1).build();
+        let mut writer = FlakyWriter {
+            failures: 2,
+            written: Vec::new(),
+        };
+        let mut chunk = bytes::Bytes::from_static(b"chunk");
+        assert!(d.write_chunk(&mut writer, &mut chunk).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_verify_size_short_write() {
+        let path = std::env::temp_dir().join("trauma-test-verify-size");
+        tokio::fs::write(&path, b"short").await.unwrap();
+        assert!(verify_size(&path, 5).await.is_ok());
+        assert!(verify_size(&path, 10).await.is_err());
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_drop_download_closes_connection() {
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::TcpListener,
+            sync::oneshot,
+        };
+
+        // Serve the beginning of a large response, then wait for the client to
+        // hang up.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (started_tx, started_rx) = oneshot::channel();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = socket.read(&mut buf).await.unwrap();
+            assert!(buf.starts_with(b"GET "));
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 1048576\r\n\r\n")
+                .await
+                .unwrap();
+            socket.write_all(&[0u8; 1024]).await.unwrap();
+            started_tx.send(()).unwrap();
+            while !matches!(socket.read(&mut buf).await, Ok(0) | Err(_)) {}
+        });
+
+        // Skip the HEAD request, so the future is dropped while streaming the
+        // body of the GET one.
+        let url = Url::parse(&format!("http://{}/cancel.bin", addr)).unwrap();
+        let downloads = [Download::try_from(&url).unwrap().with_resumable(false)];
+        let directory = std::env::temp_dir().join("trauma-test-cancel");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .build();
+
+        tokio::select! {
+            _ = d.download(&downloads) => panic!("the download should not complete"),
+            _ = started_rx => {},
+        }
+
+        // The future is dropped, so the server must see the connection close.
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("the connection was not closed")
+            .unwrap();
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[tokio::test]
+    async fn test_read_idle_timeout() {
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::TcpListener,
+        };
+
+        // Send the beginning of the responses, then stall.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    let _ = socket.read(&mut buf).await.unwrap();
+                    socket
+                        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 1024\r\n\r\nstart")
+                        .await
+                        .unwrap();
+                    let _ = socket.read(&mut buf).await;
+                });
+            }
+        });
+
+        let url = Url::parse(&format!("http://{}/stalled.bin", addr)).unwrap();
+        let download = Download::try_from(&url).unwrap().with_resumable(false);
+        let directory = std::env::temp_dir().join("trauma-test-idle");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .read_idle_timeout(Duration::from_millis(200))
+            .use_temp_file(false)
+            .build();
+        let summary = d.download_one(&download).await.unwrap();
+        assert!(matches!(summary.status(), Status::Fail(_)));
+        assert!(directory.join("stalled.bin").exists());
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[tokio::test]
+    async fn test_per_download_timeout() {
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::TcpListener,
+        };
+
+        // Keep sending bytes, never completing the file.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    while let Ok(n @ 1..) = socket.read(&mut buf).await {
+                        let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+                        if request.starts_with("head") {
+                            let head = b"HTTP/1.1 200 OK\r\naccept-ranges: bytes\r\ncontent-length: 1000000\r\n\r\n";
+                            socket.write_all(head).await.unwrap();
+                            continue;
+                        }
+                        let head = match request.contains("range: bytes=") {
+                            true => "HTTP/1.1 206 Partial Content\r\ncontent-range: bytes 4-999999/1000000\r\ncontent-length: 999996\r\n\r\n",
+                            false => "HTTP/1.1 200 OK\r\ncontent-length: 1000000\r\n\r\n",
+                        };
+                        socket.write_all(head.as_bytes()).await.unwrap();
+                        while socket.write_all(b"more").await.is_ok() {
+                            tokio::time::sleep(Duration::from_millis(10)).await;
+                        }
+                        return;
+                    }
+                });
+            }
+        });
+
+        let url = Url::parse(&format!("http://{}/endless.bin", addr)).unwrap();
+        let download = Download::try_from(&url).unwrap();
+        let directory = std::env::temp_dir().join("trauma-test-per-download-timeout");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .per_download_timeout(Duration::from_millis(200))
+            .use_temp_file(false)
+            .build();
+
+        // The download progresses, but is interrupted all the same, and its
+        // bytes are discarded.
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(
+            summary.status(),
+            &Status::Fail("per-download timeout".into())
+        );
+        assert!(!directory.join("endless.bin").exists());
+
+        // The bytes of an earlier run are kept.
+        tokio::fs::write(directory.join("endless.bin"), "kept")
+            .await
+            .unwrap();
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(
+            summary.status(),
+            &Status::Fail("per-download timeout".into())
+        );
+        let kept = tokio::fs::read(directory.join("endless.bin"))
+            .await
+            .unwrap();
+        assert_eq!(kept, b"kept");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[tokio::test]
+    async fn test_read_idle_timeout_resumes() {
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::TcpListener,
+        };
+
+        // Stall after the beginning of the file, but serve the rest of it
+        // when requested with a range.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    while let Ok(n @ 1..) = socket.read(&mut buf).await {
+                        let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+                        let response: &[u8] = if request.starts_with("head") {
+                            b"HTTP/1.1 200 OK\r\naccept-ranges: bytes\r\ncontent-length: 10\r\n\r\n"
+                        } else if request.contains("range: bytes=5-") {
+                            b"HTTP/1.1 206 Partial Content\r\ncontent-range: bytes 5-9/10\r\ncontent-length: 5\r\n\r\n-end!"
+                        } else {
+                            b"HTTP/1.1 200 OK\r\ncontent-length: 10\r\n\r\nstart"
+                        };
+                        socket.write_all(response).await.unwrap();
+                    }
+                });
+            }
+        });
+
+        let url = Url::parse(&format!("http://{}/stalled.bin", addr)).unwrap();
+        let download = Download::try_from(&url).unwrap();
+        let directory = std::env::temp_dir().join("trauma-test-idle-resumes");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .read_idle_timeout(Duration::from_millis(200))
+            .build();
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        let written = tokio::fs::read(directory.join("stalled.bin"))
+            .await
+            .unwrap();
+        assert_eq!(written, b"start-end!");
+
+        // Without retries, the stalled download fails.
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .read_idle_timeout(Duration::from_millis(200))
+            .retries(0)
+            .build();
+        let summary = d.download_one(&download).await.unwrap();
+        assert!(matches!(summary.status(), Status::Fail(_)));
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[tokio::test]
+    async fn test_download_with_handle() {
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::TcpListener,
+        };
+
+        // Send the beginning of the file, then close the connection once
+        // told to, but serve the rest of it when requested with a range.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (started, close) = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+        let (started_tx, close_rx) = (started.clone(), close.clone());
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let (started, close) = (started_tx.clone(), close_rx.clone());
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    while let Ok(n @ 1..) = socket.read(&mut buf).await {
+                        let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+                        if request.starts_with("head") {
+                            socket
+                                .write_all(b"HTTP/1.1 200 OK\r\naccept-ranges: bytes\r\ncontent-length: 10\r\n\r\n")
+                                .await
+                                .unwrap();
+                        } else if request.contains("range: bytes=5-") {
+                            socket
+                                .write_all(b"HTTP/1.1 206 Partial Content\r\ncontent-range: bytes 5-9/10\r\ncontent-length: 5\r\n\r\n-end!")
+                                .await
+                                .unwrap();
+                        } else {
+                            socket
+                                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 10\r\n\r\nstart")
+                                .await
+                                .unwrap();
+                            started.notify_one();
+                            close.notified().await;
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+
+        let url = Url::parse(&format!("http://{}/paused.bin", addr)).unwrap();
+        let downloads = [Download::try_from(&url).unwrap()];
+        let directory = std::env::temp_dir().join("trauma-test-handle");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .build();
+        let (handle, batch) = d.download_with_handle(&downloads);
+        let control = async {
+            tokio::time::timeout(Duration::from_secs(5), started.notified())
+                .await
+                .expect("the download did not start");
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            handle.pause();
+            assert!(handle.is_paused());
+            close.notify_one();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            handle.resume();
+        };
+        let (summaries, ()) = tokio::join!(batch, control);
+        assert_eq!(summaries.unwrap()[0].status(), &Status::Success);
+        let written = tokio::fs::read(directory.join("paused.bin")).await.unwrap();
+        assert_eq!(written, b"start-end!");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[tokio::test]
+    async fn test_download_one_reuses_connections() {
+        use std::sync::atomic::AtomicUsize;
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::TcpListener,
+        };
+
+        // Count the connections, answering every request with a small body.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = connections.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    while let Ok(n @ 1..) = socket.read(&mut buf).await {
+                        let requests = buf[..n].windows(4).filter(|w| w == b"\r\n\r\n").count();
+                        for _ in 0..requests {
+                            socket
+                                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 4\r\n\r\npong")
+                                .await
+                                .unwrap();
+                        }
+                    }
+                });
+            }
+        });
+
+        let url = Url::parse(&format!("http://{}/poll", addr)).unwrap();
+        let download = Download::try_from(&url).unwrap().with_resumable(false);
+        let directory = std::env::temp_dir().join("trauma-test-keep-alive");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .build();
+        for _ in 0..3 {
+            let summary = d.download_one(&download).await.unwrap();
+            assert_eq!(summary.status(), &Status::Success);
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(all(feature = "testing", feature = "serde"))]
+    #[tokio::test]
+    async fn test_download_in_memory() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server
+            .non_resumable("/manifest.json", r#"{"version": 2}"#)
+            .await;
+        let download = Download::try_from(&url).unwrap();
+        let d = DownloaderBuilder::hidden().build();
+        assert_eq!(
+            d.download_text(&download).await.unwrap(),
+            r#"{"version": 2}"#
+        );
+        let manifest: std::collections::HashMap<String, u32> =
+            d.download_json(&download).await.unwrap();
+        assert_eq!(manifest["version"], 2);
+
+        let missing = Download::try_from(&server.url("/missing.json")).unwrap();
+        assert!(d.download_bytes(&missing).await.is_err());
+    }
+
+    #[cfg(all(feature = "testing", feature = "serde"))]
+    #[tokio::test]
+    async fn test_jsonl_events() {
+        let server = crate::testing::MockServer::start().await;
+        let ok = server.non_resumable("/ok.txt", "hello world").await;
+        let missing = server.url("/missing.txt");
+        let directory = std::env::temp_dir().join("trauma-test-jsonl-events");
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        let events = directory.join("events.jsonl");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .jsonl_events(tokio::fs::File::create(&events).await.unwrap())
+            .build();
+        d.download(&[
+            Download::try_from(&ok).unwrap(),
+            Download::try_from(&missing).unwrap(),
+        ])
+        .await
+        .unwrap();
+
+        let events = tokio::fs::read_to_string(&events).await.unwrap();
+        let events = events
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        let of = |file: &str| {
+            events
+                .iter()
+                .filter(|e| e["file"] == file)
+                .map(|e| e["event"].as_str().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(of("ok.txt"), ["started", "progress", "completed"]);
+        assert_eq!(of("missing.txt"), ["failed"]);
+        let completed = events.iter().find(|e| e["event"] == "completed").unwrap();
+        assert_eq!(completed["bytes"], 11);
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_on_progress() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.non_resumable("/data.bin", vec![0u8; 4096]).await;
+        let directory = std::env::temp_dir().join("trauma-test-on-progress");
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let recorded = updates.clone();
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .on_progress(move |download, bytes, total| {
+                assert_eq!(download.filename, "data.bin");
+                recorded.lock().unwrap().push((bytes, total));
+            })
+            .build();
+        d.download_one(&Download::try_from(&url).unwrap())
+            .await
+            .unwrap();
+        let updates = updates.lock().unwrap().clone();
+        assert_eq!(updates.last(), Some(&(4096, Some(4096))));
+        assert!(updates.windows(2).all(|w| w[0].0 < w[1].0));
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[tokio::test]
+    async fn test_progress_granularity() {
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::TcpListener,
+        };
+
+        // Send the file in 10 chunks of 256 bytes.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let _ = socket.read(&mut [0u8; 1024]).await;
+                    socket
+                        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2560\r\n\r\n")
+                        .await
+                        .unwrap();
+                    for _ in 0..10 {
+                        socket.write_all(&[0u8; 256]).await.unwrap();
+                        tokio::time::sleep(Duration::from_millis(30)).await;
+                    }
+                });
+            }
+        });
+
+        let url = Url::parse(&format!("http://{}/chunks.bin", addr)).unwrap();
+        let directory = std::env::temp_dir().join("trauma-test-progress-granularity");
+        for (granularity, expected) in [
+            (0, (256..=2560).step_by(256).collect()),
+            (1024, vec![1024, 2048, 2560]),
+        ] {
+            let updates = Arc::new(Mutex::new(Vec::new()));
+            let recorded = updates.clone();
+            let d = DownloaderBuilder::hidden()
+                .directory(directory.clone())
+                .resumable(false)
+                .progress_granularity(granularity)
+                .on_progress(move |_, bytes, _| recorded.lock().unwrap().push(bytes))
+                .build();
+            let summary = d
+                .download_one(&Download::try_from(&url).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(summary.status(), &Status::Success);
+            assert_eq!(
+                *updates.lock().unwrap(),
+                expected,
+                "granularity {}",
+                granularity
+            );
+        }
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[tokio::test]
+    async fn test_time_to_first_byte() {
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::TcpListener,
+        };
+
+        // Send the headers right away, but the body after a while.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let _ = socket.read(&mut [0u8; 1024]).await;
+                    socket
+                        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 4\r\n\r\n")
+                        .await
+                        .unwrap();
+                    tokio::time::sleep(Duration::from_millis(300)).await;
+                    let _ = socket.write_all(b"late").await;
+                });
+            }
+        });
+
+        let url = Url::parse(&format!("http://{}/late.txt", addr)).unwrap();
+        let directory = std::env::temp_dir().join("trauma-test-time-to-first-byte");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .resumable(false)
+            .build();
+        let download = Download::try_from(&url).unwrap();
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        let ttfb = summary.time_to_first_byte().unwrap();
+        assert!(ttfb >= Duration::from_millis(250), "{:?}", ttfb);
+
+        // No chunk is read for the downloads skipped after the headers.
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .deny_extensions(vec!["txt".into()])
+            .build();
+        let summaries = d.download(&[download]).await.unwrap();
+        assert!(matches!(summaries[0].status(), Status::Skipped(_)));
+        assert_eq!(summaries[0].time_to_first_byte(), None);
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_memory_cache() {
+        use wiremock::{matchers, Mock, ResponseTemplate};
+
+        let server = crate::testing::MockServer::start().await;
+        Mock::given(matchers::path("/hot.json"))
+            .and(matchers::header("If-None-Match", "\"v1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .mount(server.inner())
+            .await;
+        Mock::given(matchers::path("/hot.json"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v1\"")
+                    .set_body_string("{}"),
+            )
+            .expect(1)
+            .mount(server.inner())
+            .await;
+
+        let d = DownloaderBuilder::hidden().memory_cache(1024).build();
+        let download = Download::try_from(&server.url("/hot.json")).unwrap();
+        for _ in 0..3 {
+            assert_eq!(d.download_bytes(&download).await.unwrap(), "{}");
+        }
+
+        // The texts are cached too, and decoded with their charset.
+        Mock::given(matchers::path("/hot.txt"))
+            .and(matchers::header("If-None-Match", "\"v2\""))
+            .respond_with(ResponseTemplate::new(304))
+            .expect(1)
+            .mount(server.inner())
+            .await;
+        Mock::given(matchers::path("/hot.txt"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v2\"")
+                    .insert_header("Content-Type", "text/plain; charset=iso-8859-1")
+                    .set_body_bytes(b"caf\xe9".to_vec()),
+            )
+            .expect(1)
+            .mount(server.inner())
+            .await;
+        let download = Download::try_from(&server.url("/hot.txt")).unwrap();
+        for _ in 0..2 {
+            assert_eq!(d.download_text(&download).await.unwrap(), "café");
+        }
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_download_concat() {
+        let server = crate::testing::MockServer::start().await;
+        let mut parts = Vec::new();
+        for (i, body) in ["first,", "second,", "third"].iter().enumerate() {
+            let url = server.non_resumable(&format!("/part-{}", i), *body).await;
+            parts.push(Download::try_from(&url).unwrap());
+        }
+        let directory = std::env::temp_dir().join("trauma-test-concat");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .build();
+        let summary = d.download_concat(&parts, "whole.txt".into()).await;
+        assert_eq!(summary.status(), &Status::Success);
+        assert_eq!(summary.size(), 18);
+        let written = tokio::fs::read(directory.join("whole.txt")).await.unwrap();
+        assert_eq!(written, b"first,second,third");
+
+        let summary = d.download_concat(&[], "empty.txt".into()).await;
+        assert!(matches!(summary.status(), Status::Fail(_)));
+        assert!(!directory.join("empty.txt").exists());
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_download_concat_resumes() {
+        let server = crate::testing::MockServer::start().await;
+        let mut parts = Vec::new();
+        for (i, body) in ["first,", "second,", "third"].iter().enumerate() {
+            let url = server.resumable(&format!("/part-{}", i), *body).await;
+            parts.push(Download::try_from(&url).unwrap());
+        }
+        let directory = std::env::temp_dir().join("trauma-test-concat-resumes");
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+
+        // Simulate a crash while writing the second part: the file has its
+        // full length, but only its first 9 bytes were written.
+        let output = directory.join("whole.txt");
+        let mut preallocated = b"first,sec".to_vec();
+        preallocated.resize(18, 0);
+        tokio::fs::write(&output, preallocated).await.unwrap();
+        tokio::fs::write(state_path(&output), "0-9\n")
+            .await
+            .unwrap();
+
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .build();
+        let summary = d.download_concat(&parts, "whole.txt".into()).await;
+        assert_eq!(summary.status(), &Status::Success);
+        let written = tokio::fs::read(&output).await.unwrap();
+        assert_eq!(written, b"first,second,third");
+        assert!(!state_path(&output).exists());
+
+        // The first part was complete, and the second one was resumed.
+        let requests = server.inner().received_requests().await.unwrap();
+        let gets = requests
+            .iter()
+            .filter(|r| r.method == http::Method::GET)
+            .collect::<Vec<_>>();
+        assert!(gets.iter().all(|r| r.url.path() != "/part-0"));
+        let second = gets.iter().find(|r| r.url.path() == "/part-1").unwrap();
+        assert_eq!(second.headers.get(RANGE).unwrap(), "bytes=3-");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[test]
+    fn test_hill_climb() {
+        // The throughput stops improving beyond 4 connections.
+        let throughput = |limit: usize| limit.min(4) as f64 * 10.0;
+        let mut climb = HillClimb::default();
+        let mut limit = 1;
+        for _ in 0..20 {
+            limit = climb.next(limit, throughput(limit)).clamp(1, 8);
+        }
+        assert!((3..=5).contains(&limit), "settled at {}", limit);
+    }
+
+    #[test]
+    fn test_content_addressed_path() {
+        assert_eq!(content_addressed_path("abcdef", 0), PathBuf::from("abcdef"));
+        assert_eq!(
+            content_addressed_path("abcdef", 2),
+            Path::new("ab").join("cdef")
+        );
+    }
+
+    #[cfg(all(feature = "gzip", feature = "testing"))]
+    #[tokio::test]
+    async fn test_decompress_disables_resume() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.resumable("/data.json", vec![b'x'; 64]).await;
+        let directory = std::env::temp_dir().join("trauma-test-decompress");
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        tokio::fs::write(directory.join("data.json"), [b'y'; 16])
+            .await
+            .unwrap();
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .decompress(true)
+            .build();
+        let summary = d
+            .download_one(&Download::try_from(&url).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        assert_eq!(summary.resumed_from(), 0);
+        let written = tokio::fs::read(directory.join("data.json")).await.unwrap();
+        assert_eq!(written, vec![b'x'; 64]);
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(all(feature = "auto-decompress", feature = "testing"))]
+    #[tokio::test]
+    async fn test_auto_decompress() {
+        use async_compression::tokio::bufread::{BzEncoder, GzipEncoder, XzEncoder, ZstdEncoder};
+        use tokio::io::AsyncReadExt;
+
+        let data = b"hello, trauma!\n".repeat(64);
+        let mut encoded = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        GzipEncoder::new(&data[..])
+            .read_to_end(&mut encoded[0])
+            .await
+            .unwrap();
+        ZstdEncoder::new(&data[..])
+            .read_to_end(&mut encoded[1])
+            .await
+            .unwrap();
+        BzEncoder::new(&data[..])
+            .read_to_end(&mut encoded[2])
+            .await
+            .unwrap();
+        XzEncoder::new(&data[..])
+            .read_to_end(&mut encoded[3])
+            .await
+            .unwrap();
+
+        let server = crate::testing::MockServer::start().await;
+        let mut downloads = Vec::new();
+        for (name, body) in ["/a.txt.gz", "/b.txt.zst", "/c.txt.bz2", "/d.txt.xz"]
+            .into_iter()
+            .zip(encoded)
+        {
+            let url = server.resumable(name, body).await;
+            downloads.push(Download::try_from(&url).unwrap());
+        }
+        let directory = std::env::temp_dir().join("trauma-test-auto-decompress");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .auto_decompress(true)
+            .build();
+        let summaries = d.download(&downloads).await.unwrap();
+        for summary in &summaries {
+            assert_eq!(summary.status(), &Status::Success);
+        }
+        for name in ["a.txt", "b.txt", "c.txt", "d.txt"] {
+            let written = tokio::fs::read(directory.join(name)).await.unwrap();
+            assert_eq!(written, data, "{name}");
+        }
+        for download in &downloads {
+            assert!(!directory.join(&download.filename).exists());
+        }
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(all(feature = "gzip", feature = "testing"))]
+    #[tokio::test]
+    async fn test_decode_error_fall_back_to_raw() {
+        use wiremock::{matchers, Mock, ResponseTemplate};
+
+        // A gzip header followed by garbage.
+        let corrupt = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\x03not deflate data".to_vec();
+        let server = crate::testing::MockServer::start().await;
+        Mock::given(matchers::path("/corrupt.txt"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-encoding", "gzip")
+                    .set_body_bytes(corrupt.clone()),
+            )
+            .mount(server.inner())
+            .await;
+        let download = Download::try_from(&server.url("/corrupt.txt")).unwrap();
+        let directory = std::env::temp_dir().join("trauma-test-decode-error");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .decompress(true)
+            .build();
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(summary.status(), &Status::Fail(DECODE_ERROR.into()));
+
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .decompress(true)
+            .on_decode_error(DecodeErrorPolicy::FallBackToRaw)
+            .build();
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        let written = tokio::fs::read(directory.join("corrupt.txt"))
+            .await
+            .unwrap();
+        assert_eq!(written, corrupt);
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_sync_on_complete() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.resumable("/synced.bin", vec![7u8; 64]).await;
+        let directory = std::env::temp_dir().join("trauma-test-sync-on-complete");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .sync_on_complete(true)
+            .build();
+        let summary = d
+            .download_one(&Download::try_from(&url).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        assert_eq!(summary.path(), Some(directory.join("synced.bin").as_path()));
+        let written = tokio::fs::read(directory.join("synced.bin")).await.unwrap();
+        assert_eq!(written, vec![7u8; 64]);
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(all(feature = "multipart", feature = "testing"))]
+    #[tokio::test]
+    async fn test_download_with_form() {
+        use reqwest::multipart::Form;
+        use wiremock::{matchers, Mock, ResponseTemplate};
+
+        let server = crate::testing::MockServer::start().await;
+        Mock::given(matchers::method("POST"))
+            .and(matchers::path("/export.csv"))
+            .and(matchers::header_regex(
+                "content-type",
+                "^multipart/form-data",
+            ))
+            .and(matchers::body_string_contains("name=\"format\""))
+            .respond_with(ResponseTemplate::new(200).set_body_string("a,b\n1,2\n"))
+            .mount(server.inner())
+            .await;
+        let directory = std::env::temp_dir().join("trauma-test-form");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        // A partial file is never resumed.
+        tokio::fs::write(directory.join("export.csv.part"), "a,b")
+            .await
+            .unwrap();
+        let download = Download::try_from(&server.url("/export.csv"))
+            .unwrap()
+            .with_form(|| Form::new().text("format", "csv"));
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .build();
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        assert_eq!(summary.resumed_from(), 0);
+        let written = tokio::fs::read_to_string(directory.join("export.csv"))
+            .await
+            .unwrap();
+        assert_eq!(written, "a,b\n1,2\n");
+        let requests = server.inner().received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(!requests[0].headers.contains_key(RANGE));
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_temp_dir() {
+        let server = crate::testing::MockServer::start().await;
+        let body: Vec<u8> = (0..64).collect();
+        let url = server.resumable("/moved.bin", body.clone()).await;
+        let download = Download::try_from(&url).unwrap();
+        let directory = std::env::temp_dir().join("trauma-test-temp-dir");
+        let temp_dir = std::env::temp_dir().join("trauma-test-temp-dir-scratch");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+        tokio::fs::create_dir_all(&temp_dir).await.unwrap();
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .temp_dir(temp_dir.clone())
+            .build();
+
+        // The partial file is resumed from the temporary directory, then
+        // moved to the final one.
+        tokio::fs::write(temp_dir.join("moved.bin.part"), &body[..16])
+            .await
+            .unwrap();
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        assert_eq!(summary.resumed_from(), 16);
+        assert_eq!(
+            tokio::fs::read(directory.join("moved.bin")).await.unwrap(),
+            body
+        );
+        assert!(!temp_dir.join("moved.bin.part").exists());
+
+        // The complete file is skipped.
+        server.inner().reset().await;
+        server.resumable("/moved.bin", body.clone()).await;
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(
+            summary.status(),
+            &Status::Skipped(SkipReason::AlreadyComplete)
+        );
+        let requests = server.inner().received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(!temp_dir.join("moved.bin.part").exists());
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_summary_urls() {
+        use wiremock::{matchers, Mock, ResponseTemplate};
+
+        let server = crate::testing::MockServer::start().await;
+        let target = server.non_resumable("/target.txt", "served").await;
+        Mock::given(matchers::path("/moved.txt"))
+            .respond_with(ResponseTemplate::new(302).insert_header("location", "/target.txt"))
+            .mount(server.inner())
+            .await;
+        let requested = server.url("/moved.txt");
+        let directory = std::env::temp_dir().join("trauma-test-summary-urls");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .resumable(false)
+            .retries(0)
+            .build();
+        let downloads = [
+            Download::try_from(&requested).unwrap(),
+            Download::try_from("http://localhost:1/unreachable.txt").unwrap(),
+        ];
+        let summaries = d.download(&downloads).await.unwrap();
+        let (served, failed) = match summaries[0].status() {
+            Status::Success => (&summaries[0], &summaries[1]),
+            _ => (&summaries[1], &summaries[0]),
+        };
+        assert_eq!(served.status(), &Status::Success);
+        assert_eq!(served.url(), &requested);
+        assert_eq!(served.final_url(), &target);
+
+        // Without a response, the final URL is the requested one.
+        assert!(matches!(failed.status(), Status::Fail(_)));
+        assert_eq!(failed.final_url(), failed.url());
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_download_filtered() {
+        let server = crate::testing::MockServer::start().await;
+        let mut downloads = Vec::new();
+        for name in ["/debian.iso", "/debian.iso.sig"] {
+            let url = server.non_resumable(name, "data").await;
+            downloads.push(Download::try_from(&url).unwrap());
+        }
+        let directory = std::env::temp_dir().join("trauma-test-download-filtered");
+        let is_iso = |d: &Download| d.filename.ends_with(".iso");
+        for include_filtered in [true, false] {
+            let _ = tokio::fs::remove_dir_all(&directory).await;
+            let d = DownloaderBuilder::hidden()
+                .directory(directory.clone())
+                .include_filtered(include_filtered)
+                .build();
+            let summaries = d.download_filtered(&downloads, is_iso).await.unwrap();
+            let statuses: Vec<_> = summaries
+                .iter()
+                .map(|s| (s.download().filename.as_str(), s.status().clone()))
+                .collect();
+            let mut expected = vec![("debian.iso", Status::Success)];
+            if include_filtered {
+                expected.push(("debian.iso.sig", Status::Skipped(SkipReason::Filtered)));
+            }
+            assert_eq!(statuses, expected);
+            assert!(!directory.join("debian.iso.sig").exists());
+        }
+        let requests = server.inner().received_requests().await.unwrap();
+        assert!(requests.iter().all(|r| r.url.path() == "/debian.iso"));
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_extension_filters() {
+        let server = crate::testing::MockServer::start().await;
+        let mut downloads = Vec::new();
+        for name in ["/a.zip", "/b.txt", "/c.ZIP"] {
+            let url = server.non_resumable(name, "data").await;
+            downloads.push(Download::try_from(&url).unwrap());
+        }
+        let directory = std::env::temp_dir().join("trauma-test-extensions");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .allow_extensions(vec!["zip".into(), "txt".into()])
+            .deny_extensions(vec![".txt".into()])
+            .build();
+        let summaries = d.download(&downloads).await.unwrap();
+        for summary in summaries {
+            let expected = match summary.download().filename.as_str() {
+                "b.txt" => Status::Skipped(SkipReason::ExtensionFiltered),
+                _ => Status::Success,
+            };
+            assert_eq!(summary.status(), &expected);
+        }
+        assert!(!directory.join("b.txt").exists());
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[tokio::test]
+    async fn test_directory_not_writable() {
+        let file = std::env::temp_dir().join("trauma-test-not-writable");
+        tokio::fs::write(&file, "not a directory").await.unwrap();
+        let d = DownloaderBuilder::hidden()
+            .directory(file.join("downloads"))
+            .build();
+        let downloads = [
+            Download::try_from("http://localhost:1/a.bin").unwrap(),
+            Download::try_from("http://localhost:1/b.bin").unwrap(),
+        ];
+        assert!(d.download(&downloads).await.is_err());
+        let _ = tokio::fs::remove_file(&file).await;
+    }
+
+    #[tokio::test]
+    async fn test_invalid_proxy() {
+        // The proxy URLs are validated upfront.
+        assert!(reqwest::Proxy::all("not a proxy").is_err());
+
+        let directory = std::env::temp_dir().join("trauma-test-invalid-proxy");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .retries(0)
+            .build();
+        let downloads = [
+            Download::try_from("http://localhost:1/a.bin").unwrap(),
+            Download::try_from("http://localhost:1/b.bin").unwrap(),
+        ];
+        // A proxy resolved per request, or which cannot be reached, fails the
+        // downloads but not the batch.
+        let proxies = [
+            reqwest::Proxy::custom(|_| Some("not a proxy")),
+            reqwest::Proxy::all("http://localhost:1").unwrap(),
+        ];
+        for proxy in proxies {
+            let summaries = d.download_with_proxy(&downloads, proxy).await.unwrap();
+            assert_eq!(summaries.len(), 2);
+            assert!(summaries.iter().all(
+                |s| matches!(s.status(), Status::Fail(e) if !e.contains("proxy configuration"))
+            ));
+        }
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_no_proxy() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.non_resumable("/direct.txt", "direct").await;
+        let download = Download::try_from(&url).unwrap();
+        let directory = std::env::temp_dir().join("trauma-test-no-proxy");
+        // The proxy cannot be reached, so only the bypassed hosts succeed.
+        let proxy = || reqwest::Proxy::all("http://localhost:1").unwrap();
+        for (no_proxy, succeeds) in [("127.0.0.1", true), ("example.com", false)] {
+            let _ = tokio::fs::remove_dir_all(&directory).await;
+            let d = DownloaderBuilder::hidden()
+                .directory(directory.clone())
+                .retries(0)
+                .no_proxy(no_proxy)
+                .build();
+            let summaries = d
+                .download_with_proxy(std::slice::from_ref(&download), proxy())
+                .await
+                .unwrap();
+            assert_eq!(
+                summaries[0].status() == &Status::Success,
+                succeeds,
+                "{}",
+                no_proxy
+            );
+        }
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[tokio::test]
+    async fn test_download_from_stream() {
+        let chunks = ["hello ", "from ", "a stream"].map(|c| Ok(Bytes::from(c)));
+        let download = Download::from_stream("stream.txt", stream::iter(chunks));
+        let directory = std::env::temp_dir().join("trauma-test-stream");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .build();
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        assert_eq!(summary.size(), 19);
+        let written = tokio::fs::read(directory.join("stream.txt")).await.unwrap();
+        assert_eq!(written, b"hello from a stream");
+
+        // The stream can only be consumed once.
+        let summary = d.download_one(&download).await.unwrap();
+        assert!(matches!(summary.status(), Status::Fail(_)));
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_max_header_size() {
+        use wiremock::{matchers, Mock, ResponseTemplate};
+
+        let server = crate::testing::MockServer::start().await;
+        Mock::given(matchers::path("/large-headers"))
+            .respond_with(ResponseTemplate::new(200).insert_header("x-padding", "x".repeat(4096)))
+            .mount(server.inner())
+            .await;
+        let url = server.url("/large-headers");
+        let directory = std::env::temp_dir().join("trauma-test-header-size");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .max_header_size(1024)
+            .build();
+        let summary = d
+            .download_one(&Download::try_from(&url).unwrap())
+            .await
+            .unwrap();
+        assert!(matches!(summary.status(), Status::Fail(e) if e.contains("too large")));
+        assert!(!directory.join("large-headers").exists());
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_resolve() {
+        let server = crate::testing::MockServer::start().await;
+        let mut url = server.non_resumable("/hosts.txt", "resolved").await;
+        url.set_host(Some("download.example")).unwrap();
+        let directory = std::env::temp_dir().join("trauma-test-resolve");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .resolve("download.example", *server.inner().address())
+            .build();
+        let summary = d
+            .download_one(&Download::try_from(&url).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_from_config() {
+        let config: DownloaderConfig = serde_json::from_str(
+            r#"{"directory": "downloads", "retries": 5, "timeout_secs": 30, "resumable": false, "headers": {"user-agent": "my-tool/1.0"}}"#,
+        )
+        .unwrap();
+        let d = DownloaderBuilder::from_config(config).unwrap().build();
+        assert_eq!(d.directory, Path::new("downloads"));
+        assert_eq!(d.retries, 5);
+        assert_eq!(d.timeout, Some(Duration::from_secs(30)));
+        assert!(!d.resumable);
+        assert_eq!(d.headers.unwrap()["user-agent"], "my-tool/1.0");
+
+        let config: DownloaderConfig =
+            serde_json::from_str(r#"{"headers": {"bad header": "value"}}"#).unwrap();
+        assert!(DownloaderBuilder::from_config(config).is_err());
+    }
+
+    #[test]
+    fn test_env_defaults() {
+        let env = std::collections::HashMap::from([
+            ("TRAUMA_TIMEOUT_SECS", "30"),
+            ("TRAUMA_RETRIES", " 7 "),
+            ("TRAUMA_CONCURRENCY", "0"),
+        ]);
+        let var = |name: &str| env.get(name).map(|v| v.to_string());
+        let d = DownloaderBuilder::without_env().env_defaults(var).build();
+        assert_eq!(d.timeout, Some(Duration::from_secs(30)));
+        assert_eq!(d.retries, 7);
+        assert_eq!(
+            d.concurrent_downloads,
+            Downloader::DEFAULT_CONCURRENT_DOWNLOADS
+        );
+
+        // The builder methods take precedence.
+        let d = DownloaderBuilder::without_env()
+            .env_defaults(var)
+            .retries(1)
+            .build();
+        assert_eq!(d.retries, 1);
+    }
+
+    #[test]
+    fn test_partial_path() {
+        let download = Download::try_from("https://example.com/file.zip").unwrap();
+        let builder = || DownloaderBuilder::new().directory("out".into());
+        let partial = |d: Downloader| d.partial_path(&download);
+        assert_eq!(partial(builder().build()), Path::new("out/file.zip.part"));
+        assert_eq!(
+            partial(builder().use_temp_file(false).build()),
+            Path::new("out/file.zip")
+        );
+        assert_eq!(
+            partial(builder().temp_dir("tmp".into()).build()),
+            Path::new("tmp/file.zip.part")
+        );
+        assert_eq!(
+            partial(builder().temp_salt("worker-1".into()).build()),
+            Path::new("out/file.zip.worker-1.part")
+        );
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_resume_remote_file_shrunk() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.resumable("/data.bin", vec![1u8; 32]).await;
+        let directory = std::env::temp_dir().join("trauma-test-shrunk");
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        tokio::fs::write(directory.join("data.bin"), [2u8; 64])
+            .await
+            .unwrap();
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .build();
+        let summary = d
+            .download_one(&Download::try_from(&url).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        assert_eq!(summary.resumed_from(), 0);
+        let written = tokio::fs::read(directory.join("data.bin")).await.unwrap();
+        assert_eq!(written, vec![1u8; 32]);
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_resume_overlap() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.resumable("/data.txt", "0123456789").await;
+        let directory = std::env::temp_dir().join("trauma-test-resume-overlap");
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .resume_overlap(2)
+            .build();
+        for (partial, resumed_from) in [("01234", 5), ("01X34", 5), ("012X4", 0)] {
+            tokio::fs::write(directory.join("data.txt.part"), partial)
+                .await
+                .unwrap();
+            let summary = d
+                .download_one(&Download::try_from(&url).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(summary.status(), &Status::Success);
+            assert_eq!(summary.resumed_from(), resumed_from);
+            let _ = tokio::fs::remove_file(directory.join("data.txt")).await;
+        }
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_resume_state_mismatch() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.resumable("/data.bin", vec![1u8; 64]).await;
+        let directory = std::env::temp_dir().join("trauma-test-resume-state");
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        let partial = directory.join("data.bin");
+        tokio::fs::write(&partial, [9u8; 16]).await.unwrap();
+        tokio::fs::write(state_path(&partial), "32").await.unwrap();
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .resume_state(true)
+            .use_temp_file(false)
+            .build();
+        let summary = d
+            .download_one(&Download::try_from(&url).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        assert_eq!(summary.resumed_from(), 0);
+        assert_eq!(tokio::fs::read(&partial).await.unwrap(), vec![1u8; 64]);
+        assert!(!state_path(&partial).exists());
+
+        // The bytes written after the recorded ones are downloaded again.
+        let mut torn = vec![1u8; 8];
+        torn.extend([9u8; 8]);
+        tokio::fs::write(&partial, torn).await.unwrap();
+        tokio::fs::write(state_path(&partial), "8").await.unwrap();
+        let summary = d
+            .download_one(&Download::try_from(&url).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        assert_eq!(summary.resumed_from(), 8);
+        assert_eq!(tokio::fs::read(&partial).await.unwrap(), vec![1u8; 64]);
+        assert!(!state_path(&partial).exists());
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_assume_ranges_for_hosts() {
+        use wiremock::{matchers, Mock, ResponseTemplate};
+
+        // Neither server advertises ranges, but the first one honors them.
+        let server = crate::testing::MockServer::start().await;
+        Mock::given(matchers::method("HEAD"))
+            .respond_with(ResponseTemplate::new(200).insert_header("Content-Length", "8"))
+            .mount(server.inner())
+            .await;
+        Mock::given(matchers::path("/ranges.txt"))
+            .and(matchers::header("Range", "bytes=4-"))
+            .respond_with(ResponseTemplate::new(206).set_body_string("5678"))
+            .mount(server.inner())
+            .await;
+        Mock::given(matchers::method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("12345678"))
+            .mount(server.inner())
+            .await;
+
+        let directory = std::env::temp_dir().join("trauma-test-assume-ranges");
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .assume_ranges_for_hosts(vec!["127.0.0.1".into()])
+            .use_temp_file(false)
+            .build();
+        for (path, resumed_from) in [("/ranges.txt", 4), ("/no-ranges.txt", 0)] {
+            let partial = directory.join(&path[1..]);
+            tokio::fs::write(&partial, "1234").await.unwrap();
+            let url = server.url(path);
+            let summary = d
+                .download_one(&Download::try_from(&url).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(summary.status(), &Status::Success);
+            assert_eq!(summary.resumed_from(), resumed_from);
+            assert_eq!(tokio::fs::read(&partial).await.unwrap(), b"12345678");
+        }
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_checksum() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.non_resumable("/hello.txt", "hello world").await;
+        let directory = std::env::temp_dir().join("trauma-test-checksum");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .use_temp_file(false)
+            .build();
+
+        let valid = crate::hash::Checksum::Md5("5EB63BBBE01EEED093CB22BB8F5ACDC3".into());
+        let download = Download::try_from(&url).unwrap().with_checksum(valid);
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+
+        let invalid = crate::hash::Checksum::Md5("d41d8cd98f00b204e9800998ecf8427e".into());
+        let download = Download::try_from(&url).unwrap().with_checksum(invalid);
+        let summary = d.download_one(&download).await.unwrap();
+        assert!(matches!(summary.status(), Status::ChecksumMismatch(_)));
+        assert!(!directory.join("hello.txt").exists());
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_respect_content_disposition() {
+        use wiremock::{matchers, Mock, ResponseTemplate};
+
+        let server = crate::testing::MockServer::start().await;
+        Mock::given(matchers::path("/download"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header(
+                        "Content-Disposition",
+                        "attachment; filename=\"../report.pdf\"",
+                    )
+                    .set_body_string("report"),
+            )
+            .mount(server.inner())
+            .await;
+        let directory = std::env::temp_dir().join("trauma-test-disposition");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.join("out"))
+            .respect_content_disposition(true)
+            .build();
+        let download = Download::try_from(&server.url("/download?id=1234"))
+            .unwrap()
+            .with_resumable(false);
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        let expected = directory.join("out").join("report.pdf");
+        assert_eq!(summary.path(), Some(expected.as_path()));
+        assert_eq!(tokio::fs::read(&expected).await.unwrap(), b"report");
+        assert!(!directory.join("out").join("download").exists());
+
+        // The suggested name is checked like the one of the download.
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(
+            summary.status(),
+            &Status::Skipped(SkipReason::AlreadyComplete)
+        );
+        tokio::fs::write(&expected, "old").await.unwrap();
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.join("out"))
+            .respect_content_disposition(true)
+            .collision_policy(CollisionPolicy::Rename)
+            .on_filename_collision(|path| path.with_extension("pdf.1"))
+            .build();
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        assert_eq!(tokio::fs::read(&expected).await.unwrap(), b"old");
+        let renamed = expected.with_extension("pdf.1");
+        assert_eq!(tokio::fs::read(&renamed).await.unwrap(), b"report");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_retry_policy() {
+        use reqwest_retry::RetryDecision;
+        use wiremock::{matchers, Mock, ResponseTemplate};
+
+        struct NeverRetry(Arc<AtomicUsize>);
+
+        impl RetryPolicy for NeverRetry {
+            fn should_retry(&self, _start: SystemTime, _retries: u32) -> RetryDecision {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                RetryDecision::DoNotRetry
+            }
+        }
+
+        let server = crate::testing::MockServer::start().await;
+        Mock::given(matchers::method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(server.inner())
+            .await;
+        let consulted = Arc::new(AtomicUsize::new(0));
+        let d = DownloaderBuilder::hidden()
+            .directory(std::env::temp_dir().join("trauma-test-retry-policy"))
+            .retries(5)
+            .retry_policy(NeverRetry(consulted.clone()))
+            .build();
+        let download = Download::try_from(&server.url("/unavailable.bin"))
+            .unwrap()
+            .with_resumable(false);
+        let summary = d.download_one(&download).await.unwrap();
+        assert!(matches!(summary.status(), Status::Fail(_)));
+        assert_eq!(consulted.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_inspect_request() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.resumable("/signed.bin", "signed").await;
+        let directory = std::env::temp_dir().join("trauma-test-inspect-request");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .inspect_request(|req| req.header("x-nonce", "42"))
+            .build();
+        let summary = d
+            .download_one(&Download::try_from(&url).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+
+        // The probes are prepared like the download itself.
+        let requests = server.inner().received_requests().await.unwrap();
+        assert!(requests.iter().any(|r| r.method.as_str() == "HEAD"));
+        assert!(requests.iter().all(|r| r.headers.contains_key("x-nonce")));
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_global_retry_budget() {
+        use wiremock::{matchers, Mock, ResponseTemplate};
+
+        let server = crate::testing::MockServer::start().await;
+        Mock::given(matchers::method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(server.inner())
+            .await;
+        let backoff = Duration::from_millis(1);
+        let d = DownloaderBuilder::hidden()
+            .directory(std::env::temp_dir().join("trauma-test-retry-budget"))
+            .retry_policy(
+                ExponentialBackoff::builder()
+                    .retry_bounds(backoff, backoff)
+                    .build_with_max_retries(5),
+            )
+            .global_retry_budget(1)
+            .build();
+        let requests = || async { server.inner().received_requests().await.unwrap().len() };
+
+        // Every call gets a budget of its own.
+        let download = Download::try_from(&server.url("/a.bin"))
+            .unwrap()
+            .with_resumable(false);
+        assert!(d.download_bytes(&download).await.is_err());
+        assert_eq!(requests().await, 2);
+        assert!(d.download_bytes(&download).await.is_err());
+        assert_eq!(requests().await, 4);
+
+        // The downloads of a batch share its budget.
+        let downloads = [
+            download.clone(),
+            Download::try_from(&server.url("/b.bin"))
+                .unwrap()
+                .with_resumable(false),
+        ];
+        d.download(&downloads).await.unwrap();
+        assert_eq!(requests().await, 7);
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_mirrors() {
+        use wiremock::{matchers, Mock, ResponseTemplate};
+
+        let server = crate::testing::MockServer::start().await;
+        Mock::given(matchers::path("/primary/pkg.tar.gz"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(server.inner())
+            .await;
+        let mirror = server.resumable("/mirror/pkg.tar.gz", "package").await;
+        let directory = std::env::temp_dir().join("trauma-test-mirrors");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .retries(0)
+            .build();
+        let primary = server.url("/primary/pkg.tar.gz");
+        let download = Download::try_from(&primary)
+            .unwrap()
+            .with_mirrors(vec![server.url("/missing/pkg.tar.gz"), mirror.clone()]);
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        assert_eq!(summary.url(), &primary);
+        assert_eq!(summary.final_url(), &mirror);
+        let written = tokio::fs::read(directory.join("pkg.tar.gz")).await.unwrap();
+        assert_eq!(written, b"package");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_mirrors_checksum_mismatch() {
+        let server = crate::testing::MockServer::start().await;
+        let primary = server.non_resumable("/primary/pkg.bin", "corrupt").await;
+        let mirror = server.non_resumable("/mirror/pkg.bin", "package").await;
+        let directory = std::env::temp_dir().join("trauma-test-mirrors-checksum");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .build();
+        let checksum = crate::hash::Checksum::Md5("efe90a8e604a7c840e88d03a67f6b7d8".into());
+        let download = Download::try_from(&primary)
+            .unwrap()
+            .with_checksum(checksum)
+            .with_mirrors(vec![mirror.clone()]);
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        assert_eq!(summary.final_url(), &mirror);
+        let written = tokio::fs::read(directory.join("pkg.bin")).await.unwrap();
+        assert_eq!(written, b"package");
+
+        // Both attempts share a single progress bar.
+        let progress = d.progress();
+        assert_eq!(progress.len(), 1);
+        assert_eq!(progress[0].downloaded, 7);
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_use_temp_file() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.resumable("/data.bin", vec![1u8; 32]).await;
+        let directory = std::env::temp_dir().join("trauma-test-temp-file");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .build();
+        let download = Download::try_from(&url).unwrap();
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        assert!(!directory.join("data.bin.part").exists());
+
+        // The file moved in place is complete, even if the server does not
+        // support ranges, which is found out with a single request.
+        let plain = server.non_resumable("/plain.bin", vec![2u8; 32]).await;
+        let plain = Download::try_from(&plain).unwrap();
+        d.download_one(&plain).await.unwrap();
+        for download in [&download, &plain] {
+            let before = server.inner().received_requests().await.unwrap().len();
+            let summary = d.download_one(download).await.unwrap();
+            assert_eq!(
+                summary.status(),
+                &Status::Skipped(SkipReason::AlreadyComplete)
+            );
+            let after = server.inner().received_requests().await.unwrap().len();
+            assert_eq!(after - before, 1);
+        }
+
+        // The temporary file is resumed.
+        tokio::fs::remove_file(directory.join("data.bin"))
+            .await
+            .unwrap();
+        tokio::fs::write(directory.join("data.bin.part"), [1u8; 16])
+            .await
+            .unwrap();
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(summary.resumed_from(), 16);
+        let written = tokio::fs::read(directory.join("data.bin")).await.unwrap();
+        assert_eq!(written, vec![1u8; 32]);
+
+        // A corrupted download leaves the previous file untouched.
+        let invalid = crate::hash::Checksum::Md5("d41d8cd98f00b204e9800998ecf8427e".into());
+        let corrupted = Download::try_from(&plain.url)
+            .unwrap()
+            .with_checksum(invalid);
+        tokio::fs::write(directory.join("plain.bin"), "old")
+            .await
+            .unwrap();
+        let summary = d.download_one(&corrupted).await.unwrap();
+        assert!(matches!(summary.status(), Status::ChecksumMismatch(_)));
+        assert!(!directory.join("plain.bin.part").exists());
+        let written = tokio::fs::read(directory.join("plain.bin")).await.unwrap();
+        assert_eq!(written, b"old");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_piece_hashes() {
+        use sha2::{Digest, Sha256};
+
+        let server = crate::testing::MockServer::start().await;
+        let url = server.resumable("/pieces.bin", "0123456789").await;
+        let directory = std::env::temp_dir().join("trauma-test-pieces");
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        // The first piece is corrupted, and the second one incomplete.
+        tokio::fs::write(directory.join("pieces.bin"), "X12345")
+            .await
+            .unwrap();
+        let hashes = [&b"0123"[..], b"4567", b"89"]
+            .map(|piece| Sha256::digest(piece).into())
+            .to_vec();
+        let download = Download::try_from(&url)
+            .unwrap()
+            .with_piece_hashes(4, hashes);
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .use_temp_file(false)
+            .build();
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        assert_eq!(summary.resumed_from(), 4);
+        assert_eq!(
+            tokio::fs::read(directory.join("pieces.bin")).await.unwrap(),
+            b"0123456789"
+        );
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_content_addressed() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.non_resumable("/hello.txt", "hello world").await;
+        let directory = std::env::temp_dir().join("trauma-test-cas");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .content_addressed(ChecksumAlgorithm::Sha256)
+            .content_prefix_len(2)
+            .build();
+        let summary = d
+            .download_one(&Download::try_from(&url).unwrap())
+            .await
+            .unwrap();
+        let expected = directory
+            .join("b9")
+            .join("4d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9");
+        assert_eq!(summary.path(), Some(expected.as_path()));
+        assert_eq!(tokio::fs::read(&expected).await.unwrap(), b"hello world");
+        assert!(!directory.join("hello.txt").exists());
+        let _ = tokio::fs::remove_dir_all(&directory).await;
     }
 
-    /// Add the http header
-    ///
-    /// # Example
-    ///
-    /// You can use the `.header()` chain to add multiple headers
-    ///
-    /// ```
-    /// use reqwest::header::{self, HeaderValue};
-    /// use trauma::downloader::DownloaderBuilder;
-    ///
-    /// const FIREFOX_UA: &str =
-    /// "Mozilla/5.0 (X11; Linux x86_64; rv:109.0) Gecko/20100101 Firefox/109.0";
-    ///
-    /// let ua = HeaderValue::from_str(FIREFOX_UA).expect("Invalid UA");
-    /// let auth = HeaderValue::from_str("Basic aGk6MTIzNDU2Cg==").expect("Invalid auth");
-    ///
-    /// let builder = DownloaderBuilder::new()
-    ///     .header(header::USER_AGENT, ua)
-    ///     .header(header::AUTHORIZATION, auth)
-    ///     .build();
-    /// ```
-    ///
-    /// If you need to pass in a `HeaderMap`, instead of calling `.header()` multiple times.
-    /// See also [`headers()`].
-    ///
-    /// [`headers()`]: DownloaderBuilder::headers
-    pub fn header<K: IntoHeaderName>(mut self, name: K, value: HeaderValue) -> Self {
-        let mut new = self.new_header();
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_estimate() {
+        let server = crate::testing::MockServer::start().await;
+        let downloads = [
+            server.resumable("/a.bin", vec![0u8; 1000]).await,
+            server.non_resumable("/b.bin", vec![0u8; 500]).await,
+        ]
+        .map(|url| Download::try_from(&url).unwrap());
+        let d = DownloaderBuilder::hidden().build();
+        let estimate = d.estimate(&downloads).await.unwrap();
+        assert_eq!(estimate.total_bytes(), 1500);
+        assert_eq!(estimate.unknown_sizes(), 0);
+        assert!(estimate.throughput().is_some());
+        assert!(estimate.duration().is_some());
+    }
 
-        new.insert(name, value);
+    #[tokio::test]
+    async fn test_wave_size() {
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::TcpListener,
+        };
 
-        self.0.headers = Some(new);
-        self
-    }
+        // Record the number of requests in flight when each one arrives, the
+        // first downloads of each wave being the slowest.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let arrivals = Arc::new(Mutex::new(Vec::new()));
+        let recorded = arrivals.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let in_flight = in_flight.clone();
+                let recorded = recorded.clone();
+                tokio::spawn(async move {
+                    let mut request = [0u8; 1024];
+                    let n = socket.read(&mut request).await.unwrap();
+                    let request = String::from_utf8_lossy(&request[..n]);
+                    let slow = ["/wave-0 ", "/wave-2 "].iter().any(|p| request.contains(p));
+                    let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    recorded.lock().unwrap().push(current);
+                    let delay = if slow { 300 } else { 100 };
+                    tokio::time::sleep(Duration::from_millis(delay)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    socket
+                        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 4\r\nconnection: close\r\n\r\ndata")
+                        .await
+                        .unwrap();
+                });
+            }
+        });
 
-    /// Create the [`Downloader`] with the specified options.
-    pub fn build(self) -> Downloader {
-        Downloader {
-            directory: self.0.directory,
-            retries: self.0.retries,
-            concurrent_downloads: self.0.concurrent_downloads,
-            style_options: self.0.style_options,
-            resumable: self.0.resumable,
-            headers: self.0.headers,
+        let downloads: Vec<_> = (0..5)
+            .map(|i| Download::try_from(format!("http://{}/wave-{}", addr, i).as_str()).unwrap())
+            .collect();
+        let directory = std::env::temp_dir().join("trauma-test-waves");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .resumable(false)
+            .wave_size(2)
+            .build();
+        let summaries = d.download(&downloads).await.unwrap();
+        assert_eq!(summaries.len(), 5);
+        assert!(summaries.iter().all(|s| s.status() == &Status::Success));
+        for (summary, download) in summaries.iter().zip(&downloads) {
+            assert_eq!(summary.download().url, download.url);
         }
+
+        // Each wave only starts once the previous one is complete.
+        let arrivals = arrivals.lock().unwrap().clone();
+        let peaks: Vec<_> = arrivals.chunks(2).map(|wave| wave.iter().max()).collect();
+        assert_eq!(peaks, [Some(&2), Some(&2), Some(&1)]);
+        let _ = tokio::fs::remove_dir_all(&directory).await;
     }
-}
 
-impl Default for DownloaderBuilder {
-    fn default() -> Self {
-        Self(Downloader {
-            directory: std::env::current_dir().unwrap_or_default(),
-            retries: Downloader::DEFAULT_RETRIES,
-            concurrent_downloads: Downloader::DEFAULT_CONCURRENT_DOWNLOADS,
-            style_options: StyleOptions::default(),
-            resumable: true,
-            headers: None,
-        })
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_download_ordered_stream() {
+        use wiremock::{matchers, Mock, ResponseTemplate};
+
+        let server = crate::testing::MockServer::start().await;
+        Mock::given(matchers::path("/slow.txt"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string("slow")
+                    .set_delay(Duration::from_millis(300)),
+            )
+            .mount(server.inner())
+            .await;
+        let fast = server.non_resumable("/fast.txt", "fast").await;
+        let downloads = [
+            Download::try_from(&server.url("/slow.txt")).unwrap(),
+            Download::try_from(&fast).unwrap(),
+        ];
+        let directory = std::env::temp_dir().join("trauma-test-ordered");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .channel_capacity(1)
+            .build();
+        let summaries: Vec<_> = d.download_ordered_stream(&downloads).collect().await;
+        let filenames: Vec<_> = summaries
+            .into_iter()
+            .map(|s| s.unwrap().download().filename.clone())
+            .collect();
+        assert_eq!(filenames, ["slow.txt", "fast.txt"]);
+        let _ = tokio::fs::remove_dir_all(&directory).await;
     }
-}
 
-/// Define the [`Downloader`] options.
-///
-/// By default, the main progress bar will stay on the screen upon completion,
-/// but the child ones will be cleared once complete.
-#[derive(Debug, Clone)]
-pub struct StyleOptions {
-    /// Style options for the main progress bar.
-    main: ProgressBarOpts,
-    /// Style options for the child progress bar(s).
-    child: ProgressBarOpts,
-}
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_channel_capacity() {
+        let server = crate::testing::MockServer::start().await;
+        let mut downloads = Vec::new();
+        for i in 0..6 {
+            let url = server.non_resumable(&format!("/{}.txt", i), "data").await;
+            downloads.push(Download::try_from(&url).unwrap());
+        }
+        let directory = std::env::temp_dir().join("trauma-test-channel-capacity");
+        for (capacity, expected) in [(1, 3), (32, 6)] {
+            let _ = tokio::fs::remove_dir_all(&directory).await;
+            server.inner().reset().await;
+            for i in 0..6 {
+                server.non_resumable(&format!("/{}.txt", i), "data").await;
+            }
+            let d = DownloaderBuilder::hidden()
+                .directory(directory.clone())
+                .concurrent_downloads(1)
+                .resumable(false)
+                .channel_capacity(capacity)
+                .build();
+            let mut summaries = d.download_ordered_stream(&downloads);
+            summaries.next().await.unwrap().unwrap();
 
-impl Default for StyleOptions {
-    fn default() -> Self {
-        Self {
-            main: ProgressBarOpts {
-                template: Some(ProgressBarOpts::TEMPLATE_BAR_WITH_POSITION.into()),
-                progress_chars: Some(ProgressBarOpts::CHARS_FINE.into()),
-                enabled: true,
-                clear: false,
-            },
-            child: ProgressBarOpts::with_pip_style(),
+            // A slow consumer blocks the downloads once the buffer is full:
+            // one summary is buffered, and another one waits to be sent.
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            let requests = server.inner().received_requests().await.unwrap();
+            assert_eq!(requests.len(), expected, "capacity {}", capacity);
+            assert_eq!(summaries.count().await, 5);
         }
+        let _ = tokio::fs::remove_dir_all(&directory).await;
     }
-}
 
-impl StyleOptions {
-    /// Create new [`Downloader`] [`StyleOptions`].
-    pub fn new(main: ProgressBarOpts, child: ProgressBarOpts) -> Self {
-        Self { main, child }
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_download_into_file() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.non_resumable("/body.txt", "body").await;
+        let path = std::env::temp_dir().join("trauma-test-into-file.txt");
+        let mut file = tokio::fs::File::create(&path).await.unwrap();
+        file.write_all(b"header ").await.unwrap();
+        let d = DownloaderBuilder::hidden().build();
+        let summary = d
+            .download_into_file(&Download::try_from(&url).unwrap(), file)
+            .await;
+        assert_eq!(summary.status(), &Status::Success);
+        assert_eq!(summary.size(), 4);
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), b"header body");
+        let _ = tokio::fs::remove_file(&path).await;
     }
 
-    /// Set the options for the main progress bar.
-    pub fn set_main(&mut self, main: ProgressBarOpts) {
-        self.main = main;
-    }
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_download_to_bytes() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.non_resumable("/manifest.json", "{}").await;
+        let d = DownloaderBuilder::hidden().build();
+        let bytes = d
+            .download_to_bytes(&Download::try_from(&url).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(bytes, b"{}");
 
-    /// Set the options for the child progress bar.
-    pub fn set_child(&mut self, child: ProgressBarOpts) {
-        self.child = child;
+        let missing = Download::try_from(&server.url("/missing.json")).unwrap();
+        assert!(d.download_to_bytes(&missing).await.is_err());
     }
 
-    /// Return `false` if neither the main nor the child bar is enabled.
-    pub fn is_enabled(self) -> bool {
-        self.main.enabled || self.child.enabled
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_download_to_writer() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.resumable("/hello.txt", "hello world").await;
+        let d = DownloaderBuilder::hidden().build();
+
+        // The range of the download is honored.
+        let mut written = Vec::new();
+        let download = Download::try_from(&url).unwrap().range(6, None);
+        let summary = d.download_to_writer(&download, &mut written).await;
+        assert_eq!(summary.status(), &Status::Success);
+        assert_eq!(written, b"world");
+
+        // So is its checksum.
+        let invalid = crate::hash::Checksum::Md5("d41d8cd98f00b204e9800998ecf8427e".into());
+        let download = Download::try_from(&url).unwrap().with_checksum(invalid);
+        let summary = d.download_to_writer(&download, &mut Vec::new()).await;
+        assert!(matches!(summary.status(), Status::ChecksumMismatch(_)));
     }
-}
 
-/// Define the options for a progress bar.
-#[derive(Debug, Clone)]
-pub struct ProgressBarOpts {
-    /// Progress bar template string.
-    template: Option<String>,
-    /// Progression characters set.
-    ///
-    /// There must be at least 3 characters for the following states:
-    /// "filled", "current", and "to do".
-    progress_chars: Option<String>,
-    /// Enable or disable the progress bar.
-    enabled: bool,
-    /// Clear the progress bar once completed.
-    clear: bool,
-}
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_progress_log() {
+        #[derive(Clone, Default)]
+        struct SharedLog(Arc<Mutex<Vec<u8>>>);
 
-impl Default for ProgressBarOpts {
-    fn default() -> Self {
-        Self {
-            template: None,
-            progress_chars: None,
-            enabled: true,
-            clear: true,
+        impl Write for SharedLog {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
         }
+
+        let server = crate::testing::MockServer::start().await;
+        let url = server.non_resumable("/logged.bin", vec![0u8; 2048]).await;
+        let directory = std::env::temp_dir().join("trauma-test-progress-log");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        let log = SharedLog::default();
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .progress_log(log.clone(), Duration::from_millis(10))
+            .retries(0)
+            .build();
+        // The failed downloads are finished too.
+        let downloads = [
+            Download::try_from(&url).unwrap(),
+            Download::try_from("http://localhost:1/failed.bin").unwrap(),
+        ];
+        d.download(&downloads).await.unwrap();
+        let log = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+        let last = log.lines().last().unwrap();
+        assert!(last.starts_with('['));
+        assert!(last.contains("] 2/2 files, 2.00 KiB, "));
+        let _ = tokio::fs::remove_dir_all(&directory).await;
     }
-}
 
-impl ProgressBarOpts {
-    /// Template representing the bar and its position.
-    ///
-    ///`███████████████████████████████████████ 11/12 (99%) eta 00:00:02`
-    pub const TEMPLATE_BAR_WITH_POSITION: &'static str =
-        "{bar:40.blue} {pos:>}/{len} ({percent}%) eta {eta_precise:.blue}";
-    /// Template which looks like the Python package installer pip.
-    ///
-    /// `━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━ 211.23 KiB/211.23 KiB 1008.31 KiB/s eta 0s`
-    pub const TEMPLATE_PIP: &'static str =
-        "{bar:40.green/black} {bytes:>11.green}/{total_bytes:<11.green} {bytes_per_sec:>13.red} eta {eta:.blue}";
-    /// Use increasing quarter blocks as progress characters: `"█▛▌▖  "`.
-    pub const CHARS_BLOCKY: &'static str = "█▛▌▖  ";
-    /// Use fade-in blocks as progress characters: `"█▓▒░  "`.
-    pub const CHARS_FADE_IN: &'static str = "█▓▒░  ";
-    /// Use fine blocks as progress characters: `"█▉▊▋▌▍▎▏  "`.
-    pub const CHARS_FINE: &'static str = "█▉▊▋▌▍▎▏  ";
-    /// Use a line as progress characters: `"━╾─"`.
-    pub const CHARS_LINE: &'static str = "━╾╴─";
-    /// Use rough blocks as progress characters: `"█  "`.
-    pub const CHARS_ROUGH: &'static str = "█  ";
-    /// Use increasing height blocks as progress characters: `"█▇▆▅▄▃▂▁  "`.
-    pub const CHARS_VERTICAL: &'static str = "█▇▆▅▄▃▂▁  ";
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_capture_headers() {
+        use wiremock::{matchers, Mock, ResponseTemplate};
 
-    /// Create a new [`ProgressBarOpts`].
-    pub fn new(
-        template: Option<String>,
-        progress_chars: Option<String>,
-        enabled: bool,
-        clear: bool,
-    ) -> Self {
-        Self {
-            template,
-            progress_chars,
-            enabled,
-            clear,
-        }
+        let server = crate::testing::MockServer::start().await;
+        Mock::given(matchers::path("/headers.txt"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("x-build", "1234")
+                    .set_body_string("body"),
+            )
+            .mount(server.inner())
+            .await;
+        let download = Download::try_from(&server.url("/headers.txt")).unwrap();
+        let directory = std::env::temp_dir().join("trauma-test-capture-headers");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .build();
+        let summary = d.download_one(&download).await.unwrap();
+        assert!(summary.headers().is_empty());
+        tokio::fs::remove_file(directory.join("headers.txt"))
+            .await
+            .unwrap();
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .capture_headers(true)
+            .build();
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(summary.headers()["x-build"], "1234");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
     }
 
-    /// Create a [`ProgressStyle`] based on the provided options.
-    pub fn to_progress_style(self) -> ProgressStyle {
-        let mut style = ProgressStyle::default_bar();
-        if let Some(template) = self.template {
-            style = style.template(&template).unwrap();
-        }
-        if let Some(progress_chars) = self.progress_chars {
-            style = style.progress_chars(&progress_chars);
-        }
-        style
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_check_links() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.non_resumable("/alive.bin", vec![0u8; 64]).await;
+        let downloads = [
+            Download::try_from(&url).unwrap(),
+            Download::try_from(&server.url("/dead.bin")).unwrap(),
+        ];
+        let d = DownloaderBuilder::hidden().build();
+        let links = d.check_links(&downloads).await.unwrap();
+        assert_eq!(links[0].url(), &url);
+        assert_eq!(links[0].result(), &Ok((StatusCode::OK, Some(64))));
+        assert!(!links[1].is_ok());
     }
 
-    /// Create a [`ProgressBar`] based on the provided options.
-    pub fn to_progress_bar(self, len: u64) -> ProgressBar {
-        // Return a hidden Progress bar if we disabled it.
-        if !self.enabled {
-            return ProgressBar::hidden();
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_destination() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.resumable("/object.txt", "object").await;
+        let directory = std::env::temp_dir().join("trauma-test-destination");
+        let bucket = std::env::temp_dir().join("trauma-test-destination-bucket");
+        let _ = tokio::fs::remove_dir_all(&bucket).await;
+        let names = Arc::new(Mutex::new(Vec::new()));
+        let recorded = names.clone();
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .destination(crate::destination::FileSystem::new(&bucket))
+            .post_download(move |_summary, path| {
+                recorded.lock().unwrap().push(path.to_path_buf());
+                Ok(None)
+            })
+            .build();
+        let summary = d
+            .download_one(&Download::new(&url, "nested/object.txt"))
+            .await
+            .unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        assert_eq!(summary.size(), 6);
+        assert_eq!(summary.path(), None);
+        let expected = vec![PathBuf::from("nested/object.txt")];
+        assert_eq!(*names.lock().unwrap(), expected);
+        assert!(!directory.join("nested/object.txt").exists());
+        let written = tokio::fs::read(bucket.join("nested/object.txt"))
+            .await
+            .unwrap();
+        assert_eq!(written, b"object");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        let _ = tokio::fs::remove_dir_all(&bucket).await;
+    }
+
+    #[tokio::test]
+    async fn test_adaptive_concurrency() {
+        let limit = AdaptiveConcurrency::new(1, 4);
+        let download = Download::try_from("https://example.com/file.bin").unwrap();
+        let success =
+            Summary::new(download.clone(), StatusCode::OK, 0, false).with_status(Status::Success);
+        let overloaded = Summary::new(download, StatusCode::TOO_MANY_REQUESTS, 0, false)
+            .fail("429 Too Many Requests");
+
+        // Increase by one on each success, up to the maximum.
+        for _ in 0..5 {
+            let permit = limit.acquire().await;
+            limit.release(permit, &success);
         }
+        assert_eq!(*limit.state.lock().unwrap(), (4, 0));
+        assert_eq!(limit.semaphore.available_permits(), 4);
 
-        // Otherwise returns a ProgressBar with the style.
-        let style = self.to_progress_style();
-        ProgressBar::new(len).with_style(style)
+        // Halve the limit when overloaded, shrinking the semaphore as the
+        // permits are released.
+        let (first, second) = (limit.acquire().await, limit.acquire().await);
+        limit.release(first, &overloaded);
+        assert_eq!(*limit.state.lock().unwrap(), (2, 1));
+        limit.release(second, &success);
+        assert_eq!(*limit.state.lock().unwrap(), (3, 0));
+        assert_eq!(limit.semaphore.available_permits(), 3);
     }
 
-    /// Create a new [`ProgressBarOpts`] which looks like Python pip.
-    pub fn with_pip_style() -> Self {
-        Self {
-            template: Some(ProgressBarOpts::TEMPLATE_PIP.into()),
-            progress_chars: Some(ProgressBarOpts::CHARS_LINE.into()),
-            enabled: true,
-            clear: true,
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_progress_snapshot() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.resumable("/data.bin", vec![0u8; 256]).await;
+        let directory = std::env::temp_dir().join("trauma-test-progress");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .progress_granularity(1024)
+            .build();
+        assert!(d.progress().is_empty());
+        let summaries = d
+            .download(&[Download::try_from(&url).unwrap()])
+            .await
+            .unwrap();
+        let progress = d.progress();
+        assert_eq!(progress.len(), 1);
+        assert_eq!(progress[0].filename(), "data.bin");
+        assert_eq!(progress[0].downloaded(), 256);
+        assert_eq!(progress[0].total(), Some(256));
+        assert!(summaries[0].time_to_first_byte().is_some());
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_on_filename_collision() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.non_resumable("/notes.txt", "new").await;
+        let directory = std::env::temp_dir().join("trauma-test-collision");
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        tokio::fs::write(directory.join("notes.txt"), "old")
+            .await
+            .unwrap();
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .collision_policy(CollisionPolicy::Rename)
+            .on_filename_collision(|path| path.with_extension("txt.1"))
+            .build();
+        d.download_one(&Download::try_from(&url).unwrap())
+            .await
+            .unwrap();
+        let read = |name| tokio::fs::read_to_string(directory.join(name));
+        assert_eq!(read("notes.txt").await.unwrap(), "old");
+        assert_eq!(read("notes.txt.1").await.unwrap(), "new");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_on_filename_collision_resume() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.resumable("/resume.bin", b"abcdefgh".to_vec()).await;
+        let directory = std::env::temp_dir().join("trauma-test-collision-resume");
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        tokio::fs::write(directory.join("resume.bin"), "abcd")
+            .await
+            .unwrap();
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .use_temp_file(false)
+            .collision_policy(CollisionPolicy::Rename)
+            .on_filename_collision(|path| path.with_extension("bin.1"))
+            .build();
+        let download = Download::try_from(&url).unwrap();
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        assert_eq!(summary.resumed_from(), 4);
+        let read = |name| tokio::fs::read(directory.join(name));
+        assert_eq!(read("resume.bin").await.unwrap(), b"abcdefgh");
+        assert!(!directory.join("resume.bin.1").exists());
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_max_redirects() {
+        let server = crate::testing::MockServer::start().await;
+        let target = server.non_resumable("/target.txt", "content").await;
+        let url = server.redirect("/moved.txt", &target).await;
+        let download = Download::try_from(&url).unwrap().with_resumable(false);
+        let directory = std::env::temp_dir().join("trauma-test-redirects");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .max_redirects(0)
+            .build();
+        let summary = d.download_one(&download).await.unwrap();
+        assert!(matches!(summary.status(), Status::Fail(_)));
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .max_redirects(1)
+            .build();
+        let summary = d.download_one(&download).await.unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(all(unix, feature = "testing"))]
+    #[tokio::test]
+    async fn test_follow_symlinks() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.non_resumable("/escape.txt", "data").await;
+        let directory = std::env::temp_dir().join("trauma-test-symlinks");
+        let outside = std::env::temp_dir().join("trauma-test-symlinks-outside");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        tokio::fs::create_dir_all(&outside).await.unwrap();
+        std::os::unix::fs::symlink(&outside, directory.join("link")).unwrap();
+
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .follow_symlinks(false)
+            .build();
+        let summary = d
+            .download_one(&Download::new(&url, "link/escape.txt"))
+            .await
+            .unwrap();
+        match summary.status() {
+            Status::Fail(e) => assert!(e.ends_with("is a symbolic link")),
+            status => panic!("unexpected status {:?}", status),
         }
+        assert!(!outside.join("escape.txt").exists());
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        let _ = tokio::fs::remove_dir_all(&outside).await;
     }
 
-    /// Set to `true` to clear the progress bar upon completion.
-    pub fn set_clear(&mut self, clear: bool) {
-        self.clear = clear;
+    #[cfg(all(unix, feature = "testing"))]
+    #[tokio::test]
+    async fn test_download_to_fifo() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.resumable("/stream.txt", "streamed").await;
+        let directory = std::env::temp_dir().join("trauma-test-fifo");
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        let fifo = directory.join("stream.txt");
+        let _ = tokio::fs::remove_file(&fifo).await;
+        let status = std::process::Command::new("mkfifo")
+            .arg(&fifo)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let reader = {
+            let fifo = fifo.clone();
+            tokio::task::spawn_blocking(move || fs::read_to_string(fifo).unwrap())
+        };
+
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .allow_special_files(true)
+            .build();
+        let summary = d
+            .download_one(&Download::try_from(&url).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        assert_eq!(reader.await.unwrap(), "streamed");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
     }
 
-    /// Create a new [`ProgressBarOpts`] which hides the progress bars.
-    pub fn hidden() -> Self {
-        Self {
-            enabled: false,
-            ..ProgressBarOpts::default()
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_post_download() {
+        let server = crate::testing::MockServer::start().await;
+        let url = server.non_resumable("/report.csv", "a,b").await;
+        let directory = std::env::temp_dir().join("trauma-test-post-download");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .post_download(|summary, _path| Ok(Some(format!("archive/{}", summary.size()).into())))
+            .build();
+        let summary = d
+            .download_one(&Download::try_from(&url).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(summary.status(), &Status::Success);
+        assert_eq!(summary.path(), Some(directory.join("archive/3").as_path()));
+        assert!(!directory.join("report.csv").exists());
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_download_range() {
+        let server = crate::testing::MockServer::start().await;
+        let body: Vec<u8> = (0..64).collect();
+        let directory = std::env::temp_dir().join("trauma-test-range");
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .build();
+
+        // Both the servers honoring and ignoring the range are supported.
+        for url in [
+            server.resumable("/ranged.bin", body.clone()).await,
+            server.non_resumable("/whole.bin", body.clone()).await,
+        ] {
+            let download = Download::try_from(&url).unwrap().range(8, Some(15));
+            let summaries = d.download(std::slice::from_ref(&download)).await.unwrap();
+            assert_eq!(summaries[0].size(), 8);
+            let written = tokio::fs::read(directory.join(&download.filename))
+                .await
+                .unwrap();
+            assert_eq!(written, &body[8..16]);
         }
+        let _ = tokio::fs::remove_dir_all(&directory).await;
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_download_total() {
+        let server = crate::testing::MockServer::start().await;
+        let body: Vec<u8> = (0..64).collect();
+        let directory = std::env::temp_dir().join("trauma-test-total");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        tokio::fs::write(directory.join("resumed.bin.part"), &body[..16])
+            .await
+            .unwrap();
+        let totals = Arc::new(Mutex::new(Vec::new()));
+        let recorded = totals.clone();
+        let d = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .on_progress(move |download, _bytes, total| {
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push((download.filename.clone(), total));
+            })
+            .build();
 
-    #[test]
-    fn test_builder_defaults() {
-        let d = DownloaderBuilder::new().build();
-        assert_eq!(d.retries, Downloader::DEFAULT_RETRIES);
-        assert_eq!(
-            d.concurrent_downloads,
-            Downloader::DEFAULT_CONCURRENT_DOWNLOADS
-        );
+        // The total is the size of the whole file, whether it is resumed or
+        // the server does not advertise its length up front.
+        for url in [
+            server.resumable("/resumed.bin", body.clone()).await,
+            server.non_resumable("/fresh.bin", body.clone()).await,
+        ] {
+            let summary = d
+                .download_one(&Download::try_from(&url).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(summary.status(), &Status::Success);
+        }
+        let totals = totals.lock().unwrap().clone();
+        assert!(totals.iter().any(|(f, _)| f == "resumed.bin"));
+        assert!(totals.iter().any(|(f, _)| f == "fresh.bin"));
+        assert!(totals.iter().all(|(_, total)| *total == Some(64)));
+        let _ = tokio::fs::remove_dir_all(&directory).await;
     }
 }
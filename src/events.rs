@@ -0,0 +1,62 @@
+//! Lifecycle events of the downloads, written as JSON lines.
+//!
+//! They are only written when the `serde` feature is enabled, to the writer
+//! set with
+//! [`DownloaderBuilder::jsonl_events`](crate::downloader::DownloaderBuilder::jsonl_events).
+
+use crate::download::Summary;
+use std::pin::Pin;
+use tokio::{io::AsyncWrite, sync::Mutex};
+
+/// Writer receiving the events, shared by the downloads.
+pub(crate) type EventWriter = Mutex<Pin<Box<dyn AsyncWrite + Send>>>;
+
+/// A change of the state of a download.
+#[cfg_attr(not(feature = "serde"), allow(dead_code))]
+pub(crate) enum Event<'a> {
+    /// The content started to be received.
+    Started { bytes: u64, total: Option<u64> },
+    /// More content was received.
+    Progress { bytes: u64, total: Option<u64> },
+    /// The download is over.
+    Finished(&'a Summary),
+}
+
+/// Writes an event of a download as a JSON line, like
+/// `{"file":"x.zip","event":"progress","bytes":123,"total":456}`.
+///
+/// The errors are ignored, the events being informative only.
+pub(crate) async fn emit(writer: &EventWriter, filename: &str, event: Event<'_>) {
+    #[cfg(feature = "serde")]
+    {
+        use crate::download::Status;
+        use serde_json::json;
+        use tokio::io::AsyncWriteExt;
+
+        let line = match event {
+            Event::Started { bytes, total } => {
+                json!({"file": filename, "event": "started", "bytes": bytes, "total": total})
+            }
+            Event::Progress { bytes, total } => {
+                json!({"file": filename, "event": "progress", "bytes": bytes, "total": total})
+            }
+            Event::Finished(summary) => match summary.status() {
+                Status::Success => {
+                    json!({"file": filename, "event": "completed", "bytes": summary.size()})
+                }
+                Status::Fail(e) | Status::ChecksumMismatch(e) => {
+                    json!({"file": filename, "event": "failed", "error": e})
+                }
+                Status::Skipped(reason) => {
+                    json!({"file": filename, "event": "skipped", "reason": reason.to_string()})
+                }
+                Status::NotStarted => return,
+            },
+        };
+        let mut writer = writer.lock().await;
+        let _ = writer.write_all(format!("{}\n", line).as_bytes()).await;
+        let _ = writer.flush().await;
+    }
+    #[cfg(not(feature = "serde"))]
+    let _ = (writer, filename, event);
+}
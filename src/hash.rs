@@ -0,0 +1,284 @@
+//! Computes the digests of the downloads.
+
+use md5::Md5;
+use sha2::{Digest, Sha256, Sha512};
+use std::{fmt::Write, mem, ops::Range};
+
+/// An incremental hasher computing the digest of a download.
+///
+/// The chunks of a download are fed into the hasher as they are received, and
+/// the resulting digest is recorded on its summary.
+pub trait DynHasher: Send {
+    /// Feed a chunk of data into the hasher.
+    fn update(&mut self, data: &[u8]);
+    /// Return the digest as an hexadecimal string.
+    fn finalize(self: Box<Self>) -> String;
+}
+
+/// The algorithms available to compute the digests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ChecksumAlgorithm {
+    /// SHA-256.
+    Sha256,
+    /// SHA-512.
+    Sha512,
+    /// MD5.
+    Md5,
+}
+
+impl ChecksumAlgorithm {
+    /// Create a new hasher computing digests with this algorithm.
+    pub fn hasher(self) -> Box<dyn DynHasher> {
+        match self {
+            ChecksumAlgorithm::Sha256 => Box::new(Sha256Hasher::default()),
+            ChecksumAlgorithm::Sha512 => Box::new(Sha512Hasher::default()),
+            ChecksumAlgorithm::Md5 => Box::new(Md5Hasher::default()),
+        }
+    }
+}
+
+/// Computes SHA-256 digests.
+#[derive(Debug, Clone, Default)]
+pub struct Sha256Hasher(Sha256);
+
+impl DynHasher for Sha256Hasher {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> String {
+        to_hex(&self.0.finalize())
+    }
+}
+
+/// Computes SHA-512 digests.
+#[derive(Debug, Clone, Default)]
+pub struct Sha512Hasher(Sha512);
+
+impl DynHasher for Sha512Hasher {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> String {
+        to_hex(&self.0.finalize())
+    }
+}
+
+/// Computes MD5 digests.
+#[derive(Debug, Clone, Default)]
+pub struct Md5Hasher(Md5);
+
+impl DynHasher for Md5Hasher {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> String {
+        to_hex(&self.0.finalize())
+    }
+}
+
+/// The expected digest of a download, as an hexadecimal string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Checksum {
+    /// SHA-256 digest.
+    Sha256(String),
+    /// SHA-512 digest.
+    Sha512(String),
+    /// MD5 digest.
+    Md5(String),
+}
+
+impl Checksum {
+    /// Get the algorithm computing the digest.
+    pub fn algorithm(&self) -> ChecksumAlgorithm {
+        match self {
+            Checksum::Sha256(_) => ChecksumAlgorithm::Sha256,
+            Checksum::Sha512(_) => ChecksumAlgorithm::Sha512,
+            Checksum::Md5(_) => ChecksumAlgorithm::Md5,
+        }
+    }
+
+    /// Get the expected digest.
+    pub fn expected(&self) -> &str {
+        match self {
+            Checksum::Sha256(digest) | Checksum::Sha512(digest) | Checksum::Md5(digest) => digest,
+        }
+    }
+
+    /// Check whether a digest matches the expected one, ignoring the case.
+    pub fn matches(&self, digest: &str) -> bool {
+        self.expected().trim().eq_ignore_ascii_case(digest)
+    }
+}
+
+/// The SHA-256 digests of the fixed-size pieces of a download, like the piece
+/// hashes of BitTorrent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PieceHashes {
+    /// Size of the pieces in bytes, the last one being possibly shorter.
+    pub piece_size: u64,
+    /// Digests of the pieces, in order.
+    pub hashes: Vec<[u8; 32]>,
+}
+
+impl PieceHashes {
+    /// Create the piece hashes of a download.
+    pub fn new(piece_size: u64, hashes: Vec<[u8; 32]>) -> Self {
+        Self {
+            piece_size: piece_size.max(1),
+            hashes,
+        }
+    }
+
+    /// Get the byte range of a piece, which may extend past the end of the
+    /// file for the last piece.
+    pub fn range(&self, index: usize) -> Range<u64> {
+        let start = index as u64 * self.piece_size;
+        start..start + self.piece_size
+    }
+
+    /// Check whether the content of a piece matches its digest.
+    pub fn verify(&self, index: usize, data: &[u8]) -> bool {
+        self.hashes
+            .get(index)
+            .is_some_and(|hash| Sha256::digest(data).as_slice() == hash)
+    }
+}
+
+/// Verifies the pieces of a download as its content is received.
+pub(crate) struct PieceVerifier<'a> {
+    /// Expected digests of the pieces.
+    pieces: &'a PieceHashes,
+    /// Index of the piece being received.
+    index: usize,
+    /// Number of bytes of the piece received so far.
+    filled: u64,
+    /// Hasher of the piece being received.
+    hasher: Sha256,
+    /// Indices of the corrupted pieces.
+    corrupted: Vec<usize>,
+}
+
+impl<'a> PieceVerifier<'a> {
+    /// Create a verifier expecting the content from the first piece.
+    pub(crate) fn new(pieces: &'a PieceHashes) -> Self {
+        Self {
+            pieces,
+            index: 0,
+            filled: 0,
+            hasher: Sha256::new(),
+            corrupted: Vec::new(),
+        }
+    }
+
+    /// Expect the content from the first piece again.
+    pub(crate) fn reset(&mut self) {
+        *self = Self::new(self.pieces);
+    }
+
+    /// Get the number of bytes of the complete pieces within `len` bytes.
+    pub(crate) fn complete_len(&self, len: u64) -> u64 {
+        len - len % self.pieces.piece_size
+    }
+
+    /// Feed the next bytes of the content, verifying the pieces they
+    /// complete.
+    pub(crate) fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let n = (self.pieces.piece_size - self.filled).min(data.len() as u64) as usize;
+            self.hasher.update(&data[..n]);
+            self.filled += n as u64;
+            data = &data[n..];
+            if self.filled == self.pieces.piece_size {
+                self.finish_piece();
+            }
+        }
+    }
+
+    /// Verify the piece being received, and move on to the next one.
+    fn finish_piece(&mut self) {
+        let digest = mem::take(&mut self.hasher).finalize();
+        if self.pieces.hashes.get(self.index).map(|h| h.as_slice()) != Some(digest.as_slice()) {
+            self.corrupted.push(self.index);
+        }
+        self.index += 1;
+        self.filled = 0;
+    }
+
+    /// Verify the last piece, which may be shorter, and return the indices
+    /// of the corrupted pieces, including the missing ones.
+    pub(crate) fn finish(mut self) -> Vec<usize> {
+        if self.filled > 0 {
+            self.finish_piece();
+        }
+        self.corrupted.extend(self.index..self.pieces.hashes.len());
+        self.corrupted
+    }
+}
+
+/// Encode bytes as a lowercase hexadecimal string.
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, b| {
+        let _ = write!(hex, "{:02x}", b);
+        hex
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sha256_hasher() {
+        let mut hasher: Box<dyn DynHasher> = Box::new(Sha256Hasher::default());
+        hasher.update(b"hello ");
+        hasher.update(b"world");
+        assert_eq!(
+            hasher.finalize(),
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+    }
+
+    #[test]
+    fn test_checksum_matches() {
+        let checksum = Checksum::Sha512(String::from("309ECC489C12D6EB4CC40F50C902F2B4D0ED77EE511A7C7A9BCD3CA86D4CD86F989DD35BC5FF499670DA34255B45B0CFD830E81F605DCF7DC5542E93AE9CD76F"));
+        let mut hasher = checksum.algorithm().hasher();
+        hasher.update(b"hello world");
+        assert!(checksum.matches(&hasher.finalize()));
+        assert!(!checksum.matches("309ecc48"));
+    }
+
+    #[test]
+    fn test_piece_verifier() {
+        let content = b"0123456789";
+        let hashes = content
+            .chunks(4)
+            .map(|piece| Sha256::digest(piece).into())
+            .collect();
+        let pieces = PieceHashes::new(4, hashes);
+        assert_eq!(pieces.range(2), 8..12);
+        assert!(pieces.verify(2, b"89"));
+
+        // The chunks do not need to be aligned on the pieces.
+        let mut verifier = PieceVerifier::new(&pieces);
+        verifier.update(b"012");
+        verifier.update(b"34X67");
+        verifier.update(b"89");
+        assert_eq!(verifier.finish(), [1]);
+
+        let mut verifier = PieceVerifier::new(&pieces);
+        assert_eq!(verifier.complete_len(6), 4);
+        verifier.update(b"01234567");
+        assert_eq!(verifier.finish(), [2]);
+    }
+
+    #[test]
+    fn test_md5_hasher() {
+        let mut hasher: Box<dyn DynHasher> = Box::new(Md5Hasher::default());
+        hasher.update(b"hello world");
+        assert_eq!(hasher.finalize(), "5eb63bbbe01eeed093cb22bb8f5acdc3");
+    }
+}
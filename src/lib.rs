@@ -1,10 +1,30 @@
 //! Trauma is crate aiming at providing a simple way to download files
 //! asynchronously via HTTP(S).
 
+mod cache;
+mod compression;
+#[cfg(feature = "serde")]
+pub mod config;
+pub mod destination;
 pub mod download;
 pub mod downloader;
+mod events;
+pub mod hash;
+mod middleware;
+mod netrc;
+#[cfg(feature = "rustls")]
+mod pinning;
+mod ranges;
+#[cfg(feature = "report")]
+pub mod report;
+mod telemetry;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod throttle;
 
-use std::io;
+use download::{Download, Summary};
+use downloader::DownloaderBuilder;
+use std::{io, path::PathBuf};
 use thiserror::Error;
 
 /// Errors that can happen when using Trauma.
@@ -28,4 +48,51 @@ pub enum Error {
         #[from]
         source: reqwest::Error,
     },
+    /// Error while deserializing JSON.
+    #[cfg(feature = "serde")]
+    #[error("JSON error")]
+    Json {
+        #[from]
+        source: serde_json::Error,
+    },
+}
+
+impl From<reqwest_middleware::Error> for Error {
+    fn from(e: reqwest_middleware::Error) -> Self {
+        match e {
+            reqwest_middleware::Error::Reqwest(source) => Self::Reqwest { source },
+            reqwest_middleware::Error::Middleware(e) => Self::Internal(e.to_string()),
+        }
+    }
+}
+
+/// Downloads a list of URLs into a directory, with the default settings.
+///
+/// This is a shortcut for simple use cases: the URLs are turned into
+/// [`Download`]s with [`Download::from_urls`], then downloaded by a default
+/// [`Downloader`](downloader::Downloader). Use the [`DownloaderBuilder`] for
+/// anything more complex.
+///
+/// Fails if one of the URLs is not valid, before downloading anything.
+///
+/// # Example
+///
+/// ```no_run
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), trauma::Error> {
+/// let summaries = trauma::download_all(["https://example.com/a.zip"], "output").await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn download_all<I, S>(
+    urls: I,
+    directory: impl Into<PathBuf>,
+) -> Result<Vec<Summary>, Error>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let downloads = Download::from_urls(urls)?;
+    let downloader = DownloaderBuilder::new().directory(directory.into()).build();
+    downloader.download(&downloads).await
 }
@@ -0,0 +1,357 @@
+//! Middlewares used by the download controller.
+
+use crate::telemetry;
+use http::Extensions;
+use reqwest::{Request, Response, StatusCode};
+use reqwest_middleware::{Error, Middleware, Next, Result};
+use reqwest_retry::{
+    DefaultRetryableStrategy, RetryDecision, RetryPolicy, RetryTransientMiddleware, Retryable,
+    RetryableStrategy,
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Retries transient failures, but lets streaming requests go through.
+///
+/// [`RetryTransientMiddleware`] errors when given a request with a streaming
+/// body, like a multipart form, since such a request cannot be cloned to be
+/// replayed. These requests are sent only once instead.
+///
+/// Unless specified otherwise, the requests using a non-idempotent method,
+/// like `POST`, are not retried either, to avoid duplicating their effects.
+pub(crate) struct RetryMiddleware<T>
+where
+    T: RetryPolicy + Send + Sync + 'static,
+{
+    inner: RetryTransientMiddleware<T, RetryStrategy>,
+    /// Retry the requests using non-idempotent methods.
+    retry_non_idempotent: bool,
+}
+
+impl<T> RetryMiddleware<T>
+where
+    T: RetryPolicy + Send + Sync + 'static,
+{
+    /// Create a new [`RetryMiddleware`] using the provided retry policy and
+    /// strategy.
+    pub(crate) fn new(retry_policy: T, strategy: RetryStrategy) -> Self {
+        Self {
+            inner: RetryTransientMiddleware::new_with_policy_and_strategy(retry_policy, strategy),
+            retry_non_idempotent: false,
+        }
+    }
+
+    /// Set to `true` to retry the requests using non-idempotent methods.
+    pub(crate) fn retry_non_idempotent(self, retry_non_idempotent: bool) -> Self {
+        Self {
+            retry_non_idempotent,
+            ..self
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> Middleware for RetryMiddleware<T>
+where
+    T: RetryPolicy + Send + Sync + 'static,
+{
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        if !self.retry_non_idempotent && !req.method().is_idempotent() {
+            return next.run(req, extensions).await;
+        }
+        match req.try_clone() {
+            Some(_) => self.inner.handle(req, extensions, next).await,
+            None => next.run(req, extensions).await,
+        }
+    }
+}
+
+/// Classifies the transient failures like the default strategy, except for
+/// the responses whose status code must not be retried.
+#[derive(Debug, Default)]
+pub(crate) struct RetryStrategy {
+    /// Status codes which are never retried.
+    pub(crate) no_retry_on: Vec<StatusCode>,
+}
+
+impl RetryableStrategy for RetryStrategy {
+    fn handle(&self, res: &std::result::Result<Response, Error>) -> Option<Retryable> {
+        let retryable = match res {
+            Ok(res) if self.no_retry_on.contains(&res.status()) => None,
+            _ => DefaultRetryableStrategy.handle(res),
+        };
+        if retryable == Some(Retryable::Transient) {
+            telemetry::request_retried();
+        }
+        retryable
+    }
+}
+
+tokio::task_local! {
+    /// Number of retries left for the requests of the current call, if capped.
+    pub(crate) static RETRY_BUDGET: Arc<AtomicUsize>;
+}
+
+/// Draws every retry allowed by a policy from the budget of the current
+/// call, if any.
+///
+/// The budget is scoped with [`RETRY_BUDGET`], so the concurrent calls
+/// sharing a client do not draw from each other's budget. Once the budget is
+/// exhausted, the failures are not retried anymore.
+pub(crate) struct RetryBudget {
+    /// Policy deciding whether to retry.
+    pub(crate) policy: Arc<dyn RetryPolicy + Send + Sync>,
+}
+
+impl RetryPolicy for RetryBudget {
+    fn should_retry(&self, request_start_time: SystemTime, n_past_retries: u32) -> RetryDecision {
+        let decision = self.policy.should_retry(request_start_time, n_past_retries);
+        let exhausted = || {
+            RETRY_BUDGET
+                .try_with(|budget| {
+                    budget
+                        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |b| b.checked_sub(1))
+                        .is_err()
+                })
+                .unwrap_or(false)
+        };
+        match decision {
+            RetryDecision::Retry { .. } if exhausted() => RetryDecision::DoNotRetry,
+            _ => decision,
+        }
+    }
+}
+
+/// Limits the rate of the requests sent to each host.
+///
+/// Every host gets its own token bucket holding up to `requests` tokens, and
+/// refilled at a rate of `requests` per `per`. A request waits until a token
+/// is available for its host.
+pub(crate) struct RateLimitMiddleware {
+    /// Maximum number of requests per period.
+    requests: f64,
+    /// Period of time during which the requests are counted.
+    per: Duration,
+    /// Token buckets indexed by host.
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+/// Represents the state of a token bucket.
+struct Bucket {
+    /// Available tokens.
+    tokens: f64,
+    /// Last time the bucket was refilled.
+    refilled_at: Instant,
+}
+
+impl RateLimitMiddleware {
+    /// Create a new [`RateLimitMiddleware`] allowing `requests` per `per`.
+    pub(crate) fn new(requests: u32, per: Duration) -> Self {
+        Self {
+            requests: f64::from(requests.max(1)),
+            per,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token for the host, or returns how long to wait for one.
+    fn try_acquire(&self, host: &str) -> Option<Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let now = Instant::now();
+        let bucket = buckets.entry(host.to_string()).or_insert(Bucket {
+            tokens: self.requests,
+            refilled_at: now,
+        });
+
+        // Refill the bucket according to the elapsed time.
+        let rate = self.requests / self.per.as_secs_f64().max(f64::EPSILON);
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(self.requests);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return None;
+        }
+        Some(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for RateLimitMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        let host = req.url().host_str().unwrap_or_default().to_string();
+        while let Some(wait) = self.try_acquire(&host) {
+            tokio::time::sleep(wait).await;
+        }
+        next.run(req, extensions).await
+    }
+}
+
+/// Fails the requests sent to a host fast once it has failed repeatedly.
+///
+/// A host is considered unhealthy after `failures` consecutive failures within
+/// `window`, i.e. errors or server error statuses, retries included. Its
+/// requests are rejected until the window elapses, then let through again.
+pub(crate) struct CircuitBreakerMiddleware {
+    /// Number of consecutive failures opening the circuit.
+    failures: u32,
+    /// Period of time during which the failures are counted.
+    window: Duration,
+    /// Failure counters indexed by host.
+    hosts: Mutex<HashMap<String, Failures>>,
+}
+
+/// Represents the consecutive failures of a host.
+struct Failures {
+    /// Number of consecutive failures.
+    count: u32,
+    /// Time of the first consecutive failure.
+    since: Instant,
+}
+
+impl CircuitBreakerMiddleware {
+    /// Create a new [`CircuitBreakerMiddleware`] opening after `failures`
+    /// consecutive failures within `window`.
+    pub(crate) fn new(failures: u32, window: Duration) -> Self {
+        Self {
+            failures: failures.max(1),
+            window,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns `true` if the requests to the host are rejected.
+    fn is_open(&self, host: &str) -> bool {
+        let hosts = self.hosts.lock().unwrap();
+        hosts
+            .get(host)
+            .is_some_and(|f| f.count >= self.failures && f.since.elapsed() <= self.window)
+    }
+
+    /// Records the outcome of a request to the host.
+    fn record(&self, host: &str, failed: bool) {
+        let mut hosts = self.hosts.lock().unwrap();
+        if !failed {
+            hosts.remove(host);
+            return;
+        }
+        let now = Instant::now();
+        let failures = hosts.entry(host.to_string()).or_insert(Failures {
+            count: 0,
+            since: now,
+        });
+        if now.duration_since(failures.since) > self.window {
+            failures.count = 0;
+            failures.since = now;
+        }
+        failures.count += 1;
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for CircuitBreakerMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        let host = req.url().host_str().unwrap_or_default().to_string();
+        if self.is_open(&host) {
+            return Err(Error::middleware(crate::Error::Internal(format!(
+                "circuit breaker open for host {host}"
+            ))));
+        }
+        let res = next.run(req, extensions).await;
+        let failed = match &res {
+            Ok(res) => res.status().is_server_error(),
+            Err(_) => true,
+        };
+        self.record(&host, failed);
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit_per_host() {
+        let limiter = RateLimitMiddleware::new(2, Duration::from_secs(60));
+        assert!(limiter.try_acquire("a.com").is_none());
+        assert!(limiter.try_acquire("a.com").is_none());
+        assert!(limiter.try_acquire("a.com").is_some());
+        assert!(limiter.try_acquire("b.com").is_none());
+    }
+
+    #[test]
+    fn test_circuit_breaker_per_host() {
+        let breaker = CircuitBreakerMiddleware::new(2, Duration::from_secs(60));
+        breaker.record("a.com", true);
+        assert!(!breaker.is_open("a.com"));
+        breaker.record("a.com", true);
+        assert!(breaker.is_open("a.com"));
+        assert!(!breaker.is_open("b.com"));
+        breaker.record("a.com", false);
+        assert!(!breaker.is_open("a.com"));
+    }
+
+    #[test]
+    fn test_retry_budget() {
+        let budget = Arc::new(AtomicUsize::new(2));
+        let policy = RetryBudget {
+            policy: Arc::new(
+                reqwest_retry::policies::ExponentialBackoff::builder().build_with_max_retries(5),
+            ),
+        };
+        let should_retry = || {
+            matches!(
+                policy.should_retry(SystemTime::now(), 0),
+                RetryDecision::Retry { .. }
+            )
+        };
+        RETRY_BUDGET.sync_scope(budget.clone(), || {
+            assert!(should_retry());
+            assert!(should_retry());
+            assert!(!should_retry());
+        });
+        assert_eq!(budget.load(Ordering::SeqCst), 0);
+        // The retries are not capped outside of a scope.
+        assert!(should_retry());
+    }
+
+    #[test]
+    fn test_retry_strategy_no_retry_on() {
+        let response = |status: u16| {
+            Ok(Response::from(
+                http::Response::builder().status(status).body("").unwrap(),
+            ))
+        };
+        let strategy = RetryStrategy {
+            no_retry_on: vec![StatusCode::SERVICE_UNAVAILABLE],
+        };
+        assert!(strategy.handle(&response(503)).is_none());
+        assert!(matches!(
+            strategy.handle(&response(502)),
+            Some(Retryable::Transient)
+        ));
+    }
+}
@@ -0,0 +1,136 @@
+//! Reads credentials from a netrc file, like curl's `--netrc`.
+
+use std::{collections::HashMap, fmt, path::PathBuf};
+
+/// Credentials of the machines listed in a netrc file.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Netrc {
+    /// Credentials indexed by machine name.
+    machines: HashMap<String, Credentials>,
+    /// Credentials of the machines not listed.
+    default: Option<Credentials>,
+}
+
+/// Represents the credentials of a machine.
+#[derive(Clone, Default)]
+pub(crate) struct Credentials {
+    pub(crate) login: String,
+    pub(crate) password: Option<String>,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("login", &self.login)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+impl Netrc {
+    /// Reads the file pointed by the `NETRC` environment variable, or
+    /// `~/.netrc` by default.
+    ///
+    /// Returns `None` if the file cannot be read.
+    pub(crate) fn from_env() -> Option<Self> {
+        let path = match std::env::var_os("NETRC") {
+            Some(path) => PathBuf::from(path),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".netrc"),
+        };
+        let content = std::fs::read_to_string(path).ok()?;
+        Some(Self::parse(&content))
+    }
+
+    /// Parses the content of a netrc file.
+    pub(crate) fn parse(content: &str) -> Self {
+        let mut netrc = Netrc::default();
+        // Machine being parsed, `None` for the default one.
+        let mut current: Option<Option<String>> = None;
+        let mut credentials = Credentials::default();
+        let mut lines = content.lines();
+        while let Some(line) = lines.next() {
+            let mut tokens = line.split_whitespace();
+            while let Some(token) = tokens.next() {
+                match token {
+                    "machine" | "default" => {
+                        if let Some(machine) = current.take() {
+                            netrc.insert(machine, std::mem::take(&mut credentials));
+                        }
+                        current = match token {
+                            "machine" => tokens.next().map(|m| Some(m.to_string())),
+                            _ => Some(None),
+                        };
+                    }
+                    "login" => credentials.login = tokens.next().unwrap_or_default().to_string(),
+                    "password" => credentials.password = tokens.next().map(String::from),
+                    "account" => {
+                        tokens.next();
+                    }
+                    // A macro definition lasts until the next empty line.
+                    "macdef" => {
+                        for line in lines.by_ref() {
+                            if line.trim().is_empty() {
+                                break;
+                            }
+                        }
+                        break;
+                    }
+                    // Comments last until the end of the line.
+                    t if t.starts_with('#') => break,
+                    _ => {}
+                }
+            }
+        }
+        if let Some(machine) = current {
+            netrc.insert(machine, credentials);
+        }
+        netrc
+    }
+
+    /// Returns the credentials of a host, if any.
+    pub(crate) fn credentials(&self, host: &str) -> Option<&Credentials> {
+        self.machines.get(host).or(self.default.as_ref())
+    }
+
+    /// Records the credentials of a machine, keeping the first ones.
+    fn insert(&mut self, machine: Option<String>, credentials: Credentials) {
+        match machine {
+            Some(machine) => {
+                self.machines.entry(machine).or_insert(credentials);
+            }
+            None => {
+                self.default.get_or_insert(credentials);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let netrc = Netrc::parse(
+            "# Credentials\n\
+             machine example.com login alice password s3cret\n\
+             macdef init\n\
+             cd /pub\n\
+             \n\
+             machine files.example.com\n  login bob\n  password hunter2\n\
+             default login anonymous password guest\n",
+        );
+        let c = netrc.credentials("example.com").unwrap();
+        assert_eq!(
+            (c.login.as_str(), c.password.as_deref()),
+            ("alice", Some("s3cret"))
+        );
+        let c = netrc.credentials("files.example.com").unwrap();
+        assert_eq!(
+            (c.login.as_str(), c.password.as_deref()),
+            ("bob", Some("hunter2"))
+        );
+        let c = netrc.credentials("other.com").unwrap();
+        assert_eq!(c.login, "anonymous");
+    }
+}
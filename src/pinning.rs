@@ -0,0 +1,260 @@
+//! Pins the public keys of the servers, rejecting the certificates signed by
+//! a compromised certificate authority.
+//!
+//! This module is only available with the `rustls` feature.
+
+use crate::Error;
+use rustls::{
+    client::{
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        WebPkiServerVerifier,
+    },
+    crypto::ring,
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, OtherError, RootCertStore, SignatureScheme,
+};
+use sha2::{Digest, Sha256};
+use std::{io, sync::Arc};
+
+/// Failure of the downloads from a server whose key is not pinned.
+pub(crate) const PIN_MISMATCH: &str = "certificate pin mismatch";
+
+/// Error of the handshakes with a server whose key is not pinned.
+#[derive(Debug, thiserror::Error)]
+#[error("{}", PIN_MISMATCH)]
+struct PinMismatch;
+
+/// Builds the TLS configuration accepting only the servers whose public key
+/// has one of the SHA-256 digests of `pins`.
+///
+/// The certificate chains are validated against the Mozilla root
+/// certificates.
+pub(crate) fn tls_config(pins: &[Vec<u8>]) -> Result<ClientConfig, Error> {
+    let roots = RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    tls_config_with(pins, roots)
+}
+
+/// Builds the TLS configuration accepting only the servers whose public key
+/// is pinned, and whose certificate chain leads to one of `roots`.
+fn tls_config_with(pins: &[Vec<u8>], roots: RootCertStore) -> Result<ClientConfig, Error> {
+    let provider = Arc::new(ring::default_provider());
+    let inner = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+        .build()
+        .map_err(|e| Error::Internal(e.to_string()))?;
+    let verifier = PinnedVerifier {
+        inner,
+        pins: pins.to_vec(),
+    };
+    Ok(ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| Error::Internal(e.to_string()))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth())
+}
+
+/// Checks whether an error, or one of its sources, is a pin mismatch.
+pub(crate) fn is_pin_mismatch(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(error);
+    while let Some(e) = source {
+        if let Some(rustls::Error::Other(OtherError(other))) = e.downcast_ref() {
+            if other.is::<PinMismatch>() {
+                return true;
+            }
+        }
+        // The I/O errors do not list the error they wrap as their source.
+        source = match e.downcast_ref::<io::Error>().and_then(io::Error::get_ref) {
+            Some(inner) => Some(inner),
+            None => e.source(),
+        };
+    }
+    false
+}
+
+/// Verifies the certificates like the default verifier, then checks the
+/// public key of the server against the pins.
+#[derive(Debug)]
+struct PinnedVerifier {
+    /// Default verifier, validating the certificate chain.
+    inner: Arc<WebPkiServerVerifier>,
+    /// SHA-256 digests of the accepted public keys.
+    pins: Vec<Vec<u8>>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+        check_pin(&self.pins, end_entity)?;
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Checks that the public key of a certificate is pinned.
+fn check_pin(pins: &[Vec<u8>], end_entity: &CertificateDer<'_>) -> Result<(), rustls::Error> {
+    let cert = webpki::EndEntityCert::try_from(end_entity)
+        .map_err(|e| rustls::Error::General(e.to_string()))?;
+    let digest = Sha256::digest(cert.subject_public_key_info().as_ref());
+    match pins.iter().any(|pin| pin.as_slice() == digest.as_slice()) {
+        true => Ok(()),
+        false => Err(rustls::Error::Other(OtherError(Arc::new(PinMismatch)))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rustls::{pki_types::PrivatePkcs8KeyDer, ServerConfig, ServerConnection, StreamOwned};
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+
+    /// Certificate authority signing the certificate of `localhost`, in DER.
+    const CA: &str = concat!(
+        "3082019a3082013fa00302010202145864a66097e7466ee00e962b714e106cf316daf2300a06082a8648ce3d04030230",
+        "193117301506035504030c0e747261756d6120746573742043413020170d3236313031363138323031315a180f323132",
+        "36303932323138323031315a30193117301506035504030c0e747261756d6120746573742043413059301306072a8648",
+        "ce3d020106082a8648ce3d03010703420004394582c8db469ae243f78c3fb33e8113e2f89e10eb033d38324ece497a39",
+        "d8cba2830c8f9869960cf2bc7c2d49b734440c4d93cf9e861097639f5cd826d75954a3633061301d0603551d0e041604",
+        "14d597ea442d9fc0259f1ade779194394a44c80a83301f0603551d23041830168014d597ea442d9fc0259f1ade779194",
+        "394a44c80a83300f0603551d130101ff040530030101ff300e0603551d0f0101ff040403020204300a06082a8648ce3d",
+        "0403020349003046022100e9c427630de0883f1797b998685d7e1fd6150e29e3ad5f80b39f7756fe860b17022100a621",
+        "8058cb673a78b464f5e11301c8dd7d80e8f58037b6cb10e5b6612338322c",
+    );
+
+    /// Certificate of `localhost`, in DER.
+    const CERTIFICATE: &str = concat!(
+        "308201be30820164a003020102021440e82956470e7ebb7c3fb6c2bb10b566c7959bd5300a06082a8648ce3d04030230",
+        "193117301506035504030c0e747261756d6120746573742043413020170d3236313031363138323031315a180f323132",
+        "36303932323138323031315a30143112301006035504030c096c6f63616c686f73743059301306072a8648ce3d020106",
+        "082a8648ce3d030107034200043b9c13415e6d540364b5982b83105ef721f8835eaa2e01fc2a09ddb1beeb88de6053b7",
+        "0e048101a1f1bdf8fd5c7c84b59c576bc7a0bfc1234397499e5a889098a3818c30818930140603551d11040d300b8209",
+        "6c6f63616c686f7374300c0603551d130101ff0402300030130603551d25040c300a06082b06010505070301300e0603",
+        "551d0f0101ff040403020780301d0603551d0e0416041447376e16b6227c70efb89961ac35007957da1b7f301f060355",
+        "1d23041830168014d597ea442d9fc0259f1ade779194394a44c80a83300a06082a8648ce3d0403020348003045022100",
+        "cd0fd9bee3fcee48312c20c694c49563bdeb5bce77d07e740149f0e0594197d0022076ac2bf5deab60878ffec50d8aa3",
+        "d35d617e0ff027cb9f9da0ad7e1a1e48b035",
+    );
+
+    /// Private key of the certificate, in PKCS #8 DER.
+    const KEY: &str = concat!(
+        "308187020100301306072a8648ce3d020106082a8648ce3d030107046d306b0201010420971a5e11e28241bd20dbac84",
+        "0f22d06c14bc28c0d47712fbadc5ca7cd167eb15a144034200043b9c13415e6d540364b5982b83105ef721f8835eaa2e",
+        "01fc2a09ddb1beeb88de6053b70e048101a1f1bdf8fd5c7c84b59c576bc7a0bfc1234397499e5a889098",
+    );
+
+    /// SHA-256 digest of the public key of the certificate.
+    const PIN: &str = "75554059b24050edd8665121606e9da07a6667a9f85d64ad30fa23703273bf30";
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// Serves `hello` over TLS on a random local port, returning the port.
+    fn serve() -> u16 {
+        let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![CertificateDer::from(from_hex(CERTIFICATE))],
+                PrivatePkcs8KeyDer::from(from_hex(KEY)).into(),
+            )
+            .unwrap();
+        let config = Arc::new(config);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let connection = ServerConnection::new(config.clone()).unwrap();
+                let mut tls = StreamOwned::new(connection, stream);
+                // The rejected clients abort the handshake.
+                if tls.read(&mut [0; 1024]).is_ok() {
+                    let _ = tls.write_all(
+                        b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello",
+                    );
+                    tls.conn.send_close_notify();
+                    let _ = tls.flush();
+                }
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn test_check_pin() {
+        let cert = CertificateDer::from(from_hex(CERTIFICATE));
+        assert!(check_pin(&[from_hex(PIN)], &cert).is_ok());
+
+        let error = check_pin(&[vec![0; 32]], &cert).unwrap_err();
+        assert!(is_pin_mismatch(&error));
+        let error = rustls::Error::General(PIN_MISMATCH.to_string());
+        assert!(!is_pin_mismatch(&error));
+    }
+
+    #[test]
+    fn test_tls_config() {
+        assert!(tls_config(&[from_hex(PIN)]).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_pinned_connection() {
+        let port = serve();
+        let url = format!("https://localhost:{}/", port);
+        let mut roots = RootCertStore::empty();
+        roots.add(CertificateDer::from(from_hex(CA))).unwrap();
+        let client = |pin: Vec<u8>| {
+            let tls = tls_config_with(&[pin], roots.clone()).unwrap();
+            let client = reqwest::Client::builder()
+                .use_preconfigured_tls(tls)
+                .resolve("localhost", ([127, 0, 0, 1], port).into())
+                .build()
+                .unwrap();
+            reqwest_middleware::ClientBuilder::new(client).build()
+        };
+
+        let res = client(from_hex(PIN)).get(&url).send().await.unwrap();
+        assert_eq!(res.text().await.unwrap(), "hello");
+
+        let error = client(vec![0; 32]).get(&url).send().await.unwrap_err();
+        assert!(is_pin_mismatch(&error));
+    }
+}
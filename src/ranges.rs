@@ -0,0 +1,88 @@
+//! Tracks the byte ranges written to a preallocated file, whose length does
+//! not tell how much of it was actually downloaded.
+
+use std::{fmt, num::ParseIntError, ops::Range, str::FromStr};
+
+/// Byte ranges written to a file, kept sorted and merged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Ranges(Vec<Range<u64>>);
+
+impl Ranges {
+    /// Records a range as written.
+    pub(crate) fn insert(&mut self, range: Range<u64>) {
+        if range.is_empty() {
+            return;
+        }
+        // Merge the ranges overlapping or touching the new one.
+        let first = self.0.partition_point(|r| r.end < range.start);
+        let last = self.0.partition_point(|r| r.start <= range.end);
+        let merged = self.0[first..last]
+            .iter()
+            .fold(range, |acc, r| acc.start.min(r.start)..acc.end.max(r.end));
+        self.0.splice(first..last, [merged]);
+    }
+
+    /// Returns the number of bytes written contiguously from `offset`.
+    pub(crate) fn written_from(&self, offset: u64) -> u64 {
+        self.0
+            .iter()
+            .find(|r| r.contains(&offset))
+            .map_or(0, |r| r.end - offset)
+    }
+
+    /// Returns the end of the last range written.
+    pub(crate) fn end(&self) -> u64 {
+        self.0.last().map_or(0, |r| r.end)
+    }
+}
+
+/// Writes the ranges as `start-end` lines, `end` being exclusive.
+impl fmt::Display for Ranges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for r in &self.0 {
+            writeln!(f, "{}-{}", r.start, r.end)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Ranges {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ranges = Self::default();
+        for line in s.lines().filter(|l| !l.trim().is_empty()) {
+            let (start, end) = line.trim().split_once('-').unwrap_or((line, ""));
+            ranges.insert(start.parse()?..end.parse()?);
+        }
+        Ok(ranges)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ranges() {
+        let mut ranges = Ranges::default();
+        ranges.insert(10..20);
+        ranges.insert(30..40);
+        ranges.insert(0..5);
+        assert_eq!(ranges.to_string(), "0-5\n10-20\n30-40\n");
+
+        // Touching and overlapping ranges are merged.
+        ranges.insert(5..10);
+        ranges.insert(15..35);
+        assert_eq!(ranges.to_string(), "0-40\n");
+        assert_eq!(ranges.written_from(12), 28);
+        assert_eq!(ranges.written_from(40), 0);
+        assert_eq!(ranges.end(), 40);
+
+        assert_eq!(
+            "0-40\n50-60\n".parse::<Ranges>().unwrap().written_from(55),
+            5
+        );
+        assert!("0-x".parse::<Ranges>().is_err());
+    }
+}
@@ -0,0 +1,82 @@
+//! Reports the outcome of the downloads as a table.
+//!
+//! This module is only available with the `report` feature.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use trauma::{download::Download, downloader::DownloaderBuilder, report};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), trauma::Error> {
+//! let downloader = DownloaderBuilder::new().build();
+//! let summaries = downloader
+//!     .download(&[Download::try_from("https://example.com/file.zip")?])
+//!     .await?;
+//! report::print_table(&summaries);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::download::{Status, Summary};
+use comfy_table::Table;
+
+/// Maximum length of the errors displayed, in characters.
+const MAX_ERROR_LENGTH: usize = 50;
+
+/// Builds a table with the file, size, status and error of each download.
+pub fn table(summaries: &[Summary]) -> Table {
+    let mut table = Table::new();
+    table.set_header(vec!["File", "Size", "Status", "Error"]);
+    for s in summaries {
+        let (status, error) = match s.status() {
+            Status::Success => ("✅", String::new()),
+            Status::Fail(e) | Status::ChecksumMismatch(e) => ("❌", truncate(e)),
+            Status::NotStarted => ("🔜", String::new()),
+            Status::Skipped(reason) => ("⏭️", reason.to_string()),
+        };
+        table.add_row(vec![
+            s.download().filename.clone(),
+            s.size().to_string(),
+            status.to_string(),
+            error,
+        ]);
+    }
+    table
+}
+
+/// Prints the table of the downloads to the standard output.
+pub fn print_table(summaries: &[Summary]) {
+    println!("{}", table(summaries));
+}
+
+/// Shortens an error to fit the table.
+fn truncate(error: &str) -> String {
+    match error.char_indices().nth(MAX_ERROR_LENGTH) {
+        Some((end, _)) => format!("{}...", &error[..end]),
+        None => error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::download::{Download, SkipReason};
+    use reqwest::StatusCode;
+
+    #[test]
+    fn test_table() {
+        let download = Download::try_from("https://example.com/file.zip").unwrap();
+        let summary = || Summary::new(download.clone(), StatusCode::OK, 42, false);
+        let summaries = [
+            summary().with_status(Status::Success),
+            summary().with_status(Status::Fail("x".repeat(60))),
+            summary().with_status(Status::Skipped(SkipReason::AlreadyComplete)),
+        ];
+        let table = table(&summaries).to_string();
+        assert!(table.contains("file.zip"));
+        assert!(table.contains(&format!("{}...", "x".repeat(50))));
+        assert!(!table.contains(&"x".repeat(51)));
+        assert!(table.contains("the file was already fully downloaded"));
+    }
+}
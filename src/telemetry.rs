@@ -0,0 +1,67 @@
+//! Metrics emitted through the [`metrics`](https://docs.rs/metrics) facade.
+//!
+//! They are only recorded when the `metrics` feature is enabled, and exported
+//! by the recorder installed by the application, if any:
+//!
+//! - `trauma_downloads_started`: counter of the started downloads.
+//! - `trauma_downloads_succeeded`: counter of the successful downloads.
+//! - `trauma_downloads_failed`: counter of the failed downloads.
+//! - `trauma_downloads_skipped`: counter of the skipped downloads.
+//! - `trauma_requests_retried`: counter of the transient request failures,
+//!   which are retried unless the retries are exhausted.
+//! - `trauma_downloads_in_flight`: gauge of the running downloads.
+//! - `trauma_download_duration_seconds`: histogram of the download durations.
+//! - `trauma_download_size_bytes`: histogram of the download sizes.
+
+use crate::download::Summary;
+use std::time::Duration;
+
+/// Records a started download, which is considered in flight until the
+/// returned guard is dropped.
+pub(crate) fn download_started() -> InFlight {
+    #[cfg(feature = "metrics")]
+    {
+        ::metrics::counter!("trauma_downloads_started").increment(1);
+        ::metrics::gauge!("trauma_downloads_in_flight").increment(1.0);
+    }
+    InFlight
+}
+
+/// Records the outcome of a download.
+pub(crate) fn download_finished(summary: &Summary, elapsed: Duration) {
+    #[cfg(feature = "metrics")]
+    {
+        use crate::download::Status;
+
+        let counter = match summary.status() {
+            Status::Success => "trauma_downloads_succeeded",
+            Status::Fail(_) | Status::ChecksumMismatch(_) => "trauma_downloads_failed",
+            Status::Skipped(_) => "trauma_downloads_skipped",
+            Status::NotStarted => return,
+        };
+        ::metrics::counter!(counter).increment(1);
+        if summary.status() == &Status::Success {
+            ::metrics::histogram!("trauma_download_duration_seconds").record(elapsed.as_secs_f64());
+            ::metrics::histogram!("trauma_download_size_bytes").record(summary.size() as f64);
+        }
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (summary, elapsed);
+}
+
+/// Records a transient request failure.
+pub(crate) fn request_retried() {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!("trauma_requests_retried").increment(1);
+}
+
+/// Guard decrementing the in-flight downloads gauge when dropped, including
+/// when a download is cancelled.
+pub(crate) struct InFlight;
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        #[cfg(feature = "metrics")]
+        ::metrics::gauge!("trauma_downloads_in_flight").decrement(1.0);
+    }
+}
@@ -0,0 +1,206 @@
+//! Utilities to test Trauma integrations against a mock HTTP server.
+//!
+//! This module is only available with the `testing` feature.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use trauma::{download::Download, downloader::DownloaderBuilder, testing::MockServer};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), trauma::Error> {
+//! let server = MockServer::start().await;
+//! let url = server.resumable("/file.bin", vec![0u8; 1024]).await;
+//! let downloader = DownloaderBuilder::hidden().build();
+//! let summaries = downloader
+//!     .download(&[Download::try_from(&url).unwrap()])
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use http::header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, LOCATION, RANGE, RETRY_AFTER};
+use reqwest::Url;
+use wiremock::{matchers, Mock, Request, Respond, ResponseTemplate};
+
+/// A mock HTTP server preconfigured for common download scenarios.
+///
+/// Each scenario mounts the mocks answering on a path and returns the URL to
+/// download.
+pub struct MockServer {
+    inner: wiremock::MockServer,
+}
+
+impl MockServer {
+    /// Start a new mock server on a random local port.
+    pub async fn start() -> Self {
+        Self {
+            inner: wiremock::MockServer::start().await,
+        }
+    }
+
+    /// Return the underlying [`wiremock::MockServer`], to mount custom mocks.
+    pub fn inner(&self) -> &wiremock::MockServer {
+        &self.inner
+    }
+
+    /// Return the URL of a path on the server.
+    pub fn url(&self, path: &str) -> Url {
+        Url::parse(&self.inner.uri())
+            .and_then(|u| u.join(path))
+            .expect("the mock server URL is valid")
+    }
+
+    /// Serve a file supporting range requests.
+    pub async fn resumable(&self, path: &str, body: impl Into<Vec<u8>>) -> Url {
+        let body = body.into();
+        self.head(path, body.len() as u64, true).await;
+        self.mount_get(path, RangeResponder { body }).await;
+        self.url(path)
+    }
+
+    /// Serve a file not supporting range requests.
+    pub async fn non_resumable(&self, path: &str, body: impl Into<Vec<u8>>) -> Url {
+        let body = body.into();
+        self.head(path, body.len() as u64, false).await;
+        self.mount_get(path, ResponseTemplate::new(200).set_body_bytes(body))
+            .await;
+        self.url(path)
+    }
+
+    /// Answer every request with a `429 Too Many Requests` status, and an
+    /// optional `Retry-After` header in seconds.
+    pub async fn too_many_requests(&self, path: &str, retry_after: Option<u64>) -> Url {
+        let mut response = ResponseTemplate::new(429);
+        if let Some(secs) = retry_after {
+            response = response.insert_header(RETRY_AFTER, secs.to_string());
+        }
+        Mock::given(matchers::path(path))
+            .respond_with(response)
+            .mount(&self.inner)
+            .await;
+        self.url(path)
+    }
+
+    /// Serve a file whose advertised size is larger than its actual body.
+    ///
+    /// The `HEAD` requests report `len` bytes, while the `GET` requests only
+    /// return `body`.
+    pub async fn truncated(&self, path: &str, body: impl Into<Vec<u8>>, len: u64) -> Url {
+        self.head(path, len, false).await;
+        self.mount_get(path, ResponseTemplate::new(200).set_body_bytes(body))
+            .await;
+        self.url(path)
+    }
+
+    /// Redirect every request to another URL.
+    pub async fn redirect(&self, path: &str, to: &Url) -> Url {
+        Mock::given(matchers::path(path))
+            .respond_with(ResponseTemplate::new(302).insert_header(LOCATION, to.as_str()))
+            .mount(&self.inner)
+            .await;
+        self.url(path)
+    }
+
+    /// Mount the `HEAD` mock of a file.
+    async fn head(&self, path: &str, len: u64, resumable: bool) {
+        let accept_ranges = if resumable { "bytes" } else { "none" };
+        Mock::given(matchers::method("HEAD"))
+            .and(matchers::path(path))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header(ACCEPT_RANGES, accept_ranges)
+                    .insert_header(CONTENT_LENGTH, len.to_string()),
+            )
+            .mount(&self.inner)
+            .await;
+    }
+
+    /// Mount the `GET` mock of a file.
+    async fn mount_get(&self, path: &str, responder: impl Respond + 'static) {
+        Mock::given(matchers::method("GET"))
+            .and(matchers::path(path))
+            .respond_with(responder)
+            .mount(&self.inner)
+            .await;
+    }
+}
+
+/// Serve a body, honoring the `Range` header of the requests.
+struct RangeResponder {
+    body: Vec<u8>,
+}
+
+impl Respond for RangeResponder {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let len = self.body.len() as u64;
+        let range = request
+            .headers
+            .get(RANGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_range);
+        let Some((start, end)) = range else {
+            return ResponseTemplate::new(200)
+                .insert_header(ACCEPT_RANGES, "bytes")
+                .set_body_bytes(self.body.clone());
+        };
+        let end = end
+            .unwrap_or(len.saturating_sub(1))
+            .min(len.saturating_sub(1));
+        if start >= len || start > end {
+            return ResponseTemplate::new(416)
+                .insert_header(CONTENT_RANGE, format!("bytes */{}", len));
+        }
+        ResponseTemplate::new(206)
+            .insert_header(ACCEPT_RANGES, "bytes")
+            .insert_header(CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, len))
+            .set_body_bytes(self.body[start as usize..=end as usize].to_vec())
+    }
+}
+
+/// Parse a single `bytes=start-[end]` range.
+fn parse_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (start, end) = value.strip_prefix("bytes=")?.split_once('-')?;
+    let start = start.trim().parse().ok()?;
+    let end = match end.trim() {
+        "" => None,
+        end => Some(end.parse().ok()?),
+    };
+    Some((start, end))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{download::Download, downloader::DownloaderBuilder};
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=10-"), Some((10, None)));
+        assert_eq!(parse_range("bytes=0-99"), Some((0, Some(99))));
+        assert_eq!(parse_range("items=0-99"), None);
+    }
+
+    #[tokio::test]
+    async fn test_resumable_scenario() {
+        let server = MockServer::start().await;
+        let url = server.resumable("/file.bin", vec![1u8; 64]).await;
+        let directory = std::env::temp_dir().join("trauma-test-testing");
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        tokio::fs::write(directory.join("file.bin"), [1u8; 16])
+            .await
+            .unwrap();
+        let downloader = DownloaderBuilder::hidden()
+            .directory(directory.clone())
+            .use_temp_file(false)
+            .build();
+        let summaries = downloader
+            .download(&[Download::try_from(&url).unwrap()])
+            .await
+            .unwrap();
+        assert_eq!(summaries[0].resumed_from(), 16);
+        let written = tokio::fs::read(directory.join("file.bin")).await.unwrap();
+        assert_eq!(written, vec![1u8; 64]);
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+}
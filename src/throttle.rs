@@ -0,0 +1,71 @@
+//! Caps the aggregate throughput of the downloads.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Token bucket of bytes shared by the downloads.
+///
+/// The bucket is refilled at `bytes_per_second`, and holds up to a tenth of
+/// a second worth of bytes so the downloads are slowed down smoothly rather
+/// than in bursts. A chunk larger than the available tokens puts the bucket
+/// in debt, which the following chunks wait for.
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    /// Refill rate, in bytes per second.
+    rate: f64,
+    /// Maximum number of tokens.
+    burst: f64,
+    /// Available tokens, negative when in debt, and last time the bucket was
+    /// refilled.
+    state: Mutex<(f64, Instant)>,
+}
+
+impl Throttle {
+    /// Creates a bucket allowing `bytes_per_second`.
+    pub(crate) fn new(bytes_per_second: u64) -> Self {
+        let rate = bytes_per_second.max(1) as f64;
+        let burst = (rate / 10.0).max(1.0);
+        Self {
+            rate,
+            burst,
+            state: Mutex::new((burst, Instant::now())),
+        }
+    }
+
+    /// Waits until `len` bytes can be written.
+    pub(crate) async fn consume(&self, len: u64) {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let (tokens, refilled_at) = &mut *state;
+            let now = Instant::now();
+            let elapsed = now.duration_since(*refilled_at).as_secs_f64();
+            *tokens = (*tokens + elapsed * self.rate).min(self.burst);
+            *refilled_at = now;
+            *tokens -= len as f64;
+            (*tokens < 0.0).then(|| Duration::from_secs_f64(-*tokens / self.rate))
+        };
+        if let Some(wait) = wait {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_throttle() {
+        let throttle = Throttle::new(1000);
+        let start = Instant::now();
+        // The burst is available immediately.
+        throttle.consume(100).await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+        // The next bytes are paced at the rate.
+        throttle.consume(200).await;
+        throttle.consume(100).await;
+        assert!(start.elapsed() >= Duration::from_millis(250));
+    }
+}